@@ -0,0 +1,65 @@
+use std::net::Ipv6Addr;
+
+/// Base port for VNC display numbers, display N listens on 5900 + N.
+const DISPLAY_BASE_PORT: u16 = 5900;
+
+/// Display numbers are small, so `host:N` below this is a display, not a port.
+const MAX_DISPLAY: u16 = 100;
+
+/// Splits a port off a host the way vncviewer reads it: `host::port` for a port,
+/// `host:N` for display N (or a port, if N is too big for a display) and
+/// `[v6addr]:N` for IPv6. Returns `None` if there is no port in `input`.
+pub fn split_host_port(input: &str) -> Option<(String, u16)> {
+    let input = input.trim();
+    // A bare IPv6 address is all colons but has no port.
+    if input.parse::<Ipv6Addr>().is_ok() {
+        return None;
+    }
+
+    if let Some(rest) = input.strip_prefix('[') {
+        let (host, rest) = rest.split_once(']')?;
+        let port = rest.strip_prefix(':')?;
+        return Some((host.to_string(), port_or_display(port)?));
+    }
+    if let Some((host, port)) = input.rsplit_once("::") {
+        return Some((host.to_string(), port.parse().ok()?));
+    }
+    let (host, port) = input.split_once(':')?;
+    Some((host.to_string(), port_or_display(port)?))
+}
+
+fn port_or_display(value: &str) -> Option<u16> {
+    let value: u16 = value.parse().ok()?;
+    if value < MAX_DISPLAY {
+        Some(DISPLAY_BASE_PORT + value)
+    } else {
+        Some(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn host(host: &str, port: u16) -> Option<(String, u16)> {
+        Some((host.to_string(), port))
+    }
+
+    #[test]
+    fn test_split_host_port() {
+        assert_eq!(split_host_port("192.168.1.5"), None);
+        assert_eq!(
+            split_host_port("192.168.1.5:5901"),
+            host("192.168.1.5", 5901)
+        );
+        assert_eq!(split_host_port(" server:2 "), host("server", 5902));
+        assert_eq!(split_host_port("server::5901"), host("server", 5901));
+        assert_eq!(split_host_port("server::22"), host("server", 22));
+
+        assert_eq!(split_host_port("fe80::1"), None);
+        assert_eq!(split_host_port("[fe80::1]:5901"), host("fe80::1", 5901));
+        assert_eq!(split_host_port("[fe80::1]"), None);
+
+        assert_eq!(split_host_port("server:vnc"), None);
+    }
+}
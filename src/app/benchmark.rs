@@ -0,0 +1,110 @@
+use std::time::{Duration, Instant};
+use vnc::{Encoding, Stats};
+
+/* Encoding benchmark run right after connecting. Each candidate encoding is
+offered on its own for a fixed period, starting with a full screen update, and
+the traffic and decode time it caused are recorded. Frames still in flight when
+a phase ends are counted towards the next one, so numbers are approximate. */
+
+const PHASE_DURATION: Duration = Duration::from_secs(2);
+
+/// Link speed used to weigh transfer size against decode time when picking
+/// the winner, in bytes per second (10 Mbit/s).
+const REFERENCE_BANDWIDTH: f64 = 10_000_000.0 / 8.0;
+
+/// Only encodings the library can decode are worth measuring.
+const CANDIDATES: [(&str, Encoding); 4] = [
+    ("ZRLE", Encoding::Zrle),
+    ("Tight", Encoding::Tight),
+    ("Hextile", Encoding::Hextile),
+    ("Raw", Encoding::Raw),
+];
+
+#[derive(Clone)]
+pub struct BenchmarkResult {
+    pub name: &'static str,
+    pub bytes: u64,
+    pub updates: u64,
+    pub decode_time: Duration,
+}
+
+impl BenchmarkResult {
+    fn per_update(&self, value: f64) -> f64 {
+        value / self.updates.max(1) as f64
+    }
+
+    pub fn bytes_per_update(&self) -> f64 {
+        self.per_update(self.bytes as f64)
+    }
+
+    pub fn decode_time_per_update(&self) -> Duration {
+        Duration::from_secs_f64(self.per_update(self.decode_time.as_secs_f64()))
+    }
+
+    /// Estimated seconds per update on the reference link.
+    fn cost(&self) -> f64 {
+        self.bytes_per_update() / REFERENCE_BANDWIDTH + self.decode_time_per_update().as_secs_f64()
+    }
+}
+
+pub enum Step {
+    /// Offer this encoding and request a full update.
+    Start(Encoding),
+    /// Nothing to do until the given time has passed.
+    Wait(Duration),
+    /// All candidates measured; the name of the cheapest one.
+    Done(&'static str),
+}
+
+pub struct Benchmark {
+    phase: usize,
+    phase_started: Option<(Instant, Stats)>,
+    pub results: Vec<BenchmarkResult>,
+}
+
+impl Benchmark {
+    pub fn new() -> Self {
+        Self {
+            phase: 0,
+            phase_started: None,
+            results: Vec::new(),
+        }
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.phase < CANDIDATES.len()
+    }
+
+    pub fn step(&mut self, stats: Stats) -> Step {
+        if let Some((started, start_stats)) = self.phase_started {
+            let elapsed = started.elapsed();
+            if elapsed < PHASE_DURATION {
+                return Step::Wait(PHASE_DURATION - elapsed);
+            }
+            self.results.push(BenchmarkResult {
+                name: CANDIDATES[self.phase].0,
+                bytes: stats.bytes_received - start_stats.bytes_received,
+                updates: stats.updates - start_stats.updates,
+                decode_time: stats.decode_time - start_stats.decode_time,
+            });
+            self.phase += 1;
+            self.phase_started = None;
+        }
+
+        match CANDIDATES.get(self.phase) {
+            Some((_, encoding)) => {
+                self.phase_started = Some((Instant::now(), stats));
+                Step::Start(*encoding)
+            }
+            None => Step::Done(self.best()),
+        }
+    }
+
+    fn best(&self) -> &'static str {
+        self.results
+            .iter()
+            .filter(|result| result.updates > 0)
+            .min_by(|a, b| a.cost().total_cmp(&b.cost()))
+            .map_or(CANDIDATES[0].0, |result| result.name)
+    }
+}
@@ -0,0 +1,218 @@
+use crate::app::VncApp;
+use eframe::egui::Color32;
+use log::{info, warn};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, SyncSender, TrySendError};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant, SystemTime};
+
+/// Frames waiting to be written. Past this, frames are dropped rather than queued.
+const RECORDING_QUEUE: usize = 4;
+
+type Frame = (PathBuf, Vec<Color32>, (u16, u16));
+
+/// A recording in progress. Frames are written as numbered PNGs by a thread of
+/// its own, so encoding doesn't hold up the viewer.
+pub struct Recording {
+    pub folder: PathBuf,
+    pub started: Instant,
+    pub frames: u32,
+    last_frame: Option<Instant>,
+    sender: Option<SyncSender<Frame>>,
+    writer: Option<JoinHandle<()>>,
+}
+
+impl Recording {
+    fn start(folder: PathBuf) -> std::io::Result<Recording> {
+        std::fs::create_dir_all(&folder)?;
+        let (sender, receiver) = mpsc::sync_channel::<Frame>(RECORDING_QUEUE);
+        let writer = thread::spawn(move || {
+            for (path, pixels, size) in receiver {
+                // Written under another name first, so a frame is either complete or missing.
+                let partial = path.with_extension("part");
+                let result = save_png(&partial, &pixels, size)
+                    .map_err(|e| e.to_string())
+                    .and_then(|()| std::fs::rename(&partial, &path).map_err(|e| e.to_string()));
+                if let Err(e) = result {
+                    warn!("Failed to write {}: {}", path.display(), e);
+                }
+            }
+        });
+        Ok(Recording {
+            folder,
+            started: Instant::now(),
+            frames: 0,
+            last_frame: None,
+            sender: Some(sender),
+            writer: Some(writer),
+        })
+    }
+
+    /// Queues a frame, unless one was taken less than `1 / fps` ago.
+    fn add_frame(&mut self, pixels: &[Color32], size: (u16, u16), fps: u32) {
+        let interval = Duration::from_secs(1) / fps.max(1);
+        if self
+            .last_frame
+            .is_some_and(|last| last.elapsed() < interval)
+        {
+            return;
+        }
+        let Some(ref sender) = self.sender else {
+            return;
+        };
+        let path = self.folder.join(format!("frame-{:06}.png", self.frames));
+        match sender.try_send((path, pixels.to_vec(), size)) {
+            Ok(()) => {
+                self.frames += 1;
+                self.last_frame = Some(Instant::now());
+            }
+            Err(TrySendError::Full(_)) => warn!("Recording can't keep up, frame dropped"),
+            Err(TrySendError::Disconnected(_)) => {}
+        }
+    }
+}
+
+impl Drop for Recording {
+    /// Waits for the queued frames to be written.
+    fn drop(&mut self) {
+        self.sender = None;
+        if let Some(writer) = self.writer.take() {
+            let _ = writer.join();
+        }
+    }
+}
+
+impl VncApp {
+    /// Writes the current frame to a timestamped PNG in the screenshot folder and
+    /// reports where in the status text.
+    pub fn save_screenshot(&mut self) {
+        if self.pixels.is_empty() {
+            return;
+        }
+        let path = Path::new(&self.config.screenshot_dir).join(format!(
+            "{}-{}.png",
+            file_name_safe(&self.host),
+            timestamp(SystemTime::now())
+        ));
+        match save_png(&path, &self.pixels, self.screen_size) {
+            Ok(()) => {
+                info!("Screenshot saved to {}", path.display());
+                self.status_text = format!("Screenshot saved to {}", path.display());
+            }
+            Err(e) => {
+                warn!("Failed to save screenshot {}: {}", path.display(), e);
+                self.status_text = format!("Failed to save screenshot: {}", e);
+            }
+        }
+    }
+
+    pub fn start_recording(&mut self) {
+        let folder = Path::new(&self.config.screenshot_dir).join(format!(
+            "{}-{}",
+            file_name_safe(&self.host),
+            timestamp(SystemTime::now())
+        ));
+        match Recording::start(folder.clone()) {
+            Ok(recording) => {
+                info!("Recording to {}", folder.display());
+                self.status_text = format!("Recording to {}", folder.display());
+                self.recording = Some(recording);
+            }
+            Err(e) => {
+                warn!("Failed to start recording in {}: {}", folder.display(), e);
+                self.status_text = format!("Failed to start recording: {}", e);
+            }
+        }
+    }
+
+    /// Stops recording once the frames still queued are written.
+    pub fn stop_recording(&mut self) {
+        if let Some(recording) = self.recording.take() {
+            let (folder, frames) = (recording.folder.clone(), recording.frames);
+            drop(recording);
+            info!("Recorded {} frames to {}", frames, folder.display());
+            self.status_text = format!("Recorded {} frames to {}", frames, folder.display());
+        }
+    }
+
+    /// Adds the frame that just completed to the recording, if there is one.
+    pub fn record_frame(&mut self) {
+        if let Some(ref mut recording) = self.recording {
+            recording.add_frame(&self.pixels, self.screen_size, self.config.recording.fps);
+        }
+    }
+}
+
+pub fn save_png(path: &Path, pixels: &[Color32], size: (u16, u16)) -> image::ImageResult<()> {
+    let rgba: Vec<u8> = pixels.iter().flat_map(|pixel| pixel.to_array()).collect();
+    image::save_buffer_with_format(
+        path,
+        &rgba,
+        size.0 as u32,
+        size.1 as u32,
+        image::ColorType::Rgba8,
+        image::ImageFormat::Png,
+    )
+}
+
+/// `host` with the characters file systems object to (`:` in a port or an IPv6
+/// address, for one) replaced.
+pub fn file_name_safe(host: &str) -> String {
+    host.chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+            c => c,
+        })
+        .collect()
+}
+
+/// `time` as `YYYYMMDD-HHMMSS`, in UTC.
+pub fn timestamp(time: SystemTime) -> String {
+    let seconds = time
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs());
+    let (year, month, day) = civil_from_days((seconds / 86400) as i64);
+    format!(
+        "{:04}{:02}{:02}-{:02}{:02}{:02}",
+        year,
+        month,
+        day,
+        seconds / 3600 % 24,
+        seconds / 60 % 60,
+        seconds % 60
+    )
+}
+
+/// The date `days` after 1970-01-01, by Howard Hinnant's algorithm.
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days.rem_euclid(146097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * month_index + 2) / 5 + 1) as u32;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    } as u32;
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_timestamp() {
+        let at = |seconds| timestamp(SystemTime::UNIX_EPOCH + Duration::from_secs(seconds));
+        assert_eq!(at(0), "19700101-000000");
+        // 2000-02-29, a leap day, plus a day and an hour, minute and second.
+        assert_eq!(at(951_782_400 + 86400 + 3661), "20000301-010101");
+        assert_eq!(at(1_792_240_496), "20261017-123456");
+    }
+}
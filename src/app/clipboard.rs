@@ -0,0 +1,73 @@
+use crate::app::VncApp;
+use log::{debug, info, warn};
+use std::time::{Duration, Instant};
+
+/// There is no portable change notification, so the local clipboard is polled.
+const CLIPBOARD_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+impl VncApp {
+    /// Sends an image from the local clipboard to the remote clipboard. Does
+    /// nothing if the server only negotiated text.
+    pub fn paste_clipboard_image(&mut self) {
+        if self.view_only || self.disable_clipboard {
+            return;
+        }
+        let Some(ref mut vnc) = self.vnc_client else {
+            return;
+        };
+
+        let image = match arboard::Clipboard::new().and_then(|mut clipboard| clipboard.get_image())
+        {
+            Ok(image) => image,
+            Err(e) => {
+                info!("No image in local clipboard: {}", e);
+                return;
+            }
+        };
+
+        match vnc.update_clipboard_image(image.width, image.height, &image.bytes) {
+            Ok(true) => {
+                self.status_text = format!(
+                    "Sent {}x{} image to remote clipboard",
+                    image.width, image.height
+                );
+            }
+            Ok(false) => info!("Server does not accept clipboard images"),
+            Err(e) => warn!("Failed to send clipboard image: {}", e),
+        }
+    }
+
+    /// Sends the local clipboard text to the server when it has changed. Text
+    /// that just came from the server is remembered and not sent back.
+    pub fn sync_clipboard(&mut self, ctx: &eframe::egui::Context) {
+        if self.view_only || self.disable_clipboard {
+            return;
+        }
+        let Some(ref mut vnc) = self.vnc_client else {
+            return;
+        };
+        if self
+            .clipboard_polled
+            .is_some_and(|polled| polled.elapsed() < CLIPBOARD_POLL_INTERVAL)
+        {
+            return;
+        }
+        self.clipboard_polled = Some(Instant::now());
+        ctx.request_repaint_after(CLIPBOARD_POLL_INTERVAL);
+
+        let text = match arboard::Clipboard::new().and_then(|mut clipboard| clipboard.get_text()) {
+            Ok(text) => text,
+            Err(e) => {
+                debug!("No text in local clipboard: {}", e);
+                return;
+            }
+        };
+        if self.clipboard_text.as_ref() == Some(&text) {
+            return;
+        }
+        match vnc.update_clipboard(&text) {
+            Ok(()) => self.clipboard_text = Some(text),
+            Err(e) => warn!("Failed to send clipboard text: {}", e),
+        }
+    }
+}
@@ -0,0 +1,108 @@
+use crate::app::VncApp;
+use eframe::egui::{self, Color32, Pos2, Rect, Vec2};
+use std::time::{Duration, Instant};
+
+const MAX_SIZE: Vec2 = Vec2::new(200.0, 150.0);
+
+/// The thumbnail is rebuilt at most this often while the screen is changing.
+const REFRESH_INTERVAL: Duration = Duration::from_millis(500);
+
+impl VncApp {
+    /// Turns the minimap on or off, building the thumbnail afresh when shown.
+    pub fn set_minimap(&mut self, show: bool) {
+        self.config.minimap = show;
+        self.minimap_dirty = true;
+    }
+
+    /// Rebuilds the minimap thumbnail if the screen changed and it is due.
+    pub fn refresh_minimap(&mut self, ctx: &egui::Context) {
+        if !self.config.minimap || !self.minimap_dirty {
+            return;
+        }
+        if let Some(refreshed) = self.minimap_refreshed {
+            let elapsed = refreshed.elapsed();
+            if elapsed < REFRESH_INTERVAL {
+                ctx.request_repaint_after(REFRESH_INTERVAL - elapsed);
+                return;
+            }
+        }
+
+        let (width, height) = (self.screen_size.0 as usize, self.screen_size.1 as usize);
+        if width == 0 || height == 0 || self.pixels.len() < width * height {
+            return;
+        }
+        let size = thumbnail_size(self.screen_size);
+        let (thumb_width, thumb_height) = (size.x as usize, size.y as usize);
+        let mut pixels = Vec::with_capacity(thumb_width * thumb_height);
+        for y in 0..thumb_height {
+            let row = (y * height / thumb_height) * width;
+            for x in 0..thumb_width {
+                pixels.push(self.pixels[row + x * width / thumb_width]);
+            }
+        }
+        let image = egui::ColorImage {
+            size: [thumb_width, thumb_height],
+            pixels,
+        };
+
+        match self.minimap_texture {
+            Some(ref mut handle) => handle.set(image, Default::default()),
+            None => {
+                self.minimap_texture = Some(ctx.load_texture("minimap", image, Default::default()))
+            }
+        }
+        self.minimap_dirty = false;
+        self.minimap_refreshed = Some(Instant::now());
+    }
+
+    /// Draws the minimap in the bottom right of `viewport`, with the visible part
+    /// of a `display_size` screen outlined. Clicking or dragging on it scrolls there.
+    pub fn show_minimap(&mut self, ui: &mut egui::Ui, viewport: Rect, display_size: Vec2) {
+        self.minimap_rect = None;
+        // The thumbnail is of the whole framebuffer, so only for the view of it all.
+        if !self.config.minimap
+            || self.selected_screen.is_some()
+            || (display_size.x <= viewport.width() && display_size.y <= viewport.height())
+        {
+            return;
+        }
+        let Some(ref texture) = self.minimap_texture else {
+            return;
+        };
+
+        let size = thumbnail_size(self.screen_size);
+        let rect = Rect::from_min_size(viewport.max - size - Vec2::splat(10.0), size);
+        let painter = ui.painter_at(viewport);
+        painter.rect_filled(rect.expand(2.0), 2.0, Color32::from_black_alpha(180));
+        painter.image(
+            texture.id(),
+            rect,
+            Rect::from_min_max(Pos2::ZERO, Pos2::new(1.0, 1.0)),
+            Color32::WHITE,
+        );
+
+        let to_minimap = size / display_size;
+        let visible = Rect::from_min_size(
+            rect.min + self.scroll_offset * to_minimap,
+            viewport.size() * to_minimap,
+        )
+        .intersect(rect);
+        painter.rect_stroke(visible, 0.0, egui::Stroke::new(1.5, Color32::YELLOW));
+
+        let response = ui.interact(rect, ui.id().with("minimap"), egui::Sense::click_and_drag());
+        if response.clicked() || response.dragged() {
+            if let Some(pos) = response.interact_pointer_pos() {
+                // Center the view on the clicked point.
+                let target = (pos - rect.min) / to_minimap - viewport.size() / 2.0;
+                self.pending_scroll = Some(target.max(Vec2::ZERO));
+            }
+        }
+        self.minimap_rect = Some(rect);
+    }
+}
+
+fn thumbnail_size(screen_size: (u16, u16)) -> Vec2 {
+    let screen = Vec2::new(screen_size.0 as f32, screen_size.1 as f32);
+    let ratio = (MAX_SIZE.x / screen.x).min(MAX_SIZE.y / screen.y).min(1.0);
+    (screen * ratio).round().max(Vec2::splat(1.0))
+}
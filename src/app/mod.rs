@@ -1,11 +1,52 @@
-use crate::config::Config;
-use eframe::egui::{Color32, TextureHandle};
+use crate::config::{
+    ButtonMap, ColourDepth, Config, DisplayFilter, FormatOverride, HostConfig, HostImport,
+};
+use crate::relative_pointer::RelativePointer;
+use crate::scroll::ScrollAccumulator;
+use crate::startup::{Overrides, Startup};
+use crate::vnc_file;
+use crate::watchdog::Watchdog;
+use benchmark::Benchmark;
+use capture::Recording;
+use eframe::egui::{Color32, Modifiers, Rect, TextureHandle, Vec2};
+use log::info;
+use pixels::ColourTables;
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use std::time::Instant;
 
+pub mod benchmark;
+pub mod capture;
+pub mod clipboard;
+pub mod minimap;
+pub mod pixels;
 pub mod ui;
+pub mod virtual_keyboard;
 pub mod vnc_handler;
 
 pub use ui::get_app_icon;
 
+/// The manual zoom range.
+pub const MIN_SCALE: f32 = 0.1;
+pub const MAX_SCALE: f32 = 8.0;
+
+/// How the screen was being viewed, remembered per remote resolution.
+#[derive(Clone, Copy)]
+pub struct ViewState {
+    pub scale: f32,
+    pub fit_to_window: bool,
+    pub fill_window: bool,
+    pub stretch_window: bool,
+    pub scroll_offset: Vec2,
+}
+
+/// A password the server wants and none was saved, as typed so far.
+pub struct PasswordPrompt {
+    pub reply: std::sync::mpsc::Sender<Option<String>>,
+    pub password: String,
+    pub save: bool,
+}
+
 #[derive(Clone, Copy, PartialEq)]
 pub enum AppState {
     Connect,
@@ -18,110 +59,1298 @@ pub struct VncApp {
     // Connection params
     pub host: String,
     pub port: String,
+    pub username: String,
     pub password: String,
     pub shared: bool,
 
     // VNC Client
     pub vnc_client: Option<vnc::Client>,
     pub vnc_rx: Option<std::sync::mpsc::Receiver<Result<vnc::Client, String>>>,
+    /// The connecting thread asking for a password, with where to send it.
+    pub password_rx: Option<std::sync::mpsc::Receiver<std::sync::mpsc::Sender<Option<String>>>>,
+    pub password_prompt: Option<PasswordPrompt>,
 
     // Screen data
     pub screen_texture: Option<TextureHandle>,
+    /// Screen area changed since the texture was last uploaded.
+    pub dirty_rect: Option<vnc::Rect>,
     pub screen_size: (u16, u16),
     pub pixels: Vec<Color32>,
+    /// Palette for servers using an indexed pixel format.
+    pub colour_map: Vec<Color32>,
+    /// For the true colour format last seen in an update.
+    pub colour_tables: Option<ColourTables>,
+    /// The server's monitors, from ExtendedDesktopSize.
+    pub screens: Vec<vnc::Screen>,
+    /// Id of the one monitor shown, all of them if `None`.
+    pub selected_screen: Option<u32>,
+
+    // View
+    pub scroll_offset: Vec2,
+    /// Applied to the screen's scroll area on the next frame.
+    pub pending_scroll: Option<Vec2>,
+    /// Size of the scroll area and the scale the screen was drawn at, as of
+    /// the last frame, for zooming about a point.
+    pub viewport_size: Vec2,
+    pub display_scale: f32,
+    /// View state to return to when the server switches back to a resolution
+    /// seen earlier in this session.
+    pub view_states: HashMap<(u16, u16), ViewState>,
+    /// Part of the remote screen currently in the window.
+    pub visible_area: Option<vnc::Rect>,
+    /// Area covered by the last update request, when limited to the visible area.
+    pub requested_area: Option<vnc::Rect>,
+    pub minimap_texture: Option<TextureHandle>,
+    pub minimap_dirty: bool,
+    pub minimap_refreshed: Option<Instant>,
+    /// Where the minimap was last drawn, so clicks on it stay local.
+    pub minimap_rect: Option<Rect>,
 
     // Icons
-    pub icons: std::collections::HashMap<String, TextureHandle>,
+    pub icons: HashMap<String, TextureHandle>,
 
     // Status
     pub status_text: String,
+    /// The last failed connection attempt, shown in red while it is the status.
+    pub connect_error: Option<String>,
+    /// The dialog asking whether to disconnect is open.
+    pub confirm_disconnect: bool,
+    /// The window was asked to close mid-session and waits for confirmation.
+    pub confirm_quit: bool,
+    /// Length in characters of the last text the server put on the clipboard.
+    pub clipboard_received: Option<usize>,
+    /// Clipboard text last exchanged with the server, in either direction.
+    pub clipboard_text: Option<String>,
+    pub clipboard_polled: Option<Instant>,
 
     // Options
     pub view_only: bool,
-    pub zoom_fit: bool,
+    pub fit_to_window: bool,
+    /// When fitting, cover the window rather than fit inside it.
+    pub fill_window: bool,
+    /// When fitting, take the window's shape whatever the screen's.
+    pub stretch_window: bool,
     pub scale: f32,
+    pub smooth_scaling: bool,
+    pub display_filter: DisplayFilter,
     pub preferred_encoding: String,
     pub compression_level: u8,
     pub quality_level: u8,
     pub allow_copyrect: bool,
     pub disable_clipboard: bool,
+    pub vnc_max_fps: u32,
+    pub update_visible_only: bool,
+    pub benchmark_encodings: bool,
+    pub capture_mouse: bool,
+    pub relative_pointer: bool,
+    pub pointer_max_rate: u32,
+    pub button_map: ButtonMap,
+    pub send_cad_on_connect: bool,
+    pub format_override: FormatOverride,
+    pub force_32bit: bool,
+    pub colour_depth: ColourDepth,
+    /// The format the server offered when the session started, to go back to.
+    pub server_format: Option<vnc::PixelFormat>,
+    pub auto_reconnect: bool,
+    pub reconnect_max_attempts: u32,
+    pub connect_timeout_secs: u32,
+    pub idle_timeout_mins: u32,
+    pub server_timeout_secs: u32,
+    pub bind_address: String,
+    pub repeater: String,
+    pub repeater_id: String,
+    pub mac_address: String,
+    /// When to connect to a machine that was just sent a wake-up packet.
+    pub wake_connect_at: Option<Instant>,
+    pub use_vencrypt: bool,
+    pub trust_any_certificate: bool,
+    /// Window size and full screen state to restore for this host.
+    pub window_size: Vec2,
+    pub start_fullscreen: bool,
+    /// The window is resized at the next update, once the session has started.
+    pub window_prefs_pending: bool,
+    pub resize_remote: bool,
+    /// The remote size the window calls for and since when, sent once it settles.
+    pub remote_size_wanted: Option<((u16, u16), Instant)>,
+    /// The last size asked of the server, not asked again if it was refused.
+    pub remote_size_requested: Option<(u16, u16)>,
+
+    // Metrics
+    pub connect_started: Option<Instant>,
+    pub first_frame_at: Option<Instant>,
+    pub benchmark: Option<Benchmark>,
+    pub recording: Option<Recording>,
+    /// Ctrl-Alt-Del goes out with the first complete frame of a connection.
+    pub cad_pending: bool,
+    pub last_auto_cad: Option<Instant>,
+    /// A bell arrived and is rung at the next update.
+    pub bell_pending: bool,
+    pub bell_flash_at: Option<Instant>,
+    /// Client stats at the start of the current rate measurement.
+    pub stats_sample: Option<(Instant, vnc::Stats)>,
+    pub frame_rate: f32,
+    pub bytes_per_second: f32,
+    /// The encoding most of the last frame came in.
+    pub frame_encoding: Option<vnc::Encoding>,
+
+    // Frame pacing
+    pub last_repaint: Instant,
+    /// What the client was last told to keep asking for.
+    pub pacing: Option<vnc::UpdatePacing>,
+    /// A frame was taken as complete without its end, still to tell the client.
+    pub update_request_pending: bool,
+    pub awaiting_full_update: bool,
+    /// When pixels last arrived for a frame that hasn't ended yet.
+    pub last_pixels_at: Option<Instant>,
+    pub window_focused: bool,
+
+    /// Notices when the server has gone quiet for too long.
+    pub watchdog: Option<Watchdog>,
+
+    // Reconnect
+    pub reconnect_at: Option<Instant>,
+    /// Failed or in-flight attempts since the connection was lost.
+    pub reconnect_attempts: u32,
 
     // Input throttling
     pub last_pointer_pos: Option<(u16, u16)>,
     pub last_buttons: u8,
+    pub last_pointer_sent: Option<Instant>,
+    /// When the mouse or keyboard was last used, for the idle timeout.
+    pub last_input_at: Option<Instant>,
+    pub scroll: ScrollAccumulator,
+    pub relative: RelativePointer,
+    /// The local cursor is hidden and all pointer input goes to the remote.
+    pub pointer_grabbed: bool,
+    /// System shortcuts go to the remote while the view has focus.
+    pub grab_keyboard: bool,
+    /// Dragging over the screen moves the view instead of the remote pointer.
+    pub pan_mode: bool,
+    /// Two fingers are zooming or panning the view; the pointer is left alone
+    /// until they are all lifted.
+    pub touch_gesture: bool,
+    /// The modifiers the remote was last told are held.
+    pub remote_modifiers: Modifiers,
+    /// Keysyms pressed on the remote and not yet released.
+    pub pressed_keys: HashSet<u32>,
 
     // Dialogs
     pub show_options: bool,
     pub show_info: bool,
+    pub show_log: bool,
+    pub show_help: bool,
+    pub show_keysym_tool: bool,
+    pub show_virtual_keyboard: bool,
+    /// Modifiers tapped on the on-screen keyboard, held for its next key.
+    pub latched_keys: Vec<u32>,
+    pub keysym_input: String,
+    /// A saved host being renamed on the connect screen, and the name typed so far.
+    pub renaming_host: Option<(String, String)>,
+    /// Passwords go into exported host lists.
+    pub export_passwords: bool,
+    /// Imported hosts waiting on a choice about those already saved.
+    pub pending_import: Option<HostImport>,
 
     // Persistence
     pub config: Config,
+    /// Private session: the config file is left untouched.
+    pub private: bool,
+    /// Command line values in use for this session only.
+    pub overrides: Overrides,
 }
 
 impl Default for VncApp {
     fn default() -> Self {
-        let config: Config = if let Ok(content) = std::fs::read_to_string("vnc_config.json") {
-            serde_json::from_str(&content).unwrap_or_default()
-        } else {
-            Config::default()
-        };
+        let config = Config::load(false);
+        let startup = Startup::resolve(std::iter::empty(), &config);
+        Self::new(config, startup)
+    }
+}
 
-        let host = if config.last_host.is_empty() {
-            "localhost".to_string()
-        } else {
-            config.last_host.clone()
-        };
+impl VncApp {
+    pub fn new(config: Config, startup: Startup) -> Self {
+        let overrides = startup.overrides();
+        let host = startup.host.unwrap_or_else(|| "localhost".to_string());
 
-        let host_config = config
-            .hosts
-            .get(&host)
-            .cloned()
-            .unwrap_or_else(|| crate::config::HostConfig::default());
+        let mut host_config = config.hosts.get(&host).cloned().unwrap_or_default();
+        if let Some(ref file) = startup.vnc_file {
+            file.apply(&mut host_config);
+        }
 
-        Self {
+        let mut app = Self {
             state: AppState::Connect,
             host,
-            port: host_config.port,
-            password: host_config.password,
+            port: startup.port.unwrap_or(host_config.port),
+            username: startup.username.unwrap_or(host_config.username),
+            password: startup.password.unwrap_or(host_config.password),
             shared: host_config.shared,
             vnc_client: None,
             vnc_rx: None,
+            password_rx: None,
+            password_prompt: None,
             screen_texture: None,
+            dirty_rect: None,
             screen_size: (0, 0),
             pixels: Vec::new(),
-            icons: std::collections::HashMap::new(),
-            status_text: "Ready".to_string(),
-            view_only: host_config.view_only,
-            zoom_fit: host_config.zoom_fit,
-            scale: host_config.scale,
+            colour_map: Vec::new(),
+            colour_tables: None,
+            scroll_offset: Vec2::new(host_config.scroll_x, host_config.scroll_y),
+            pending_scroll: None,
+            screens: Vec::new(),
+            selected_screen: None,
+            viewport_size: Vec2::ZERO,
+            display_scale: 0.0,
+            view_states: HashMap::new(),
+            visible_area: None,
+            requested_area: None,
+            minimap_texture: None,
+            minimap_dirty: false,
+            minimap_refreshed: None,
+            minimap_rect: None,
+            icons: HashMap::new(),
+            status_text: startup.error.unwrap_or_else(|| "Ready".to_string()),
+            connect_error: None,
+            confirm_disconnect: false,
+            confirm_quit: false,
+            clipboard_received: None,
+            clipboard_text: None,
+            clipboard_polled: None,
+            view_only: startup.view_only || host_config.view_only,
+            fit_to_window: host_config.fit_to_window,
+            fill_window: host_config.fill_window,
+            stretch_window: host_config.stretch_window,
+            smooth_scaling: host_config.smooth_scaling,
+            display_filter: host_config.display_filter,
+            scale: host_config.scale.clamp(MIN_SCALE, MAX_SCALE),
             preferred_encoding: host_config.preferred_encoding,
             compression_level: host_config.compression_level,
             quality_level: host_config.quality_level,
             allow_copyrect: host_config.allow_copyrect,
             disable_clipboard: host_config.disable_clipboard,
+            vnc_max_fps: host_config.vnc_max_fps,
+            update_visible_only: host_config.update_visible_only,
+            benchmark_encodings: host_config.benchmark_encodings,
+            capture_mouse: host_config.capture_mouse,
+            relative_pointer: host_config.relative_pointer,
+            pointer_max_rate: host_config.pointer_max_rate,
+            button_map: host_config.button_map,
+            send_cad_on_connect: host_config.send_cad_on_connect,
+            format_override: host_config.format_override,
+            force_32bit: host_config.force_32bit,
+            colour_depth: host_config.colour_depth,
+            server_format: None,
+            auto_reconnect: host_config.auto_reconnect,
+            reconnect_max_attempts: host_config.reconnect_max_attempts,
+            connect_timeout_secs: host_config.connect_timeout_secs,
+            idle_timeout_mins: host_config.idle_timeout_mins,
+            server_timeout_secs: host_config.server_timeout_secs,
+            bind_address: host_config.bind_address.clone(),
+            repeater: host_config.repeater.clone(),
+            repeater_id: host_config.repeater_id.clone(),
+            mac_address: host_config.mac_address.clone(),
+            wake_connect_at: None,
+            use_vencrypt: host_config.use_vencrypt,
+            trust_any_certificate: host_config.trust_any_certificate,
+            window_size: Vec2::new(host_config.window_width, host_config.window_height),
+            start_fullscreen: startup.fullscreen || host_config.start_fullscreen,
+            window_prefs_pending: false,
+            resize_remote: host_config.resize_remote,
+            remote_size_wanted: None,
+            remote_size_requested: None,
+            connect_started: None,
+            first_frame_at: None,
+            benchmark: None,
+            recording: None,
+            cad_pending: false,
+            last_auto_cad: None,
+            bell_pending: false,
+            bell_flash_at: None,
+            stats_sample: None,
+            frame_rate: 0.0,
+            bytes_per_second: 0.0,
+            frame_encoding: None,
+            last_repaint: Instant::now(),
+            pacing: None,
+            update_request_pending: false,
+            awaiting_full_update: false,
+            last_pixels_at: None,
+            window_focused: true,
+            watchdog: None,
+            reconnect_at: None,
+            reconnect_attempts: 0,
             last_pointer_pos: None,
             last_buttons: 0,
+            last_pointer_sent: None,
+            last_input_at: None,
+            scroll: ScrollAccumulator::default(),
+            relative: RelativePointer::default(),
+            pointer_grabbed: false,
+            grab_keyboard: false,
+            pan_mode: false,
+            touch_gesture: false,
+            remote_modifiers: Modifiers::NONE,
+            pressed_keys: HashSet::new(),
             show_options: false,
             show_info: false,
+            show_log: false,
+            show_help: false,
+            show_keysym_tool: false,
+            show_virtual_keyboard: false,
+            latched_keys: Vec::new(),
+            keysym_input: String::new(),
+            renaming_host: None,
+            export_passwords: false,
+            pending_import: None,
             config,
+            private: startup.private,
+            overrides,
+        };
+
+        if startup.connect {
+            app.connect();
         }
+        app
+    }
+
+    /// Remembers the current host and its options in the config file, unless
+    /// this is a private session.
+    pub fn save_config(&mut self) {
+        if self.private {
+            return;
+        }
+        let mut host_config = HostConfig {
+            port: self.port.clone(),
+            username: self.username.clone(),
+            password: self.password.clone(),
+            shared: self.shared,
+            view_only: self.view_only,
+            fit_to_window: self.fit_to_window,
+            fill_window: self.fill_window,
+            stretch_window: self.stretch_window,
+            smooth_scaling: self.smooth_scaling,
+            display_filter: self.display_filter,
+            scale: self.scale,
+            preferred_encoding: self.preferred_encoding.clone(),
+            compression_level: self.compression_level,
+            quality_level: self.quality_level,
+            allow_copyrect: self.allow_copyrect,
+            disable_clipboard: self.disable_clipboard,
+            vnc_max_fps: self.vnc_max_fps,
+            update_visible_only: self.update_visible_only,
+            benchmark_encodings: self.benchmark_encodings,
+            capture_mouse: self.capture_mouse,
+            relative_pointer: self.relative_pointer,
+            pointer_max_rate: self.pointer_max_rate,
+            button_map: self.button_map,
+            send_cad_on_connect: self.send_cad_on_connect,
+            format_override: self.format_override,
+            force_32bit: self.force_32bit,
+            colour_depth: self.colour_depth,
+            auto_reconnect: self.auto_reconnect,
+            reconnect_max_attempts: self.reconnect_max_attempts,
+            connect_timeout_secs: self.connect_timeout_secs,
+            idle_timeout_mins: self.idle_timeout_mins,
+            server_timeout_secs: self.server_timeout_secs,
+            bind_address: self.bind_address.clone(),
+            repeater: self.repeater.clone(),
+            repeater_id: self.repeater_id.clone(),
+            mac_address: self.mac_address.clone(),
+            use_vencrypt: self.use_vencrypt,
+            trust_any_certificate: self.trust_any_certificate,
+            window_width: self.window_size.x,
+            window_height: self.window_size.y,
+            scroll_x: self.scroll_offset.x,
+            scroll_y: self.scroll_offset.y,
+            start_fullscreen: self.start_fullscreen,
+            resize_remote: self.resize_remote,
+        };
+        let saved = self
+            .config
+            .hosts
+            .get(&self.host)
+            .cloned()
+            .unwrap_or_default();
+        self.overrides.restore(&mut host_config, &saved);
+        self.config.last_host = self.host.clone();
+        self.config.hosts.insert(self.host.clone(), host_config);
+
+        self.config.save();
+    }
+
+    /// Writes the config file as it is, unless this is a private session.
+    pub fn write_config(&self) {
+        if !self.private {
+            self.config.save();
+        }
+    }
+
+    /// Forgets the saved hosts and options. A private session only forgets them
+    /// for itself and leaves the config file as it is.
+    pub fn clear_history(&mut self) {
+        let startup = Startup {
+            private: self.private,
+            ..Startup::default()
+        };
+        *self = Self::new(Config::default(), startup);
+        self.write_config();
+    }
+
+    /// Remembers the view for the outgoing resolution and brings back the one
+    /// last used at the incoming resolution, if any.
+    pub fn swap_view_state(&mut self, old_size: (u16, u16), new_size: (u16, u16)) {
+        self.view_states.insert(
+            old_size,
+            ViewState {
+                scale: self.scale,
+                fit_to_window: self.fit_to_window,
+                fill_window: self.fill_window,
+                stretch_window: self.stretch_window,
+                scroll_offset: self.scroll_offset,
+            },
+        );
+        if let Some(view) = self.view_states.get(&new_size) {
+            self.scale = view.scale;
+            self.fit_to_window = view.fit_to_window;
+            self.fill_window = view.fill_window;
+            self.stretch_window = view.stretch_window;
+            self.pending_scroll = Some(view.scroll_offset);
+        }
+    }
+
+    /// Whether updates are on hold because the window is in the background.
+    pub fn frozen(&self) -> bool {
+        self.config.freeze_unfocused && !self.window_focused
+    }
+
+    pub fn toggle_fit_to_window(&mut self) {
+        self.fit_to_window = !self.fit_to_window;
+    }
+
+    /// Scales to the window, fitting inside it or, with `fill`, covering it.
+    pub fn set_fit(&mut self, fill: bool) {
+        self.fit_to_window = true;
+        self.fill_window = fill;
+        self.stretch_window = false;
+    }
+
+    /// Scales to the window's exact size, distorting the screen if need be.
+    pub fn set_stretch(&mut self) {
+        self.fit_to_window = true;
+        self.fill_window = false;
+        self.stretch_window = true;
+    }
+
+    /// The part of the framebuffer on view: the selected monitor, or all of it.
+    pub fn view_rect(&self) -> vnc::Rect {
+        let selected = self
+            .selected_screen
+            .and_then(|id| self.screens.iter().find(|screen| screen.id == id));
+        screen_bounds(selected, self.screen_size)
+    }
+
+    /// Shows one monitor, or all with `None`, from the top left.
+    pub fn select_screen(&mut self, id: Option<u32>) {
+        if id != self.selected_screen {
+            self.selected_screen = id;
+            self.pending_scroll = Some(Vec2::ZERO);
+        }
+    }
+
+    /// Which way the screen is scaled to the window, `None` at a manual scale.
+    pub fn fit_label(&self) -> Option<&'static str> {
+        match (self.fit_to_window, self.fill_window, self.stretch_window) {
+            (false, _, _) => None,
+            (true, _, true) => Some("Stretch"),
+            (true, true, false) => Some("Fill"),
+            (true, false, false) => Some("Fit"),
+        }
+    }
+
+    /// Switches to a manual scale, leaving fit-to-window mode. The middle of the
+    /// view stays where it is.
+    pub fn set_scale(&mut self, scale: f32) {
+        self.set_scale_at(scale, None);
+    }
+
+    /// Like `set_scale`, keeping the screen under `anchor` (a point in the
+    /// view, the middle if `None`) in place.
+    pub fn set_scale_at(&mut self, scale: f32, anchor: Option<Vec2>) {
+        let scale = scale.clamp(MIN_SCALE, MAX_SCALE);
+        if self.display_scale > 0.0 && self.viewport_size != Vec2::ZERO {
+            let anchor = anchor.unwrap_or(self.viewport_size / 2.0);
+            let ratio = scale / self.display_scale;
+            self.pending_scroll = Some(anchored_offset(self.scroll_offset, anchor, ratio));
+        }
+        self.scale = scale;
+        self.fit_to_window = false;
+    }
+
+    /// Switches the screen texture's filtering. The texture is uploaded again
+    /// in full, since partial updates can't change it.
+    pub fn set_smooth_scaling(&mut self, smooth: bool) {
+        self.smooth_scaling = smooth;
+        self.screen_texture = None;
+        self.mark_dirty(vnc::Rect {
+            left: 0,
+            top: 0,
+            width: self.screen_size.0,
+            height: self.screen_size.1,
+        });
+    }
+
+    /// Switches the display filter, redrawing the whole screen with it.
+    pub fn set_display_filter(&mut self, filter: DisplayFilter) {
+        self.display_filter = filter;
+        self.mark_dirty(vnc::Rect {
+            left: 0,
+            top: 0,
+            width: self.screen_size.0,
+            height: self.screen_size.1,
+        });
+    }
+
+    pub fn zoom_by(&mut self, factor: f32) {
+        self.set_scale(self.scale * factor);
+    }
+
+    pub fn zoom_at(&mut self, factor: f32, anchor: Vec2) {
+        self.set_scale_at(self.scale * factor, Some(anchor));
+    }
+
+    /// Starts a touch gesture when a second finger comes down, and ends it once
+    /// the pointer (the first finger) is up again.
+    pub fn track_touch_gesture(&mut self, multi_touch: bool, pointer_down: bool) {
+        if self.config.touch_controls_remote {
+            self.touch_gesture = false;
+        } else if multi_touch {
+            self.touch_gesture = true;
+        } else if !pointer_down {
+            self.touch_gesture = false;
+        }
+    }
+
+    /// Back to 100%, with the middle of the screen in the middle of the view.
+    pub fn reset_zoom(&mut self) {
+        self.set_scale(1.0);
+        let view = self.view_rect();
+        let size = Vec2::new(view.width as f32, view.height as f32);
+        self.pending_scroll = Some(((size - self.viewport_size) / 2.0).max(Vec2::ZERO));
     }
-}
 
-impl VncApp {
     pub fn load_config_for_host(&mut self, host: &str) {
+        // The command line was about the host the app started with.
+        self.overrides = Overrides::default();
         if let Some(host_config) = self.config.hosts.get(host) {
             self.port = host_config.port.clone();
+            self.username = host_config.username.clone();
             self.password = host_config.password.clone();
             self.shared = host_config.shared;
             self.view_only = host_config.view_only;
-            self.zoom_fit = host_config.zoom_fit;
-            self.scale = host_config.scale;
+            self.fit_to_window = host_config.fit_to_window;
+            self.fill_window = host_config.fill_window;
+            self.stretch_window = host_config.stretch_window;
+            self.smooth_scaling = host_config.smooth_scaling;
+            self.display_filter = host_config.display_filter;
+            self.scale = host_config.scale.clamp(MIN_SCALE, MAX_SCALE);
+            self.scroll_offset = Vec2::new(host_config.scroll_x, host_config.scroll_y);
             self.preferred_encoding = host_config.preferred_encoding.clone();
             self.compression_level = host_config.compression_level;
             self.quality_level = host_config.quality_level;
             self.allow_copyrect = host_config.allow_copyrect;
             self.disable_clipboard = host_config.disable_clipboard;
+            self.vnc_max_fps = host_config.vnc_max_fps;
+            self.update_visible_only = host_config.update_visible_only;
+            self.benchmark_encodings = host_config.benchmark_encodings;
+            self.capture_mouse = host_config.capture_mouse;
+            self.relative_pointer = host_config.relative_pointer;
+            self.pointer_max_rate = host_config.pointer_max_rate;
+            self.button_map = host_config.button_map;
+            self.send_cad_on_connect = host_config.send_cad_on_connect;
+            self.format_override = host_config.format_override;
+            self.force_32bit = host_config.force_32bit;
+            self.colour_depth = host_config.colour_depth;
+            self.auto_reconnect = host_config.auto_reconnect;
+            self.reconnect_max_attempts = host_config.reconnect_max_attempts;
+            self.connect_timeout_secs = host_config.connect_timeout_secs;
+            self.idle_timeout_mins = host_config.idle_timeout_mins;
+            self.server_timeout_secs = host_config.server_timeout_secs;
+            self.bind_address = host_config.bind_address.clone();
+            self.repeater = host_config.repeater.clone();
+            self.repeater_id = host_config.repeater_id.clone();
+            self.mac_address = host_config.mac_address.clone();
+            self.use_vencrypt = host_config.use_vencrypt;
+            self.trust_any_certificate = host_config.trust_any_certificate;
+            self.window_size = Vec2::new(host_config.window_width, host_config.window_height);
+            self.start_fullscreen = host_config.start_fullscreen;
+            self.resize_remote = host_config.resize_remote;
+        }
+    }
+
+    /// Connects as a .vnc connection file says, over the host's saved options.
+    pub fn open_vnc_file(&mut self, path: &Path) {
+        let file = match vnc_file::read(path) {
+            Ok(file) => file,
+            Err(e) => {
+                self.status_text = e.clone();
+                self.connect_error = Some(e);
+                return;
+            }
+        };
+        info!("Opening {}", path.display());
+        let mut host_config = self
+            .config
+            .hosts
+            .get(&file.host)
+            .cloned()
+            .unwrap_or_default();
+        file.apply(&mut host_config);
+        self.config.hosts.insert(file.host.clone(), host_config);
+        self.host = file.host;
+        self.load_config_for_host(&self.host.clone());
+        self.connect();
+    }
+}
+
+/// `screen`'s bounds cut to the framebuffer, or the whole framebuffer if there
+/// is no screen or nothing of it is left.
+fn screen_bounds(screen: Option<&vnc::Screen>, screen_size: (u16, u16)) -> vnc::Rect {
+    let whole = vnc::Rect {
+        left: 0,
+        top: 0,
+        width: screen_size.0,
+        height: screen_size.1,
+    };
+    let Some(screen) = screen else {
+        return whole;
+    };
+    let right = screen
+        .x_position
+        .saturating_add(screen.width)
+        .min(screen_size.0);
+    let bottom = screen
+        .y_position
+        .saturating_add(screen.height)
+        .min(screen_size.1);
+    if right <= screen.x_position || bottom <= screen.y_position {
+        return whole;
+    }
+    vnc::Rect {
+        left: screen.x_position,
+        top: screen.y_position,
+        width: right - screen.x_position,
+        height: bottom - screen.y_position,
+    }
+}
+
+/// The scroll offset that keeps the content under `anchor` in place when the
+/// content is scaled by `ratio`.
+fn anchored_offset(offset: Vec2, anchor: Vec2, ratio: f32) -> Vec2 {
+    ((offset + anchor) * ratio - anchor).max(Vec2::ZERO)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn app() -> VncApp {
+        VncApp::new(Config::default(), Startup::default())
+    }
+
+    #[test]
+    fn test_fit_to_window_is_one_setting() {
+        let mut app = app();
+        assert!(!app.fit_to_window);
+
+        // Toolbar toggle, then the checkboxes read the same field.
+        app.toggle_fit_to_window();
+        assert!(app.fit_to_window);
+
+        // Any manual zoom leaves fit mode.
+        app.zoom_by(1.25);
+        assert!(!app.fit_to_window);
+        assert_eq!(app.scale, 1.25);
+
+        app.toggle_fit_to_window();
+        app.set_scale(1.0);
+        assert!(!app.fit_to_window);
+        assert_eq!(app.fit_label(), None);
+
+        app.set_stretch();
+        assert_eq!(app.fit_label(), Some("Stretch"));
+        app.set_fit(true);
+        assert_eq!(app.fit_label(), Some("Fill"));
+        app.set_fit(false);
+        assert_eq!(app.fit_label(), Some("Fit"));
+    }
+
+    #[test]
+    fn test_view_only_drops_held_input() {
+        let mut app = app();
+        app.pointer_grabbed = true;
+        app.last_buttons = 0x01;
+        app.remote_modifiers = Modifiers::CTRL;
+        app.set_view_only(true);
+        assert!(app.view_only);
+        assert!(!app.pointer_grabbed);
+        assert_eq!(app.last_buttons, 0);
+        assert_eq!(app.remote_modifiers, Modifiers::NONE);
+
+        app.set_view_only(false);
+        assert!(!app.view_only);
+    }
+
+    #[test]
+    fn test_one_connection_attempt_at_a_time() {
+        let mut app = app();
+        let (tx, rx) = std::sync::mpsc::channel();
+        app.vnc_rx = Some(rx);
+        assert!(app.is_connecting());
+
+        // The attempt in progress keeps its channel.
+        app.connect();
+        tx.send(Err("refused".to_string())).unwrap();
+        app.handle_vnc_events(&eframe::egui::Context::default());
+        assert!(!app.is_connecting());
+        assert_eq!(app.status_text, "refused");
+
+        // A thread that dies without answering doesn't leave it connecting.
+        let (tx, rx) = std::sync::mpsc::channel();
+        app.vnc_rx = Some(rx);
+        drop(tx);
+        app.handle_vnc_events(&eframe::egui::Context::default());
+        assert!(!app.is_connecting());
+    }
+
+    #[test]
+    fn test_password_prompt() {
+        let ctx = eframe::egui::Context::default();
+        let mut app = app();
+        app.private = true;
+        let (_tx, rx) = std::sync::mpsc::channel();
+        app.vnc_rx = Some(rx);
+        let (requests, password_rx) = std::sync::mpsc::channel();
+        app.password_rx = Some(password_rx);
+
+        let (reply, answer) = std::sync::mpsc::channel();
+        requests.send(reply).unwrap();
+        app.handle_vnc_events(&ctx);
+        assert_eq!(app.status_text, "Password required");
+        let prompt = app.password_prompt.as_mut().unwrap();
+        prompt.password = "secret".to_string();
+        prompt.save = true;
+        app.answer_password_prompt(true);
+        assert_eq!(answer.recv().unwrap().as_deref(), Some("secret"));
+        assert_eq!(app.password, "secret");
+        assert!(app.password_prompt.is_none());
+
+        // Cancelling the attempt turns the thread waiting on the prompt away.
+        let (reply, answer) = std::sync::mpsc::channel();
+        requests.send(reply).unwrap();
+        app.handle_vnc_events(&ctx);
+        app.cancel_connect();
+        assert!(app.password_prompt.is_none());
+        assert!(answer.recv().is_err());
+    }
+
+    #[test]
+    fn test_close_asks_during_a_session() {
+        use eframe::App;
+        let mut app = app();
+        assert!(app.on_close_event());
+
+        app.state = AppState::Viewing;
+        assert!(!app.on_close_event());
+        assert!(app.confirm_quit);
+    }
+
+    #[test]
+    fn test_encodings_carry_levels() {
+        let mut app = app();
+        app.preferred_encoding = "Tight".to_string();
+        app.compression_level = 2;
+        app.quality_level = 7;
+        let encodings = app.encodings();
+        assert_eq!(encodings[0], vnc::Encoding::Tight);
+        assert!(encodings.contains(&vnc::Encoding::CompressionLevel(2)));
+        assert!(encodings.contains(&vnc::Encoding::QualityLevel(7)));
+    }
+
+    #[test]
+    fn test_zoom_keeps_anchor() {
+        assert_eq!(
+            anchored_offset(Vec2::new(100.0, 50.0), Vec2::new(200.0, 100.0), 2.0),
+            Vec2::new(400.0, 200.0)
+        );
+        // Zooming out near the origin can't scroll past it.
+        assert_eq!(
+            anchored_offset(Vec2::new(10.0, 0.0), Vec2::new(20.0, 20.0), 0.5),
+            Vec2::ZERO
+        );
+
+        let mut app = app();
+        app.scroll_offset = Vec2::new(100.0, 100.0);
+        app.viewport_size = Vec2::new(800.0, 600.0);
+        app.display_scale = 1.0;
+        app.zoom_by(2.0);
+        // The middle of the view, (500, 400) on the screen, is still in the middle.
+        assert_eq!(app.pending_scroll, Some(Vec2::new(600.0, 500.0)));
+    }
+
+    #[test]
+    fn test_scale_is_clamped() {
+        let mut app = app();
+        for _ in 0..50 {
+            app.zoom_by(0.8);
+        }
+        assert_eq!(app.scale, MIN_SCALE);
+        for _ in 0..50 {
+            app.zoom_by(1.25);
+        }
+        assert_eq!(app.scale, MAX_SCALE);
+
+        // Reset puts the middle of the screen in the middle of the view.
+        app.screen_size = (1920, 1080);
+        app.viewport_size = Vec2::new(800.0, 600.0);
+        app.reset_zoom();
+        assert_eq!(app.scale, 1.0);
+        assert_eq!(app.pending_scroll, Some(Vec2::new(560.0, 240.0)));
+    }
+
+    #[test]
+    fn test_touch_gesture() {
+        let mut app = app();
+        app.track_touch_gesture(false, true);
+        assert!(!app.touch_gesture);
+        app.track_touch_gesture(true, true);
+        assert!(app.touch_gesture);
+        // One finger lifted: still the gesture, not a drag on the remote.
+        app.track_touch_gesture(false, true);
+        assert!(app.touch_gesture);
+        app.track_touch_gesture(false, false);
+        assert!(!app.touch_gesture);
+
+        app.config.touch_controls_remote = true;
+        app.track_touch_gesture(true, true);
+        assert!(!app.touch_gesture);
+    }
+
+    #[test]
+    fn test_screen_bounds() {
+        let screen = |x_position, y_position, width, height| vnc::Screen {
+            id: 1,
+            x_position,
+            y_position,
+            width,
+            height,
+            flags: 0,
+        };
+        let rect = |left, top, width, height| vnc::Rect {
+            left,
+            top,
+            width,
+            height,
+        };
+        assert_eq!(screen_bounds(None, (3840, 1080)), rect(0, 0, 3840, 1080));
+        assert_eq!(
+            screen_bounds(Some(&screen(1920, 0, 1920, 1080)), (3840, 1080)),
+            rect(1920, 0, 1920, 1080)
+        );
+        // Cut to the framebuffer, and ignored if entirely outside it.
+        assert_eq!(
+            screen_bounds(Some(&screen(1920, 0, 1920, 1200)), (3840, 1080)),
+            rect(1920, 0, 1920, 1080)
+        );
+        assert_eq!(
+            screen_bounds(Some(&screen(4000, 0, 100, 100)), (3840, 1080)),
+            rect(0, 0, 3840, 1080)
+        );
+    }
+
+    #[test]
+    fn test_view_restored_per_resolution() {
+        let mut app = app();
+        app.set_scale(2.0);
+        app.scroll_offset = Vec2::new(10.0, 20.0);
+
+        // A game switches to a low resolution, where the user zooms to fit.
+        app.swap_view_state((1920, 1080), (800, 600));
+        assert_eq!(app.scale, 2.0);
+        assert!(app.pending_scroll.is_none());
+        app.toggle_fit_to_window();
+
+        // Back on the desktop, the earlier view returns.
+        app.swap_view_state((800, 600), (1920, 1080));
+        assert_eq!(app.scale, 2.0);
+        assert!(!app.fit_to_window);
+        assert_eq!(app.pending_scroll, Some(Vec2::new(10.0, 20.0)));
+
+        app.swap_view_state((1920, 1080), (800, 600));
+        assert!(app.fit_to_window);
+    }
+
+    #[test]
+    fn test_copy_rect_past_edges() {
+        let mut app = app();
+        app.screen_size = (3, 3);
+        app.pixels = (0..9).map(Color32::from_gray).collect();
+        let rect = |left, top, width, height| vnc::Rect {
+            left,
+            top,
+            width,
+            height,
+        };
+
+        // The destination hangs off the right and bottom, so only the top left
+        // 2x2 of the source fits.
+        app.copy_pixels(rect(0, 0, 3, 3), rect(1, 1, 3, 3));
+        let gray = |values: [u8; 9]| values.map(Color32::from_gray).to_vec();
+        assert_eq!(app.pixels, gray([0, 1, 2, 3, 0, 1, 6, 3, 4]));
+
+        // Entirely off screen, or copied from a screen since resized.
+        app.copy_pixels(rect(0, 0, 2, 2), rect(5, 5, 2, 2));
+        app.copy_pixels(rect(100, 0, 50, 50), rect(0, 0, 50, 50));
+        assert_eq!(app.pixels, gray([0, 1, 2, 3, 0, 1, 6, 3, 4]));
+    }
+
+    #[test]
+    fn test_dirty_rect_union() {
+        let mut app = app();
+        app.mark_dirty(vnc::Rect {
+            left: 10,
+            top: 20,
+            width: 5,
+            height: 5,
+        });
+        app.mark_dirty(vnc::Rect {
+            left: 0,
+            top: 30,
+            width: 2,
+            height: 10,
+        });
+        assert_eq!(
+            app.dirty_rect,
+            Some(vnc::Rect {
+                left: 0,
+                top: 20,
+                width: 15,
+                height: 20,
+            })
+        );
+    }
+
+    #[test]
+    fn test_indexed_pixels() {
+        let mut app = app();
+        app.screen_size = (2, 2);
+        app.pixels = vec![Color32::BLACK; 4];
+        app.set_colour_map(
+            1,
+            &[
+                vnc::Colour {
+                    red: 0xffff,
+                    green: 0,
+                    blue: 0,
+                },
+                vnc::Colour {
+                    red: 0,
+                    green: 0x8000,
+                    blue: 0xffff,
+                },
+            ],
+        );
+
+        let format = vnc::PixelFormat {
+            bits_per_pixel: 8,
+            depth: 8,
+            big_endian: false,
+            true_colour: false,
+            red_max: 0,
+            green_max: 0,
+            blue_max: 0,
+            red_shift: 0,
+            green_shift: 0,
+            blue_shift: 0,
+        };
+        let rect = vnc::Rect {
+            left: 0,
+            top: 0,
+            width: 2,
+            height: 2,
+        };
+        // Index 7 is outside the palette.
+        app.update_pixels(rect, &[1, 2, 0, 7], format);
+        assert_eq!(
+            app.pixels,
+            [
+                Color32::from_rgb(255, 0, 0),
+                Color32::from_rgb(0, 128, 255),
+                Color32::BLACK,
+                Color32::BLACK,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_packed_24bpp() {
+        let mut app = app();
+        app.screen_size = (2, 1);
+        app.pixels = vec![Color32::BLACK; 2];
+        let mut format = vnc::PixelFormat {
+            bits_per_pixel: 24,
+            depth: 24,
+            big_endian: false,
+            true_colour: true,
+            red_max: 255,
+            green_max: 255,
+            blue_max: 255,
+            red_shift: 16,
+            green_shift: 8,
+            blue_shift: 0,
+        };
+        let rect = vnc::Rect {
+            left: 0,
+            top: 0,
+            width: 2,
+            height: 1,
+        };
+        let expected = [
+            Color32::from_rgb(0x12, 0x34, 0x56),
+            Color32::from_rgb(0xff, 0x80, 0x00),
+        ];
+
+        app.update_pixels(rect, &[0x56, 0x34, 0x12, 0x00, 0x80, 0xff], format);
+        assert_eq!(app.pixels, expected);
+
+        format.big_endian = true;
+        app.update_pixels(rect, &[0x12, 0x34, 0x56, 0xff, 0x80, 0x00], format);
+        assert_eq!(app.pixels, expected);
+    }
+
+    #[test]
+    fn test_16bpp_byte_order() {
+        let mut app = app();
+        app.screen_size = (2, 1);
+        app.pixels = vec![Color32::BLACK; 2];
+        let mut format = vnc::PixelFormat {
+            bits_per_pixel: 16,
+            depth: 16,
+            big_endian: true,
+            true_colour: true,
+            red_max: 31,
+            green_max: 63,
+            blue_max: 31,
+            red_shift: 11,
+            green_shift: 5,
+            blue_shift: 0,
+        };
+        let rect = vnc::Rect {
+            left: 0,
+            top: 0,
+            width: 2,
+            height: 1,
+        };
+        // 0xfc00 is full red and half green, 0x001f full blue; with the bytes
+        // swapped they would come out dark red and green.
+        let expected = [Color32::from_rgb(255, 129, 0), Color32::from_rgb(0, 0, 255)];
+
+        app.update_pixels(rect, &[0xfc, 0x00, 0x00, 0x1f], format);
+        assert_eq!(app.pixels, expected);
+
+        format.big_endian = false;
+        app.update_pixels(rect, &[0x00, 0xfc, 0x1f, 0x00], format);
+        assert_eq!(app.pixels, expected);
+    }
+
+    #[test]
+    fn test_frame_without_end_of_frame() {
+        let mut app = app();
+        let start = Instant::now();
+        assert_eq!(app.end_quiet_frame(start), None);
+
+        // A 3.3-era server sends rectangles but never ends the frame.
+        app.awaiting_full_update = true;
+        app.last_pixels_at = Some(start);
+        let ms = std::time::Duration::from_millis;
+        assert_eq!(app.end_quiet_frame(start + ms(100)), Some(ms(150)));
+        assert!(!app.update_request_pending);
+
+        assert_eq!(app.end_quiet_frame(start + ms(250)), None);
+        assert!(app.update_request_pending);
+        assert!(!app.awaiting_full_update);
+        assert!(app.last_pixels_at.is_none());
+    }
+
+    /// An RFB 3.3 server with no authentication that answers the first update
+    /// request with a single pixel, in an update of unknown length that it never
+    /// ends. Every later incremental update request is passed on.
+    fn rfb33_server_without_end_of_frame(requests: std::sync::mpsc::Sender<()>) -> u16 {
+        use std::io::{Read, Write};
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            stream.write_all(b"RFB 003.003\n").unwrap();
+            stream.read_exact(&mut [0; 12]).unwrap();
+            stream.write_all(&1u32.to_be_bytes()).unwrap();
+            stream.read_exact(&mut [0; 1]).unwrap();
+            // ServerInit: 4x4, 32bpp little-endian RGB888, named "old".
+            let mut init = vec![0, 4, 0, 4, 32, 24, 0, 1, 0, 255, 0, 255, 0, 255, 16, 8, 0];
+            init.extend_from_slice(&[0; 3]);
+            init.extend_from_slice(&3u32.to_be_bytes());
+            init.extend_from_slice(b"old");
+            stream.write_all(&init).unwrap();
+
+            let mut answered = false;
+            let mut kind = [0; 1];
+            while stream.read_exact(&mut kind).is_ok() {
+                let length = match kind[0] {
+                    0 => 19,
+                    2 => {
+                        let mut header = [0; 3];
+                        stream.read_exact(&mut header).unwrap();
+                        4 * u16::from_be_bytes([header[1], header[2]]) as usize
+                    }
+                    3 => 9,
+                    4 => 7,
+                    5 => 5,
+                    _ => return,
+                };
+                let mut body = vec![0; length];
+                stream.read_exact(&mut body).unwrap();
+                if kind[0] != 3 {
+                    continue;
+                }
+                if answered {
+                    // Only the pacing asks for incremental updates.
+                    if body[0] == 1 {
+                        let _ = requests.send(());
+                    }
+                    continue;
+                }
+                answered = true;
+                // 0xFFFF rectangles, but no LastRect ever follows the first.
+                let mut update = vec![0, 0, 0xff, 0xff, 0, 0, 0, 0, 0, 1, 0, 1];
+                update.extend_from_slice(&0i32.to_be_bytes());
+                update.extend_from_slice(&[0, 0, 255, 0]);
+                stream.write_all(&update).unwrap();
+            }
+        });
+        port
+    }
+
+    #[test]
+    fn test_rfb33_server_without_end_of_frame() {
+        let (tx_requests, rx_requests) = std::sync::mpsc::channel();
+        let port = rfb33_server_without_end_of_frame(tx_requests);
+        let stream = std::net::TcpStream::connect(("127.0.0.1", port)).unwrap();
+        let vnc =
+            vnc::Client::from_tcp_stream(stream, true, |_| Some(vnc::client::AuthChoice::None))
+                .unwrap();
+
+        let mut app = app();
+        let (tx, rx) = std::sync::mpsc::channel();
+        app.vnc_rx = Some(rx);
+        tx.send(Ok(vnc)).unwrap();
+
+        // The frame never ends, yet the next update is asked for once it goes quiet.
+        let ctx = eframe::egui::Context::default();
+        let deadline = Instant::now() + std::time::Duration::from_secs(5);
+        while rx_requests.try_recv().is_err() {
+            assert!(
+                Instant::now() < deadline,
+                "no update requested after the frame"
+            );
+            app.handle_vnc_events(&ctx);
+            std::thread::sleep(std::time::Duration::from_millis(20));
         }
+        assert_eq!(app.pixels[1], Color32::BLACK);
+        assert_eq!(app.pixels[0], Color32::from_rgb(255, 0, 0));
+        assert!(!app.awaiting_full_update);
+    }
+
+    #[test]
+    fn test_private_session_keeps_config() {
+        let mut app = VncApp::new(
+            Config::default(),
+            Startup {
+                private: true,
+                ..Startup::default()
+            },
+        );
+        app.host = "example".to_string();
+        app.save_config();
+        assert!(app.config.last_host.is_empty());
+        assert!(app.config.hosts.is_empty());
+    }
+
+    #[test]
+    fn test_clear_history_in_private() {
+        let mut config = Config {
+            last_host: "example".to_string(),
+            ..Config::default()
+        };
+        config
+            .hosts
+            .insert("example".to_string(), HostConfig::default());
+        let mut app = VncApp::new(
+            config,
+            Startup {
+                private: true,
+                ..Startup::default()
+            },
+        );
+        app.clear_history();
+        assert!(app.private);
+        assert!(app.config.hosts.is_empty());
+        assert!(app.config.last_host.is_empty());
+    }
+
+    #[test]
+    fn test_fit_to_window_reads_old_config_key() {
+        let host_config: HostConfig = serde_json::from_str(r#"{"zoom_fit": true}"#).unwrap();
+        let mut config = Config::default();
+        config.hosts.insert("example".to_string(), host_config);
+
+        let mut app = VncApp::new(config, Startup::default());
+        app.load_config_for_host("example");
+        assert!(app.fit_to_window);
+    }
+
+    #[test]
+    fn test_scroll_position_per_host() {
+        let mut config = Config::default();
+        config.hosts.insert(
+            "example".to_string(),
+            HostConfig {
+                scroll_x: 300.0,
+                scroll_y: 200.0,
+                ..HostConfig::default()
+            },
+        );
+        config
+            .hosts
+            .insert("other".to_string(), HostConfig::default());
+
+        let mut app = VncApp::new(config, Startup::default());
+        app.load_config_for_host("example");
+        assert_eq!(app.scroll_offset, Vec2::new(300.0, 200.0));
+        app.load_config_for_host("other");
+        assert_eq!(app.scroll_offset, Vec2::ZERO);
+    }
+
+    #[test]
+    fn test_idle_deadline() {
+        let mut app = app();
+        let start = Instant::now();
+        app.last_input_at = Some(start);
+        assert_eq!(app.idle_deadline(), None);
+        app.idle_timeout_mins = 5;
+        assert_eq!(
+            app.idle_deadline(),
+            Some(start + std::time::Duration::from_secs(300))
+        );
+        app.last_input_at = None;
+        assert_eq!(app.idle_deadline(), None);
     }
 }
@@ -0,0 +1,278 @@
+use crate::config::ColourDepth;
+use eframe::egui::Color32;
+use vnc::PixelFormat;
+
+/// 32-bit true colour with blue in the lowest byte, the usual native format of
+/// servers and one `update_pixels` copies without decoding.
+pub const TRUE_COLOUR_32: PixelFormat = PixelFormat {
+    bits_per_pixel: 32,
+    depth: 24,
+    big_endian: false,
+    true_colour: true,
+    red_max: 255,
+    green_max: 255,
+    blue_max: 255,
+    red_shift: 16,
+    green_shift: 8,
+    blue_shift: 0,
+};
+
+/// 16-bit high colour, five bits of red and blue and six of green.
+pub const HIGH_COLOUR_16: PixelFormat = PixelFormat {
+    bits_per_pixel: 16,
+    depth: 16,
+    big_endian: false,
+    true_colour: true,
+    red_max: 31,
+    green_max: 63,
+    blue_max: 31,
+    red_shift: 11,
+    green_shift: 5,
+    blue_shift: 0,
+};
+
+/// 256 colours as three bits of red and green and two of blue, the BGR233
+/// format other viewers use for low colour.
+pub const LOW_COLOUR_8: PixelFormat = PixelFormat {
+    bits_per_pixel: 8,
+    depth: 8,
+    big_endian: false,
+    true_colour: true,
+    red_max: 7,
+    green_max: 7,
+    blue_max: 3,
+    red_shift: 0,
+    green_shift: 3,
+    blue_shift: 6,
+};
+
+/// The format to ask the server for, `None` to keep the one it offered.
+pub fn requested_format(depth: ColourDepth, force_32bit: bool) -> Option<PixelFormat> {
+    match depth {
+        ColourDepth::Server => force_32bit.then_some(TRUE_COLOUR_32),
+        ColourDepth::Full => Some(TRUE_COLOUR_32),
+        ColourDepth::High => Some(HIGH_COLOUR_16),
+        ColourDepth::Low => Some(LOW_COLOUR_8),
+    }
+}
+
+/// Lookup tables scaling each channel of a true colour format to 8 bits, so
+/// decoding a pixel takes no division.
+pub struct ColourTables {
+    format: PixelFormat,
+    red: Vec<u8>,
+    green: Vec<u8>,
+    blue: Vec<u8>,
+}
+
+impl ColourTables {
+    pub fn new(format: PixelFormat) -> ColourTables {
+        ColourTables {
+            format,
+            red: channel_table(format.red_max),
+            green: channel_table(format.green_max),
+            blue: channel_table(format.blue_max),
+        }
+    }
+
+    /// The format the tables were built for.
+    pub fn format(&self) -> PixelFormat {
+        self.format
+    }
+
+    /// The colour of pixel value `val`.
+    #[inline]
+    pub fn colour(&self, val: u32) -> Color32 {
+        let format = &self.format;
+        Color32::from_rgb(
+            self.red[((val >> format.red_shift) & format.red_max as u32) as usize],
+            self.green[((val >> format.green_shift) & format.green_max as u32) as usize],
+            self.blue[((val >> format.blue_shift) & format.blue_max as u32) as usize],
+        )
+    }
+}
+
+/// Where the red, green and blue bytes sit within a pixel, for 32bpp true
+/// colour formats with 8 bit channels on byte boundaries. Most servers send
+/// such a format, usually with blue first.
+pub fn byte_offsets(format: &PixelFormat) -> Option<[usize; 3]> {
+    if format.bits_per_pixel != 32 || !format.true_colour {
+        return None;
+    }
+    let offset = |max: u16, shift: u8| {
+        if max != 255 || !shift.is_multiple_of(8) || shift > 24 {
+            return None;
+        }
+        let byte = shift as usize / 8;
+        Some(if format.big_endian { 3 - byte } else { byte })
+    };
+    Some([
+        offset(format.red_max, format.red_shift)?,
+        offset(format.green_max, format.green_shift)?,
+        offset(format.blue_max, format.blue_shift)?,
+    ])
+}
+
+/// Maps each value `0..=max` of a channel to `0..=255`.
+fn channel_table(max: u16) -> Vec<u8> {
+    if max == 0 {
+        return vec![0];
+    }
+    let max = max as u32;
+    (0..=max).map(|raw| (raw * 255 / max) as u8).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::VncApp;
+    use crate::config::Config;
+    use crate::startup::Startup;
+    use std::time::Instant;
+    use vnc::Rect;
+
+    /// How pixels were decoded before the tables, for comparison.
+    fn colour_by_division(format: &PixelFormat, val: u32) -> Color32 {
+        let scale = |shift: u8, max: u16| {
+            let max = max as u32;
+            match max {
+                0 => 0,
+                255 => ((val >> shift) & max) as u8,
+                _ => (((val >> shift) & max) * 255 / max) as u8,
+            }
+        };
+        Color32::from_rgb(
+            scale(format.red_shift, format.red_max),
+            scale(format.green_shift, format.green_max),
+            scale(format.blue_shift, format.blue_max),
+        )
+    }
+
+    #[test]
+    fn test_tables_match_division() {
+        let tables = ColourTables::new(HIGH_COLOUR_16);
+        for val in 0..=u16::MAX as u32 {
+            assert_eq!(tables.colour(val), colour_by_division(&HIGH_COLOUR_16, val));
+        }
+        assert_eq!(channel_table(0), [0]);
+        assert_eq!(channel_table(1), [0, 255]);
+        assert_eq!(channel_table(255)[128], 128);
+    }
+
+    #[test]
+    fn test_requested_format() {
+        assert_eq!(requested_format(ColourDepth::Server, false), None);
+        assert_eq!(
+            requested_format(ColourDepth::Server, true),
+            Some(TRUE_COLOUR_32)
+        );
+        // A chosen depth wins over forcing 32 bits.
+        assert_eq!(requested_format(ColourDepth::Low, true), Some(LOW_COLOUR_8));
+
+        let tables = ColourTables::new(LOW_COLOUR_8);
+        assert_eq!(tables.colour(0b11_111_111), Color32::WHITE);
+        assert_eq!(tables.colour(0b00_000_111), Color32::from_rgb(255, 0, 0));
+        assert_eq!(tables.colour(0b10_000_000), Color32::from_rgb(0, 0, 170));
+    }
+
+    #[test]
+    fn test_fast_path_matches_decoding() {
+        let bgrx = TRUE_COLOUR_32;
+        assert_eq!(byte_offsets(&bgrx), Some([2, 1, 0]));
+        let rgbx = PixelFormat {
+            red_shift: 0,
+            blue_shift: 16,
+            ..bgrx
+        };
+        let big_endian = PixelFormat {
+            big_endian: true,
+            ..bgrx
+        };
+        assert_eq!(byte_offsets(&big_endian), Some([1, 2, 3]));
+        assert_eq!(byte_offsets(&HIGH_COLOUR_16), None);
+        assert_eq!(
+            byte_offsets(&PixelFormat {
+                red_shift: 4,
+                ..bgrx
+            }),
+            None
+        );
+
+        // A 3x2 update into the middle of a 5x4 screen, one byte short.
+        let rect = Rect {
+            left: 1,
+            top: 1,
+            width: 3,
+            height: 2,
+        };
+        let update: Vec<u8> = (0..23u8).map(|i| i.wrapping_mul(37)).collect();
+        for format in [bgrx, rgbx, big_endian] {
+            let mut fast = VncApp::new(Config::default(), Startup::default());
+            fast.screen_size = (5, 4);
+            fast.pixels = vec![Color32::BLACK; 20];
+            let mut slow = VncApp::new(Config::default(), Startup::default());
+            slow.screen_size = (5, 4);
+            slow.pixels = vec![Color32::BLACK; 20];
+
+            fast.update_pixels(rect, &update, format);
+            slow.decode_pixels(rect, &update, format);
+            assert_eq!(fast.pixels, slow.pixels);
+            assert_ne!(fast.pixels[6], Color32::BLACK);
+            assert_eq!(fast.pixels[13], Color32::BLACK);
+        }
+    }
+
+    /// Decodes a full 1080p Raw frame at 16bpp with the tables and with the old
+    /// per-pixel division, in the same loop, then times `update_pixels` on it.
+    /// Run with `cargo test --release bench_update_pixels -- --ignored --nocapture`.
+    #[test]
+    #[ignore]
+    fn bench_update_pixels() {
+        use std::hint::black_box;
+        const ROUNDS: u32 = 20;
+        let (width, height) = (1920u16, 1080u16);
+        let frame: Vec<u8> = (0..width as usize * height as usize)
+            .flat_map(|i| (i as u16).wrapping_mul(40503).to_le_bytes())
+            .collect();
+        // Hidden from the optimiser, or it divides by the constant maxima cheaply.
+        let format = black_box(HIGH_COLOUR_16);
+
+        let time = |decode: &dyn Fn(u32) -> Color32| {
+            let mut pixels = vec![Color32::BLACK; frame.len() / 2];
+            let start = Instant::now();
+            for _ in 0..ROUNDS {
+                for (pixel, bytes) in pixels.iter_mut().zip(frame.chunks_exact(2)) {
+                    *pixel = decode(u16::from_le_bytes([bytes[0], bytes[1]]) as u32);
+                }
+            }
+            black_box(&pixels);
+            (start.elapsed() / ROUNDS, pixels)
+        };
+        let tables = ColourTables::new(format);
+        let (with_tables, decoded) = time(&|val| tables.colour(val));
+        let (dividing, divided) = time(&|val| colour_by_division(&format, val));
+        assert_eq!(decoded, divided);
+
+        let mut app = VncApp::new(Config::default(), Startup::default());
+        app.screen_size = (width, height);
+        app.pixels = vec![Color32::BLACK; width as usize * height as usize];
+        let rect = Rect {
+            left: 0,
+            top: 0,
+            width,
+            height,
+        };
+        let start = Instant::now();
+        for _ in 0..ROUNDS {
+            app.update_pixels(rect, &frame, format);
+        }
+        let update = start.elapsed() / ROUNDS;
+        assert_eq!(app.pixels, decoded);
+
+        println!(
+            "1080p Raw frame at 16bpp: decoding {:?} with tables, {:?} dividing; \
+             update_pixels {:?}",
+            with_tables, dividing, update
+        );
+    }
+}
@@ -1,7 +1,25 @@
-use crate::app::{AppState, VncApp};
+use crate::app::vnc_handler::{release_keys, remaining_interval, send_key, send_keys};
+use crate::app::{AppState, VncApp, MAX_SCALE, MIN_SCALE};
+use crate::config::{self, BellMode, ColourDepth, DisplayFilter, MiddleEmulation};
+use crate::keyboard_grab;
 use crate::keys;
+use crate::log_buffer;
+use crate::vnc_file;
 use eframe::egui::{self, Color32, Vec2};
-use log::warn;
+use log::{info, warn};
+use std::path::PathBuf;
+
+const ZOOM_PRESETS: [u32; 6] = [25, 50, 75, 100, 150, 200];
+
+/// How long the visual bell takes to fade out.
+const BELL_FLASH: std::time::Duration = std::time::Duration::from_millis(300);
+
+const PASSWORD_TRUNCATED_WARNING: &str =
+    "Password will be truncated to 8 characters for VNC authentication";
+
+const BENCHMARK_HINT: &str = "Spends the first few seconds trying each encoding on the \
+     current screen content, then keeps the cheapest. The screen is redrawn \
+     once per encoding while this runs.";
 
 pub fn setup_custom_style(ctx: &egui::Context) {
     let mut style = (*ctx.style()).clone();
@@ -44,7 +62,304 @@ pub fn get_app_icon() -> Option<eframe::IconData> {
     None
 }
 
+fn format_mb(bytes: usize) -> String {
+    format!("{:.1} MB", bytes as f64 / (1024.0 * 1024.0))
+}
+
+fn version_label(version: vnc::Version) -> &'static str {
+    match version {
+        vnc::Version::Rfb33 => "RFB 3.3",
+        vnc::Version::Rfb37 => "RFB 3.7",
+        vnc::Version::Rfb38 => "RFB 3.8",
+    }
+}
+
+fn security_label(security_type: vnc::SecurityType) -> String {
+    match security_type {
+        vnc::SecurityType::None => "None".to_string(),
+        vnc::SecurityType::VncAuthentication => "VNC password".to_string(),
+        vnc::SecurityType::VeNCrypt => "VeNCrypt (TLS)".to_string(),
+        vnc::SecurityType::AppleRemoteDesktop => "Apple Remote Desktop".to_string(),
+        vnc::SecurityType::MsLogon => "MS-Logon II".to_string(),
+        vnc::SecurityType::Invalid => "Invalid".to_string(),
+        vnc::SecurityType::Unknown(n) => format!("Unknown ({})", n),
+    }
+}
+
+/// The pixel format as it would go in a bug report, such as
+/// `32 bpp, depth 24, little endian, RGB max 255/255/255 shift 16/8/0`.
+fn describe_format(format: &vnc::PixelFormat) -> String {
+    let endian = if format.big_endian { "big" } else { "little" };
+    if !format.true_colour {
+        return format!(
+            "{} bpp, depth {}, {} endian, colour map",
+            format.bits_per_pixel, format.depth, endian
+        );
+    }
+    format!(
+        "{} bpp, depth {}, {} endian, RGB max {}/{}/{} shift {}/{}/{}",
+        format.bits_per_pixel,
+        format.depth,
+        endian,
+        format.red_max,
+        format.green_max,
+        format.blue_max,
+        format.red_shift,
+        format.green_shift,
+        format.blue_shift
+    )
+}
+
+fn depth_label(depth: ColourDepth) -> &'static str {
+    match depth {
+        ColourDepth::Server => "Server's choice",
+        ColourDepth::Full => "32-bit",
+        ColourDepth::High => "16-bit",
+        ColourDepth::Low => "256 colours",
+    }
+}
+
+fn encoding_name(encoding: vnc::Encoding) -> String {
+    match encoding {
+        vnc::Encoding::Raw => "Raw".to_string(),
+        vnc::Encoding::Hextile => "Hextile".to_string(),
+        vnc::Encoding::Tight => "Tight".to_string(),
+        vnc::Encoding::Trle => "TRLE".to_string(),
+        vnc::Encoding::Zrle => "ZRLE".to_string(),
+        other => format!("{:?}", other),
+    }
+}
+
+fn middle_label(middle: MiddleEmulation) -> &'static str {
+    match middle {
+        MiddleEmulation::Off => "Middle button only",
+        MiddleEmulation::Chord => "Left and right together",
+        MiddleEmulation::AltClick => "Alt+left click",
+    }
+}
+
+fn filter_label(filter: DisplayFilter) -> &'static str {
+    match filter {
+        DisplayFilter::None => "None",
+        DisplayFilter::Invert => "Invert colours",
+        DisplayFilter::Grayscale => "Grayscale",
+        DisplayFilter::Night => "Night",
+    }
+}
+
+/// Draws grid lines every `spacing` remote pixels over `image_rect`, with the
+/// remote coordinate of each labelled line along the top and left edges.
+fn paint_grid(
+    painter: &egui::Painter,
+    image_rect: egui::Rect,
+    screen_size: (u16, u16),
+    spacing: u32,
+) {
+    if screen_size.0 == 0 || screen_size.1 == 0 || spacing == 0 {
+        return;
+    }
+    let zoom = image_rect.width() / screen_size.0 as f32;
+    let step = spacing as f32 * zoom;
+    // Lines closer than this would just tint the whole image.
+    if step < 4.0 {
+        return;
+    }
+    // Keep labels from overlapping when zoomed out.
+    let label_every = (40.0 / step).ceil().max(1.0) as u32;
+
+    let line = egui::Stroke::new(1.0, Color32::from_rgba_unmultiplied(255, 0, 255, 110));
+    let font = egui::FontId::monospace(10.0);
+    let label_color = Color32::from_rgb(255, 0, 255);
+
+    for (i, x) in (0..=screen_size.0 as u32)
+        .step_by(spacing as usize)
+        .enumerate()
+    {
+        let screen_x = image_rect.left() + x as f32 * zoom;
+        painter.vline(screen_x, image_rect.y_range(), line);
+        if (i as u32).is_multiple_of(label_every) {
+            painter.text(
+                egui::pos2(screen_x + 2.0, image_rect.top() + 1.0),
+                egui::Align2::LEFT_TOP,
+                x.to_string(),
+                font.clone(),
+                label_color,
+            );
+        }
+    }
+    for (i, y) in (0..=screen_size.1 as u32)
+        .step_by(spacing as usize)
+        .enumerate()
+    {
+        let screen_y = image_rect.top() + y as f32 * zoom;
+        painter.hline(image_rect.x_range(), screen_y, line);
+        if i != 0 && (i as u32).is_multiple_of(label_every) {
+            painter.text(
+                egui::pos2(image_rect.left() + 2.0, screen_y + 1.0),
+                egui::Align2::LEFT_TOP,
+                y.to_string(),
+                font.clone(),
+                label_color,
+            );
+        }
+    }
+}
+
 impl VncApp {
+    /// The one fit-to-window toggle, shared by the connect screen and the viewer
+    /// options so both always show and change the same setting.
+    fn fit_to_window_checkbox(&mut self, ui: &mut egui::Ui) {
+        ui.checkbox(&mut self.fit_to_window, "Fit to window")
+            .on_hover_text(
+                "Scale the remote screen to the window instead of using the manual scale",
+            );
+    }
+
+    fn colour_depth_options(&mut self, ui: &mut egui::Ui) {
+        egui::ComboBox::from_label("Colour depth")
+            .selected_text(depth_label(self.colour_depth))
+            .show_ui(ui, |ui| {
+                for depth in [
+                    ColourDepth::Server,
+                    ColourDepth::Full,
+                    ColourDepth::High,
+                    ColourDepth::Low,
+                ] {
+                    ui.selectable_value(&mut self.colour_depth, depth, depth_label(depth));
+                }
+            })
+            .response
+            .on_hover_text(
+                "Fewer colours cut the bandwidth a lot on slow links. Sent to the \
+                 server on connect and on Apply.",
+            );
+        ui.add_enabled(
+            self.colour_depth == ColourDepth::Server,
+            egui::Checkbox::new(&mut self.force_32bit, "Force 32-bit colour"),
+        )
+        .on_hover_text(
+            "Ask the server for 32-bit true colour, the quickest to draw, whatever \
+             it offers. Costs bandwidth with servers that offer fewer colours. \
+             Only with the colour depth left to the server.",
+        );
+    }
+
+    fn button_map_options(&mut self, ui: &mut egui::Ui) {
+        let map = &mut self.button_map;
+        ui.checkbox(&mut map.swap_left_right, "Swap left and right buttons");
+        egui::ComboBox::from_label("Middle button")
+            .selected_text(middle_label(map.middle))
+            .show_ui(ui, |ui| {
+                for middle in [
+                    MiddleEmulation::Off,
+                    MiddleEmulation::Chord,
+                    MiddleEmulation::AltClick,
+                ] {
+                    ui.selectable_value(&mut map.middle, middle, middle_label(middle));
+                }
+            })
+            .response
+            .on_hover_text("A middle click for mice and touchpads without a middle button");
+        ui.horizontal(|ui| {
+            ui.label("Don't send:");
+            ui.checkbox(&mut map.disable_left, "Left");
+            ui.checkbox(&mut map.disable_middle, "Middle");
+            ui.checkbox(&mut map.disable_right, "Right");
+        });
+    }
+
+    /// Opens a .vnc connection file dropped on the window.
+    fn open_dropped_file(&mut self, ctx: &egui::Context) {
+        let path = ctx.input(|i| {
+            i.raw
+                .dropped_files
+                .iter()
+                .filter_map(|file| file.path.clone())
+                .find(|path| vnc_file::is_vnc_file(path))
+        });
+        let Some(path) = path else {
+            return;
+        };
+        if self.state == AppState::Viewing || self.is_connecting() {
+            let name = path.file_name().unwrap_or_default().to_string_lossy();
+            self.status_text = format!("Disconnect first to open {}", name);
+            return;
+        }
+        self.open_vnc_file(&path);
+    }
+
+    fn timeout_sliders(&mut self, ui: &mut egui::Ui) {
+        ui.add(
+            egui::Slider::new(&mut self.idle_timeout_mins, 0..=120)
+                .text("Disconnect when idle (min)"),
+        )
+        .on_hover_text("After this long without mouse or keyboard input. 0 never disconnects.");
+        ui.add(
+            egui::Slider::new(&mut self.server_timeout_secs, 0..=300).text("Server timeout (s)"),
+        )
+        .on_hover_text(
+            "Treat the connection as lost after this long without hearing from the \
+             server. 0 waits forever.",
+        );
+    }
+
+    fn bind_address_field(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.label("Source address:");
+            ui.add(
+                egui::TextEdit::singleline(&mut self.bind_address)
+                    .desired_width(120.0)
+                    .hint_text("any"),
+            )
+            .on_hover_text(
+                "Local IP address to connect from, to go through a particular \
+                 interface such as a VPN adapter. Takes effect on the next connection.",
+            );
+        });
+    }
+
+    fn repeater_fields(&mut self, ui: &mut egui::Ui) {
+        egui::Grid::new(ui.id().with("repeater")).show(ui, |ui| {
+            ui.label("Repeater:");
+            ui.add(
+                egui::TextEdit::singleline(&mut self.repeater)
+                    .desired_width(120.0)
+                    .hint_text("none"),
+            )
+            .on_hover_text(
+                "UltraVNC repeater to connect through, as host or host:port. \
+                 Leave empty to connect directly.",
+            );
+            ui.end_row();
+            ui.label("Repeater ID:");
+            ui.add_enabled(
+                !self.repeater.trim().is_empty(),
+                egui::TextEdit::singleline(&mut self.repeater_id).desired_width(120.0),
+            )
+            .on_hover_text(
+                "The ID the server registered under (mode II). Leave empty for the \
+                 repeater to connect to the host and port above (mode I).",
+            );
+            ui.end_row();
+        });
+    }
+
+    fn encryption_options(&mut self, ui: &mut egui::Ui) {
+        ui.checkbox(&mut self.use_vencrypt, "Use encryption (VeNCrypt)")
+            .on_hover_text("Only connect over TLS. Takes effect on the next connection.");
+        ui.add_enabled(
+            self.use_vencrypt,
+            egui::Checkbox::new(
+                &mut self.trust_any_certificate,
+                "Trust the server certificate without checking it",
+            ),
+        )
+        .on_hover_text(
+            "Needed for self-signed certificates, as most VNC servers use. The \
+             connection is still encrypted, but the server isn't verified.",
+        );
+    }
+
     pub fn load_icons(&mut self, ctx: &egui::Context) {
         let icon_data: [(&str, &[u8]); 10] = [
             (
@@ -100,24 +415,699 @@ impl VncApp {
         }
     }
 
+    /// Sizes the window for the host when a session starts, and from then on keeps
+    /// track of how the user sizes it, to be saved on disconnect.
+    fn update_window_prefs(&mut self, frame: &mut eframe::Frame) {
+        if std::mem::take(&mut self.window_prefs_pending) {
+            if self.window_size.x > 0.0 && self.window_size.y > 0.0 {
+                frame.set_window_size(self.window_size);
+            }
+            frame.set_fullscreen(self.start_fullscreen);
+            return;
+        }
+        let window = &frame.info().window_info;
+        if window.minimized {
+            return;
+        }
+        self.start_fullscreen = window.fullscreen;
+        if !window.fullscreen && !window.maximized {
+            self.window_size = window.size;
+        }
+    }
+
+    /// Picks the remote monitor to show, for servers with more than one.
+    fn monitor_selector(&mut self, ui: &mut egui::Ui) {
+        let label = |index: usize, screen: &vnc::Screen| {
+            format!("Monitor {} ({}x{})", index + 1, screen.width, screen.height)
+        };
+        let selected = self
+            .screens
+            .iter()
+            .enumerate()
+            .find(|(_, screen)| Some(screen.id) == self.selected_screen)
+            .map_or("All monitors".to_string(), |(index, screen)| {
+                label(index, screen)
+            });
+        let mut choice = self.selected_screen;
+        egui::ComboBox::from_id_source("monitor")
+            .selected_text(selected)
+            .show_ui(ui, |ui| {
+                ui.selectable_value(&mut choice, None, "All monitors");
+                for (index, screen) in self.screens.iter().enumerate() {
+                    ui.selectable_value(&mut choice, Some(screen.id), label(index, screen));
+                }
+            });
+        self.select_screen(choice);
+    }
+
+    /// Moves the view by a drag over the screen, like grabbing the image.
+    fn pan_view(&mut self, ui: &egui::Ui, response: &egui::Response) {
+        let dragging = ui.input(|i| {
+            i.pointer.button_down(egui::PointerButton::Primary)
+                || i.pointer.button_down(egui::PointerButton::Middle)
+        });
+        if response.dragged() && dragging {
+            self.pending_scroll = Some(self.scroll_offset - response.drag_delta());
+            ui.ctx().set_cursor_icon(egui::CursorIcon::Grabbing);
+        } else if response.hovered() {
+            ui.ctx().set_cursor_icon(egui::CursorIcon::Grab);
+        }
+    }
+
+    /// Alerts the user to a bell from the server, the way the options ask for.
+    fn ring_bell(&mut self, frame: &mut eframe::Frame, focused: bool) {
+        match self.config.bell {
+            BellMode::Off => {}
+            BellMode::Audible if system_beep() => {}
+            BellMode::Audible | BellMode::Visual => {
+                self.bell_flash_at = Some(std::time::Instant::now());
+                if !focused {
+                    frame.request_user_attention(egui::UserAttentionType::Informational);
+                }
+            }
+        }
+    }
+
+    /// Draws the fading frame around the window for the visual bell.
+    fn paint_bell_flash(&mut self, ctx: &egui::Context) {
+        let Some(rung_at) = self.bell_flash_at else {
+            return;
+        };
+        let elapsed = rung_at.elapsed();
+        if elapsed >= BELL_FLASH {
+            self.bell_flash_at = None;
+            return;
+        }
+        let fade = 1.0 - elapsed.as_secs_f32() / BELL_FLASH.as_secs_f32();
+        let painter = ctx.layer_painter(egui::LayerId::new(
+            egui::Order::Foreground,
+            egui::Id::new("bell_flash"),
+        ));
+        painter.rect_stroke(
+            ctx.screen_rect().shrink(3.0),
+            0.0,
+            egui::Stroke::new(6.0, Color32::from_rgb(255, 190, 0).gamma_multiply(fade)),
+        );
+        ctx.request_repaint();
+    }
+
+    /// Takes local hotkeys out of the input, so they never reach the remote.
+    fn handle_hotkeys(&mut self, ctx: &egui::Context, frame: &mut eframe::Frame) {
+        let mut actions = Vec::new();
+        let fullscreen = frame.info().window_info.fullscreen;
+        ctx.input_mut(|i| {
+            i.events.retain(|event| match event {
+                egui::Event::Key {
+                    key,
+                    pressed,
+                    modifiers,
+                    ..
+                } => match keys::hotkey(*key, *modifiers)
+                    .or_else(|| keys::window_key(*key, *modifiers, fullscreen, self.grab_keyboard))
+                {
+                    Some(action) => {
+                        if *pressed {
+                            actions.push(action);
+                        }
+                        false
+                    }
+                    None => true,
+                },
+                _ => true,
+            })
+        });
+
+        for action in actions {
+            match action {
+                keys::Action::ShowHelp => self.show_help = !self.show_help,
+                keys::Action::ToggleFullscreen => frame.set_fullscreen(!fullscreen),
+                keys::Action::ExitFullscreen => frame.set_fullscreen(false),
+                keys::Action::ZoomIn => self.zoom_by(1.25),
+                keys::Action::ZoomOut => self.zoom_by(0.8),
+                keys::Action::ZoomReset => self.reset_zoom(),
+                keys::Action::ToggleFitToWindow => self.toggle_fit_to_window(),
+                keys::Action::SendCtrlAltDel => {
+                    if let Some(ref mut vnc) = self.vnc_client {
+                        if !self.view_only {
+                            send_keys(vnc, keys::CTRL_ALT_DEL);
+                        }
+                    }
+                }
+                keys::Action::Screenshot => self.save_screenshot(),
+                keys::Action::ReleasePointer => {
+                    self.pointer_grabbed = false;
+                    self.grab_keyboard = false;
+                }
+            }
+        }
+    }
+
+    /// The status text, in red while it reports a failed connection attempt.
+    fn status_label(&self, ui: &mut egui::Ui) {
+        let text = egui::RichText::new(&self.status_text);
+        if self.connect_error.as_ref() == Some(&self.status_text) {
+            ui.label(text.color(ui.visuals().error_fg_color));
+        } else {
+            ui.label(text);
+        }
+    }
+
+    /// The encoding frames arrive in, in the warning colour when it isn't the
+    /// one asked for.
+    fn encoding_label(&self, ui: &mut egui::Ui, encoding: vnc::Encoding) {
+        let name = encoding_name(encoding);
+        let mut text = egui::RichText::new(format!("Encoding: {}", name));
+        if name != self.preferred_encoding {
+            text = text.color(ui.visuals().warn_fg_color);
+        }
+        ui.label(text).on_hover_text(format!(
+            "Most of the last frame came in {}. Asked for {}.",
+            name, self.preferred_encoding
+        ));
+    }
+
+    /// Asks before closing the window would end a session.
+    fn show_quit_window(&mut self, ctx: &egui::Context, frame: &mut eframe::Frame) {
+        egui::Window::new("Quit")
+            .collapsible(false)
+            .resizable(false)
+            .anchor(egui::Align2::CENTER_CENTER, Vec2::ZERO)
+            .show(ctx, |ui| {
+                ui.label(format!("You are still connected to {}.", self.host));
+                ui.horizontal(|ui| {
+                    if ui.button("Disconnect and quit").clicked() {
+                        // With the session over, the next close request goes through.
+                        self.disconnect();
+                        frame.close();
+                    }
+                    if ui.button("Cancel").clicked() {
+                        self.confirm_quit = false;
+                    }
+                });
+            });
+    }
+
+    fn show_disconnect_window(&mut self, ctx: &egui::Context) {
+        egui::Window::new("Disconnect")
+            .collapsible(false)
+            .resizable(false)
+            .anchor(egui::Align2::CENTER_CENTER, Vec2::ZERO)
+            .show(ctx, |ui| {
+                ui.label(format!("End the session with {}?", self.host));
+                ui.horizontal(|ui| {
+                    if ui.button("Disconnect").clicked() {
+                        self.disconnect();
+                    }
+                    if ui.button("Cancel").clicked() {
+                        self.confirm_disconnect = false;
+                    }
+                });
+            });
+    }
+
+    /// Sends keys a desktop keyboard can't, from a list or as arbitrary X11 keysyms.
+    fn show_keysym_window(&mut self, ctx: &egui::Context) {
+        let mut open = self.show_keysym_tool;
+        egui::Window::new("Special Keys")
+            .open(&mut open)
+            .collapsible(false)
+            .resizable(false)
+            .show(ctx, |ui| {
+                ui.add_enabled_ui(!self.view_only, |ui| {
+                    let Some(ref mut vnc) = self.vnc_client else {
+                        return;
+                    };
+                    ui.horizontal_wrapped(|ui| {
+                        for key in keys::SPECIAL_KEYS {
+                            if ui.button(key.label).clicked() {
+                                send_keys(vnc, key.keysyms);
+                            }
+                        }
+                    });
+                    ui.separator();
+                    ui.horizontal_wrapped(|ui| {
+                        for n in 13..=24 {
+                            if ui.button(format!("F{}", n)).clicked() {
+                                send_keys(vnc, &[keys::function_key(n)]);
+                            }
+                        }
+                    });
+                    ui.separator();
+                    ui.horizontal_wrapped(|ui| {
+                        for key in keys::MEDIA_KEYS {
+                            if ui.button(key.label).clicked() {
+                                send_keys(vnc, key.keysyms);
+                            }
+                        }
+                    });
+                    ui.separator();
+                    ui.label("Ctrl+Alt+F-key (switches virtual terminals on Linux)");
+                    ui.horizontal_wrapped(|ui| {
+                        for n in 1..=12 {
+                            if ui.button(format!("F{}", n)).clicked() {
+                                send_keys(
+                                    vnc,
+                                    &[keys::CONTROL_L, keys::ALT_L, keys::function_key(n)],
+                                );
+                            }
+                        }
+                    });
+                });
+                ui.separator();
+
+                ui.horizontal(|ui| {
+                    ui.label("Keysym (hex):");
+                    ui.add(
+                        egui::TextEdit::singleline(&mut self.keysym_input)
+                            .desired_width(100.0)
+                            .hint_text("0x1008FF11"),
+                    );
+                });
+                let keysym = keys::parse_keysym(&self.keysym_input);
+                match keysym {
+                    Some(keysym) => ui.label(keys::keysym_name(keysym)),
+                    None => ui.label("Enter a hexadecimal keysym"),
+                };
+
+                ui.add_enabled_ui(keysym.is_some() && !self.view_only, |ui| {
+                    ui.horizontal(|ui| {
+                        let Some(keysym) = keysym else {
+                            return;
+                        };
+                        let Some(ref mut vnc) = self.vnc_client else {
+                            return;
+                        };
+                        if ui.button("Press").clicked() {
+                            send_key(vnc, &mut self.pressed_keys, true, keysym);
+                        }
+                        if ui.button("Release").clicked() {
+                            send_key(vnc, &mut self.pressed_keys, false, keysym);
+                        }
+                        if ui.button("Press and release").clicked() {
+                            send_keys(vnc, &[keysym]);
+                        }
+                    });
+                });
+            });
+        self.show_keysym_tool = open;
+    }
+
+    /// Saved hosts matching what is typed in the host field, listed below it while
+    /// typing. Anything else typed is still connected to as a new host.
+    fn host_suggestions(&mut self, ui: &egui::Ui, host_response: &egui::Response) {
+        let popup_id = ui.make_persistent_id("host_suggestions");
+        if host_response.changed() || host_response.gained_focus() {
+            ui.memory_mut(|m| m.open_popup(popup_id));
+        }
+        let hosts = self.config.matching_hosts(&self.host);
+        if hosts.is_empty() || hosts == [self.host.trim()] {
+            if ui.memory(|m| m.is_popup_open(popup_id)) {
+                ui.memory_mut(|m| m.close_popup());
+            }
+            return;
+        }
+        egui::popup::popup_below_widget(ui, popup_id, host_response, |ui| {
+            ui.set_min_width(180.0);
+            for host in hosts {
+                if ui.selectable_label(self.host == host, &host).clicked() {
+                    self.host = host.clone();
+                    self.load_config_for_host(&host);
+                    ui.memory_mut(|m| m.close_popup());
+                }
+            }
+        });
+    }
+
+    /// Saved hosts with buttons to connect to, rename and forget each.
+    fn show_saved_hosts(&mut self, ui: &mut egui::Ui) {
+        let mut hosts: Vec<_> = self.config.hosts.keys().cloned().collect();
+        hosts.sort();
+        if hosts.is_empty() {
+            return;
+        }
+
+        egui::CollapsingHeader::new("Saved hosts").show(ui, |ui| {
+            egui::ScrollArea::vertical()
+                .max_height(150.0)
+                .show(ui, |ui| {
+                    for host in hosts {
+                        ui.horizontal(|ui| {
+                            if let Some((ref old, ref mut new)) = self.renaming_host {
+                                if *old == host {
+                                    let response = ui.text_edit_singleline(new);
+                                    let enter = response.lost_focus()
+                                        && ui.input(|i| i.key_pressed(egui::Key::Enter));
+                                    if ui.button("Save").clicked() || enter {
+                                        self.rename_saved_host();
+                                    }
+                                    if ui.button("Cancel").clicked() {
+                                        self.renaming_host = None;
+                                    }
+                                    return;
+                                }
+                            }
+
+                            if ui.selectable_label(self.host == host, &host).clicked() {
+                                self.host = host.clone();
+                                self.load_config_for_host(&host);
+                            }
+                            ui.with_layout(
+                                egui::Layout::right_to_left(egui::Align::Center),
+                                |ui| {
+                                    if ui.small_button("Delete").clicked() {
+                                        self.config.remove_host(&host);
+                                        self.write_config();
+                                    }
+                                    if ui.small_button("Edit").clicked() {
+                                        self.renaming_host = Some((host.clone(), host.clone()));
+                                    }
+                                    let connect = egui::Button::new("Connect").small();
+                                    if ui.add_enabled(!self.is_connecting(), connect).clicked() {
+                                        self.host = host.clone();
+                                        self.load_config_for_host(&host);
+                                        self.cancel_reconnect();
+                                        self.connect();
+                                    }
+                                },
+                            );
+                        });
+                    }
+                });
+        });
+    }
+
+    fn rename_saved_host(&mut self) {
+        let Some((old, new)) = self.renaming_host.take() else {
+            return;
+        };
+        match self.config.rename_host(&old, &new) {
+            Ok(()) => {
+                if self.host == old {
+                    self.host = new.trim().to_string();
+                }
+                self.write_config();
+            }
+            Err(e) => {
+                self.status_text = e;
+                self.renaming_host = Some((old, new));
+            }
+        }
+    }
+
+    /// A file to export the saved hosts to or import them from.
+    fn show_host_transfer(&mut self, ui: &mut egui::Ui) {
+        egui::CollapsingHeader::new("Export and import").show(ui, |ui| {
+            ui.horizontal(|ui| {
+                ui.label("File:");
+                ui.add(
+                    egui::TextEdit::singleline(&mut self.config.hosts_file).hint_text("hosts.json"),
+                );
+            });
+            ui.checkbox(&mut self.export_passwords, "Include passwords")
+                .on_hover_text("Leave them out for a list to share with others");
+            ui.horizontal(|ui| {
+                let chosen = !self.config.hosts_file.trim().is_empty();
+                let export = egui::Button::new("Export connections");
+                if ui
+                    .add_enabled(chosen && !self.config.hosts.is_empty(), export)
+                    .clicked()
+                {
+                    self.export_saved_hosts();
+                }
+                if ui
+                    .add_enabled(chosen, egui::Button::new("Import connections"))
+                    .clicked()
+                {
+                    self.import_saved_hosts();
+                }
+            });
+        });
+    }
+
+    fn export_saved_hosts(&mut self) {
+        let path = PathBuf::from(self.config.hosts_file.trim());
+        let result = self
+            .config
+            .export_hosts(self.export_passwords)
+            .map_err(|e| e.to_string())
+            .and_then(|json| std::fs::write(&path, json).map_err(|e| e.to_string()));
+        self.status_text = match result {
+            Ok(()) => {
+                info!("Exported saved hosts to {}", path.display());
+                self.write_config();
+                format!(
+                    "Exported {} hosts to {}",
+                    self.config.hosts.len(),
+                    path.display()
+                )
+            }
+            Err(e) => {
+                warn!("Failed to export to {}: {}", path.display(), e);
+                format!("Failed to export: {}", e)
+            }
+        };
+    }
+
+    /// Adds the hosts in the chosen file, asking first about any that would
+    /// replace different settings.
+    fn import_saved_hosts(&mut self) {
+        let path = PathBuf::from(self.config.hosts_file.trim());
+        let list = std::fs::read_to_string(&path)
+            .map_err(|e| e.to_string())
+            .and_then(|json| {
+                serde_json::from_str::<config::HostList>(&json).map_err(|e| e.to_string())
+            });
+        match list {
+            Ok(list) => {
+                let import = self.config.prepare_import(list);
+                if import.conflicts.is_empty() {
+                    self.finish_import(import);
+                } else {
+                    self.pending_import = Some(import);
+                }
+            }
+            Err(e) => {
+                warn!("Failed to import from {}: {}", path.display(), e);
+                self.status_text = format!("Failed to import: {}", e);
+            }
+        }
+    }
+
+    fn finish_import(&mut self, import: config::HostImport) {
+        let count = self.config.import_hosts(import);
+        self.write_config();
+        self.status_text = format!("Imported {} hosts", count);
+    }
+
+    /// Asks which saved hosts the import may replace.
+    fn show_import_conflicts(&mut self, ctx: &egui::Context) {
+        let Some(ref mut import) = self.pending_import else {
+            return;
+        };
+        let mut finish = None;
+        egui::Window::new("Import connections")
+            .collapsible(false)
+            .resizable(false)
+            .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+            .show(ctx, |ui| {
+                ui.label("These hosts are already saved with other settings:");
+                egui::ScrollArea::vertical()
+                    .max_height(200.0)
+                    .show(ui, |ui| {
+                        egui::Grid::new("import_conflicts").show(ui, |ui| {
+                            for (host, _, replace) in &mut import.conflicts {
+                                ui.label(host.as_str());
+                                ui.radio_value(replace, false, "Keep mine");
+                                ui.radio_value(replace, true, "Replace");
+                                ui.end_row();
+                            }
+                        });
+                    });
+                ui.horizontal(|ui| {
+                    if ui.button("Import").clicked() {
+                        finish = Some(true);
+                    }
+                    if ui.button("Cancel").clicked() {
+                        finish = Some(false);
+                    }
+                });
+            });
+        match finish {
+            Some(true) => {
+                if let Some(import) = self.pending_import.take() {
+                    self.finish_import(import);
+                }
+            }
+            Some(false) => {
+                self.pending_import = None;
+                self.status_text = "Import cancelled".to_string();
+            }
+            None => {}
+        }
+    }
+
+    fn show_password_prompt(&mut self, ctx: &egui::Context) {
+        let Some(ref mut prompt) = self.password_prompt else {
+            return;
+        };
+        let mut answer = None;
+        egui::Window::new("Password")
+            .collapsible(false)
+            .resizable(false)
+            .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+            .show(ctx, |ui| {
+                ui.label(format!("{} asks for a password.", self.host));
+                let field = ui.add(
+                    egui::TextEdit::singleline(&mut prompt.password)
+                        .password(true)
+                        .desired_width(220.0),
+                );
+                if field.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter)) {
+                    answer = Some(true);
+                } else if !field.has_focus() && prompt.password.is_empty() {
+                    field.request_focus();
+                }
+                ui.add_enabled(
+                    !self.private,
+                    egui::Checkbox::new(&mut prompt.save, "Save password for this host"),
+                );
+                ui.horizontal(|ui| {
+                    if ui.button("OK").clicked() {
+                        answer = Some(true);
+                    }
+                    if ui.button("Cancel").clicked() {
+                        answer = Some(false);
+                    }
+                });
+            });
+        if let Some(confirmed) = answer {
+            self.answer_password_prompt(confirmed);
+        }
+    }
+
+    fn show_help_window(&mut self, ctx: &egui::Context) {
+        let mut open = self.show_help;
+        egui::Window::new("Keyboard Shortcuts")
+            .open(&mut open)
+            .collapsible(false)
+            .resizable(false)
+            .anchor(egui::Align2::CENTER_CENTER, Vec2::ZERO)
+            .show(ctx, |ui| {
+                egui::Grid::new("hotkey_grid")
+                    .num_columns(2)
+                    .striped(true)
+                    .show(ui, |ui| {
+                        for hotkey in keys::HOTKEYS {
+                            ui.label(
+                                egui::RichText::new(keys::hotkey_label(hotkey.key)).monospace(),
+                            );
+                            ui.label(hotkey.description);
+                            ui.end_row();
+                        }
+                        for (key, description) in [
+                            ("F11", "Toggle full screen"),
+                            ("Escape", "Leave full screen"),
+                        ] {
+                            ui.label(egui::RichText::new(key).monospace());
+                            ui.label(description);
+                            ui.end_row();
+                        }
+                        if self.config.quick_keys.enabled {
+                            for (digit, keysym) in &self.config.quick_keys.keys {
+                                ui.label(
+                                    egui::RichText::new(format!("Ctrl+Alt+{}", digit)).monospace(),
+                                );
+                                ui.label(format!("Send {}", keys::keysym_name(*keysym)));
+                                ui.end_row();
+                            }
+                        }
+                    });
+            });
+        self.show_help = open;
+    }
+
     pub fn handle_input(&mut self, ui: &egui::Ui, response: &egui::Response) {
+        // Server updates don't count, only someone at this end.
+        let active = ui.input(|i| {
+            i.events.iter().any(|event| {
+                matches!(
+                    event,
+                    egui::Event::Key { .. }
+                        | egui::Event::Text(_)
+                        | egui::Event::Paste(_)
+                        | egui::Event::PointerMoved(_)
+                        | egui::Event::PointerButton { .. }
+                        | egui::Event::Scroll(_)
+                        | egui::Event::Zoom(_)
+                        | egui::Event::Touch { .. }
+                )
+            })
+        });
+        if active {
+            self.last_input_at = Some(std::time::Instant::now());
+        }
+
         if self.view_only {
+            self.release_held_keys();
             return;
         }
 
+        let view = self.view_rect();
         let Some(ref mut vnc) = self.vnc_client else {
             return;
         };
 
+        // Fingers zooming and panning don't press anything on the remote.
+        if self.touch_gesture {
+            if self.last_buttons != 0 {
+                if let Some((x, y)) = self.last_pointer_pos {
+                    let _ = vnc.send_pointer_event(0, x, y);
+                }
+                self.last_buttons = 0;
+            }
+            return;
+        }
+
+        let over_minimap = response
+            .hover_pos()
+            .is_some_and(|pos| self.minimap_rect.is_some_and(|rect| rect.contains(pos)));
+
+        // Grabbing only ever happens on an explicit click, never on its own.
+        if (self.capture_mouse || self.relative_pointer)
+            && response.clicked()
+            && !over_minimap
+            && !self.pan_mode
+        {
+            self.pointer_grabbed = true;
+        }
+        let relative = self.relative_pointer && self.pointer_grabbed;
+        if !relative {
+            self.relative.reset();
+        }
+
         // Mouse motion and clicks
-        if response.hovered() {
+        if response.hovered() && !over_minimap && !self.pan_mode {
             if let Some(pos) = response.hover_pos() {
                 let rect = response.rect;
-                let x = (((pos.x - rect.min.x) / rect.width()) * self.screen_size.0 as f32) as u16;
-                let y = (((pos.y - rect.min.y) / rect.height()) * self.screen_size.1 as f32) as u16;
+                let (x, y) = if relative {
+                    let start = self
+                        .last_pointer_pos
+                        .unwrap_or((view.left + view.width / 2, view.top + view.height / 2));
+                    let scale = rect.width() / view.width.max(1) as f32;
+                    let moved = self.relative.motion(pos, start, scale, self.screen_size);
+                    self.relative.recenter(rect, ui.ctx().pixels_per_point());
+                    moved
+                } else {
+                    (
+                        view.left
+                            + (((pos.x - rect.min.x) / rect.width()) * view.width as f32) as u16,
+                        view.top
+                            + (((pos.y - rect.min.y) / rect.height()) * view.height as f32) as u16,
+                    )
+                };
 
                 let mut buttons = 0u8;
-                ui.input(|i| {
+                let alt = ui.input(|i| {
                     if i.pointer.button_down(egui::PointerButton::Primary) {
                         buttons |= 0x01;
                     }
@@ -127,29 +1117,87 @@ impl VncApp {
                     if i.pointer.button_down(egui::PointerButton::Secondary) {
                         buttons |= 0x04;
                     }
+                    i.modifiers.alt
                 });
-                if self.last_pointer_pos != Some((x, y)) || self.last_buttons != buttons {
+                let buttons = self.button_map.apply(buttons, alt);
+                // Button changes go out at once, motion alone no faster than the cap.
+                // A throttled position is sent by the repaint once the wait is over.
+                let moved = self.last_pointer_pos != Some((x, y));
+                let wait = remaining_interval(self.last_pointer_sent, self.pointer_max_rate);
+                if self.last_buttons != buttons || (moved && wait.is_none()) {
                     let _ = vnc.send_pointer_event(buttons, x, y);
                     self.last_pointer_pos = Some((x, y));
                     self.last_buttons = buttons;
+                    self.last_pointer_sent = Some(std::time::Instant::now());
+                } else if let Some(wait) = wait.filter(|_| moved) {
+                    ui.ctx().request_repaint_after(wait);
+                }
+
+                // Scrolling over the screen goes to the remote, not to the viewer's
+                // scroll area, which reads the delta after its contents.
+                let scroll_delta = ui.ctx().input_mut(|i| std::mem::take(&mut i.scroll_delta));
+                for (wheel, count) in self.scroll.clicks(scroll_delta) {
+                    for _ in 0..count {
+                        let _ = vnc.send_pointer_event(buttons | wheel, x, y);
+                        let _ = vnc.send_pointer_event(buttons, x, y);
+                    }
                 }
             }
         }
 
         // Keyboard
+        // Typing into one of the viewer's own text fields, such as the keysym
+        // field of the Send Keys window, stays local. The remote lets go of
+        // whatever is held, as when the window loses focus.
+        let typing = ui.ctx().wants_keyboard_input();
+        // Modifiers held as the window lost focus are released there, don't press
+        // them again.
+        let modifiers = if self.window_focused && !typing {
+            ui.input(|i| i.modifiers)
+        } else {
+            egui::Modifiers::NONE
+        };
+        for (pressed, keysym) in
+            keys::modifier_events(self.remote_modifiers, modifiers, self.config.altgr)
+        {
+            send_key(vnc, &mut self.pressed_keys, pressed, keysym);
+        }
+        self.remote_modifiers = modifiers;
+        let grabbed = keyboard_grab::take_keys();
+        if typing {
+            release_keys(vnc, &mut self.pressed_keys);
+            return;
+        }
+        for key in grabbed {
+            send_key(vnc, &mut self.pressed_keys, key.pressed, key.keysym);
+        }
         ui.input(|i| {
             for event in &i.events {
                 match event {
-                    egui::Event::Key { key, pressed, .. } => {
-                        if let Some(keysym) = keys::map_key(*key) {
-                            let _ = vnc.send_key_event(*pressed, keysym);
+                    egui::Event::Key {
+                        key,
+                        pressed,
+                        modifiers,
+                        ..
+                    } => {
+                        if let Some(keysym) =
+                            keys::quick_key(&self.config.quick_keys, *key, *modifiers)
+                        {
+                            // Tap on press and swallow the release, so the remote key
+                            // can't stick if the modifiers are let go first.
+                            if *pressed {
+                                send_key(vnc, &mut self.pressed_keys, true, keysym);
+                                send_key(vnc, &mut self.pressed_keys, false, keysym);
+                            }
+                        } else if let Some(keysym) = keys::map_key(*key) {
+                            send_key(vnc, &mut self.pressed_keys, *pressed, keysym);
                         }
                     }
                     egui::Event::Text(text) => {
                         for c in text.chars() {
                             let keysym = 0x01000000 + c as u32;
-                            let _ = vnc.send_key_event(true, keysym);
-                            let _ = vnc.send_key_event(false, keysym);
+                            send_key(vnc, &mut self.pressed_keys, true, keysym);
+                            send_key(vnc, &mut self.pressed_keys, false, keysym);
                         }
                     }
                     _ => {}
@@ -167,6 +1215,40 @@ impl eframe::App for VncApp {
         }
 
         self.handle_vnc_events(ctx);
+        self.sync_clipboard(ctx);
+        self.last_repaint = std::time::Instant::now();
+
+        // Never keep the pointer trapped in a window the user has left.
+        let window = &frame.info().window_info;
+        if self.pointer_grabbed && (window.minimized || !window.focused) {
+            self.pointer_grabbed = false;
+        }
+
+        let focused = window.focused && !window.minimized;
+        if focused != self.window_focused {
+            self.window_focused = focused;
+            if !focused {
+                self.release_held_keys();
+            }
+            // The frozen frame is stale by now.
+            if focused && self.config.freeze_unfocused {
+                self.request_full_update();
+            }
+        }
+
+        if std::mem::take(&mut self.bell_pending) {
+            self.ring_bell(frame, focused);
+        }
+
+        keyboard_grab::set_active(
+            ctx,
+            self.grab_keyboard && self.state == AppState::Viewing && focused && !self.view_only,
+        );
+        if self.state == AppState::Viewing {
+            self.update_window_prefs(frame);
+            self.handle_hotkeys(ctx, frame);
+        }
+        self.open_dropped_file(ctx);
 
         match self.state {
             AppState::Connect => {
@@ -194,6 +1276,18 @@ impl eframe::App for VncApp {
                                             egui::RichText::new("Connect to your remote session")
                                                 .color(Color32::from_rgb(150, 150, 160)),
                                         );
+                                        if self.private {
+                                            ui.label(
+                                                egui::RichText::new(" Private ")
+                                                    .small()
+                                                    .strong()
+                                                    .color(Color32::WHITE)
+                                                    .background_color(Color32::from_rgb(
+                                                        120, 60, 160,
+                                                    )),
+                                            )
+                                            .on_hover_text("Nothing is saved this session");
+                                        }
                                     });
 
                                     ui.add_space(20.0);
@@ -213,6 +1307,7 @@ impl eframe::App for VncApp {
                                                     let host = self.host.clone();
                                                     self.load_config_for_host(&host);
                                                 }
+                                                self.host_suggestions(ui, &host_response);
 
                                                 let mut hosts: Vec<_> =
                                                     self.config.hosts.keys().cloned().collect();
@@ -248,6 +1343,17 @@ impl eframe::App for VncApp {
                                             );
                                             ui.end_row();
 
+                                            ui.label(egui::RichText::new("Username:").strong());
+                                            ui.add(
+                                                egui::TextEdit::singleline(&mut self.username)
+                                                    .hint_text("Only for account login"),
+                                            )
+                                            .on_hover_text(
+                                                "Used with servers that log in with a Windows \
+                                                 or Unix account (UltraVNC MS-Logon)",
+                                            );
+                                            ui.end_row();
+
                                             ui.label(egui::RichText::new("Password:").strong());
                                             ui.add(
                                                 egui::TextEdit::singleline(&mut self.password)
@@ -255,14 +1361,89 @@ impl eframe::App for VncApp {
                                                     .hint_text("Optional"),
                                             );
                                             ui.end_row();
+
+                                            ui.label(egui::RichText::new("MAC address:").strong());
+                                            ui.horizontal(|ui| {
+                                                ui.add(
+                                                    egui::TextEdit::singleline(
+                                                        &mut self.mac_address,
+                                                    )
+                                                    .desired_width(140.0)
+                                                    .hint_text("For Wake-on-LAN"),
+                                                );
+                                                if ui
+                                                    .add_enabled(
+                                                        !self.mac_address.trim().is_empty(),
+                                                        egui::Button::new("Wake"),
+                                                    )
+                                                    .on_hover_text(
+                                                        "Send a Wake-on-LAN packet on the local \
+                                                         network",
+                                                    )
+                                                    .clicked()
+                                                {
+                                                    self.wake();
+                                                }
+                                            });
+                                            ui.end_row();
                                         });
 
+                                    if self.password_truncated() {
+                                        ui.add_space(5.0);
+                                        ui.colored_label(
+                                            ui.visuals().warn_fg_color,
+                                            PASSWORD_TRUNCATED_WARNING,
+                                        );
+                                    }
+
                                     ui.add_space(15.0);
-                                    ui.checkbox(&mut self.shared, "Request shared session");
+                                    ui.radio_value(
+                                        &mut self.shared,
+                                        true,
+                                        "Share the session with other viewers",
+                                    );
+                                    ui.radio_value(
+                                        &mut self.shared,
+                                        false,
+                                        "Take over the session (disconnect others)",
+                                    )
+                                    .on_hover_text(
+                                        "Asks for exclusive use: the server disconnects \
+                                         anyone else viewing this screen, or depending on \
+                                         its settings refuses this connection instead",
+                                    );
+                                    ui.checkbox(
+                                        &mut self.config.connect_after_wake,
+                                        "Connect after waking",
+                                    )
+                                    .on_hover_text(
+                                        "Connect 20 seconds after sending a Wake-on-LAN \
+                                         packet, once the machine has had time to start",
+                                    );
+                                    ui.checkbox(&mut self.private, "Private session")
+                                        .on_hover_text(
+                                            "Don't save the host, password or options \
+                                             to the config file",
+                                        );
 
                                     ui.add_space(25.0);
 
                                     ui.vertical_centered_justified(|ui| {
+                                        if self.is_connecting() {
+                                            ui.horizontal(|ui| {
+                                                ui.add(egui::Spinner::new().size(24.0));
+                                                ui.add_enabled(
+                                                    false,
+                                                    egui::Button::new(
+                                                        egui::RichText::new("Connecting...")
+                                                            .size(16.0)
+                                                            .strong(),
+                                                    )
+                                                    .min_size(Vec2::new(ui.available_width(), 40.0)),
+                                                );
+                                            });
+                                            return;
+                                        }
                                         let connect_btn = ui.add_sized(
                                             [ui.available_width(), 40.0],
                                             egui::Button::new(
@@ -274,11 +1455,16 @@ impl eframe::App for VncApp {
                                         );
 
                                         if connect_btn.clicked() {
+                                            self.cancel_reconnect();
                                             self.connect();
                                         }
                                     });
 
-                                    ui.add_space(20.0);
+                                    ui.add_space(15.0);
+                                    self.show_saved_hosts(ui);
+                                    self.show_host_transfer(ui);
+
+                                    ui.add_space(5.0);
                                     ui.horizontal(|ui| {
                                         ui.style_mut().visuals.widgets.inactive.bg_fill =
                                             Color32::from_rgb(40, 40, 50);
@@ -292,8 +1478,7 @@ impl eframe::App for VncApp {
                                                     frame.close();
                                                 }
                                                 if ui.button("Clear history").clicked() {
-                                                    let _ = std::fs::remove_file("vnc_config.json");
-                                                    *self = Self::default();
+                                                    self.clear_history();
                                                 }
                                             },
                                         );
@@ -304,7 +1489,16 @@ impl eframe::App for VncApp {
                     });
 
                 egui::TopBottomPanel::bottom("status_bar").show(ctx, |ui| {
-                    ui.label(&self.status_text);
+                    ui.horizontal(|ui| {
+                        self.status_label(ui);
+                        if self.vnc_rx.is_some() && ui.button("Cancel").clicked() {
+                            self.cancel_connect();
+                        }
+                        if self.reconnecting() && ui.button("Stop reconnecting").clicked() {
+                            self.cancel_reconnect();
+                            self.status_text = "Reconnection stopped".to_string();
+                        }
+                    });
                 });
             }
             AppState::Viewing => {
@@ -336,70 +1530,58 @@ impl eframe::App for VncApp {
                                             .tint(Color32::WHITE),
                                     )
                                     .on_hover_text("Refresh")
-                                    .clicked()
-                                {
-                                    if let Some(ref mut vnc) = self.vnc_client {
-                                        let _ = vnc.request_update(
-                                            vnc::Rect {
-                                                left: 0,
-                                                top: 0,
-                                                width: self.screen_size.0,
-                                                height: self.screen_size.1,
-                                            },
-                                            false,
-                                        );
-                                    }
-                                }
-                            } else if ui.button("🔄").on_hover_text("Refresh").clicked() {
-                                if let Some(ref mut vnc) = self.vnc_client {
-                                    let _ = vnc.request_update(
-                                        vnc::Rect {
-                                            left: 0,
-                                            top: 0,
-                                            width: self.screen_size.0,
-                                            height: self.screen_size.1,
-                                        },
-                                        false,
-                                    );
+                                    .clicked()
+                                {
+                                    self.request_full_update();
                                 }
+                            } else if ui.button("🔄").on_hover_text("Refresh").clicked() {
+                                self.request_full_update();
                             }
 
                             ui.add(egui::Separator::default().vertical().spacing(2.0));
 
+                            let can_zoom_out = self.fit_to_window || self.scale > MIN_SCALE;
                             if let Some(icon) = self.icons.get("button-zoom-out") {
                                 if ui
-                                    .add(
+                                    .add_enabled(
+                                        can_zoom_out,
                                         egui::ImageButton::new(icon, Vec2::splat(18.0))
                                             .tint(Color32::WHITE),
                                     )
                                     .on_hover_text("Zoom Out")
                                     .clicked()
                                 {
-                                    self.scale *= 0.8;
-                                    self.zoom_fit = false;
+                                    self.zoom_by(0.8);
                                     ctx.request_repaint();
                                 }
-                            } else if ui.button("➖").on_hover_text("Zoom Out").clicked() {
-                                self.scale *= 0.8;
-                                self.zoom_fit = false;
+                            } else if ui
+                                .add_enabled(can_zoom_out, egui::Button::new("➖"))
+                                .on_hover_text("Zoom Out")
+                                .clicked()
+                            {
+                                self.zoom_by(0.8);
                             }
 
+                            let can_zoom_in = self.fit_to_window || self.scale < MAX_SCALE;
                             if let Some(icon) = self.icons.get("button-zoom-in") {
                                 if ui
-                                    .add(
+                                    .add_enabled(
+                                        can_zoom_in,
                                         egui::ImageButton::new(icon, Vec2::splat(18.0))
                                             .tint(Color32::WHITE),
                                     )
                                     .on_hover_text("Zoom In")
                                     .clicked()
                                 {
-                                    self.scale *= 1.25;
-                                    self.zoom_fit = false;
+                                    self.zoom_by(1.25);
                                     ctx.request_repaint();
                                 }
-                            } else if ui.button("➕").on_hover_text("Zoom In").clicked() {
-                                self.scale *= 1.25;
-                                self.zoom_fit = false;
+                            } else if ui
+                                .add_enabled(can_zoom_in, egui::Button::new("➕"))
+                                .on_hover_text("Zoom In")
+                                .clicked()
+                            {
+                                self.zoom_by(1.25);
                             }
 
                             if let Some(icon) = self.icons.get("button-zoom-100") {
@@ -408,16 +1590,18 @@ impl eframe::App for VncApp {
                                         egui::ImageButton::new(icon, Vec2::splat(18.0))
                                             .tint(Color32::WHITE),
                                     )
-                                    .on_hover_text("Zoom 100%")
+                                    .on_hover_text("Zoom 100%, centred")
                                     .clicked()
                                 {
-                                    self.scale = 1.0;
-                                    self.zoom_fit = false;
+                                    self.reset_zoom();
                                     ctx.request_repaint();
                                 }
-                            } else if ui.button("1:1").on_hover_text("Zoom 100%").clicked() {
-                                self.scale = 1.0;
-                                self.zoom_fit = false;
+                            } else if ui
+                                .button("1:1")
+                                .on_hover_text("Zoom 100%, centred")
+                                .clicked()
+                            {
+                                self.reset_zoom();
                             }
 
                             if let Some(icon) = self.icons.get("button-zoom-fit") {
@@ -426,14 +1610,64 @@ impl eframe::App for VncApp {
                                         egui::ImageButton::new(icon, Vec2::splat(18.0))
                                             .tint(Color32::WHITE),
                                     )
-                                    .on_hover_text("Zoom to Fit")
+                                    .on_hover_text("Fit to Window")
                                     .clicked()
                                 {
-                                    self.zoom_fit = !self.zoom_fit;
+                                    self.toggle_fit_to_window();
                                     ctx.request_repaint();
                                 }
-                            } else if ui.button("⛶").on_hover_text("Zoom to Fit").clicked() {
-                                self.zoom_fit = !self.zoom_fit;
+                            } else if ui.button("⛶").on_hover_text("Fit to Window").clicked() {
+                                self.toggle_fit_to_window();
+                            }
+
+                            let selected = match self.fit_label() {
+                                Some(label) => label.to_string(),
+                                None => format!("{:.0}%", self.scale * 100.0),
+                            };
+                            egui::ComboBox::from_id_source("zoom_presets")
+                                .selected_text(selected)
+                                .width(60.0)
+                                .show_ui(ui, |ui| {
+                                    for percent in ZOOM_PRESETS {
+                                        if ui.button(format!("{}%", percent)).clicked() {
+                                            self.set_scale(percent as f32 / 100.0);
+                                        }
+                                    }
+                                    ui.separator();
+                                    if ui.button("Fit").clicked() {
+                                        self.set_fit(false);
+                                    }
+                                    if ui
+                                        .button("Fill")
+                                        .on_hover_text("Cover the window, cropping the overflow")
+                                        .clicked()
+                                    {
+                                        self.set_fit(true);
+                                    }
+                                    if ui
+                                        .button("Stretch")
+                                        .on_hover_text(
+                                            "Fill the window exactly, ignoring the aspect ratio",
+                                        )
+                                        .clicked()
+                                    {
+                                        self.set_stretch();
+                                    }
+                                });
+
+                            if ui
+                                .selectable_label(self.config.minimap, "🗺")
+                                .on_hover_text(
+                                    "Minimap: an overview of the screen in the corner while it \
+                                     is larger than the window; click it to scroll there",
+                                )
+                                .clicked()
+                            {
+                                self.set_minimap(!self.config.minimap);
+                            }
+
+                            if self.screens.len() > 1 {
+                                self.monitor_selector(ui);
                             }
 
                             if let Some(icon) = self.icons.get("button-zoom-fullscreen") {
@@ -453,6 +1687,14 @@ impl eframe::App for VncApp {
                                 frame.set_fullscreen(!fullscreen);
                             }
 
+                            if ui.button("⏏").on_hover_text("Disconnect").clicked() {
+                                if self.config.confirm_disconnect {
+                                    self.confirm_disconnect = true;
+                                } else {
+                                    self.disconnect();
+                                }
+                            }
+
                             ui.add(egui::Separator::default().vertical().spacing(2.0));
 
                             if let Some(icon) = self.icons.get("button-ctrl-alt-del") {
@@ -465,12 +1707,9 @@ impl eframe::App for VncApp {
                                     .clicked()
                                 {
                                     if let Some(ref mut vnc) = self.vnc_client {
-                                        let _ = vnc.send_key_event(true, 0xFFE3); // Ctrl
-                                        let _ = vnc.send_key_event(true, 0xFFE9); // Alt
-                                        let _ = vnc.send_key_event(true, 0xFFFF); // Del
-                                        let _ = vnc.send_key_event(false, 0xFFFF);
-                                        let _ = vnc.send_key_event(false, 0xFFE9);
-                                        let _ = vnc.send_key_event(false, 0xFFE3);
+                                        if !self.view_only {
+                                            send_keys(vnc, keys::CTRL_ALT_DEL);
+                                        }
                                     }
                                 }
                             } else if ui
@@ -479,12 +1718,9 @@ impl eframe::App for VncApp {
                                 .clicked()
                             {
                                 if let Some(ref mut vnc) = self.vnc_client {
-                                    let _ = vnc.send_key_event(true, 0xFFE3); // Ctrl
-                                    let _ = vnc.send_key_event(true, 0xFFE9); // Alt
-                                    let _ = vnc.send_key_event(true, 0xFFFF); // Del
-                                    let _ = vnc.send_key_event(false, 0xFFFF);
-                                    let _ = vnc.send_key_event(false, 0xFFE9);
-                                    let _ = vnc.send_key_event(false, 0xFFE3);
+                                    if !self.view_only {
+                                        send_keys(vnc, keys::CTRL_ALT_DEL);
+                                    }
                                 }
                             }
 
@@ -498,20 +1734,148 @@ impl eframe::App for VncApp {
                                     .clicked()
                                 {
                                     if let Some(ref mut vnc) = self.vnc_client {
-                                        let _ = vnc.send_key_event(true, 0xFFE3); // Ctrl
-                                        let _ = vnc.send_key_event(true, 0xFF1B); // Esc
-                                        let _ = vnc.send_key_event(false, 0xFF1B);
-                                        let _ = vnc.send_key_event(false, 0xFFE3);
+                                        if !self.view_only {
+                                            send_keys(vnc, keys::CTRL_ESC);
+                                        }
                                     }
                                 }
                             } else if ui.button("Win").on_hover_text("Send Win Key").clicked() {
                                 if let Some(ref mut vnc) = self.vnc_client {
-                                    let _ = vnc.send_key_event(true, 0xFFE3); // Ctrl
-                                    let _ = vnc.send_key_event(true, 0xFF1B); // Esc
-                                    let _ = vnc.send_key_event(false, 0xFF1B);
-                                    let _ = vnc.send_key_event(false, 0xFFE3);
+                                    if !self.view_only {
+                                        send_keys(vnc, keys::CTRL_ESC);
+                                    }
+                                }
+                            }
+
+                            if ui
+                                .button("📷")
+                                .on_hover_text(format!(
+                                    "Save screenshot ({})",
+                                    keys::hotkey_label(egui::Key::S)
+                                ))
+                                .clicked()
+                            {
+                                self.save_screenshot();
+                            }
+
+                            let recording = self.recording.is_some();
+                            let record_button = egui::RichText::new("⏺").color(if recording {
+                                Color32::RED
+                            } else {
+                                Color32::WHITE
+                            });
+                            if ui
+                                .selectable_label(recording, record_button)
+                                .on_hover_text(if recording {
+                                    "Stop recording"
+                                } else {
+                                    "Record the screen as a PNG sequence"
+                                })
+                                .clicked()
+                            {
+                                if recording {
+                                    self.stop_recording();
+                                } else {
+                                    self.start_recording();
                                 }
                             }
+                            if let Some(ref recording) = self.recording {
+                                let elapsed = recording.started.elapsed().as_secs();
+                                ui.label(
+                                    egui::RichText::new(format!(
+                                        "REC {:02}:{:02}",
+                                        elapsed / 60,
+                                        elapsed % 60
+                                    ))
+                                    .color(Color32::RED),
+                                );
+                                ui.ctx()
+                                    .request_repaint_after(std::time::Duration::from_secs(1));
+                            }
+
+                            if ui.button("Fn").on_hover_text("Special keys").clicked() {
+                                self.show_keysym_tool = !self.show_keysym_tool;
+                            }
+
+                            if ui
+                                .selectable_label(self.show_virtual_keyboard, "🖮")
+                                .on_hover_text("On-screen keyboard, for touch screens")
+                                .clicked()
+                            {
+                                self.show_virtual_keyboard = !self.show_virtual_keyboard;
+                            }
+
+                            if ui
+                                .selectable_label(self.grab_keyboard, "⌨")
+                                .on_hover_text(format!(
+                                    "Grab keyboard: send Alt+Tab, the Windows key and \
+                                     function key combinations to the remote ({} releases)",
+                                    keys::hotkey_label(egui::Key::G)
+                                ))
+                                .clicked()
+                            {
+                                self.grab_keyboard = !self.grab_keyboard;
+                            }
+                            if self.grab_keyboard {
+                                ui.label(
+                                    egui::RichText::new(" Keyboard grabbed ")
+                                        .small()
+                                        .strong()
+                                        .color(Color32::WHITE)
+                                        .background_color(Color32::from_rgb(0, 120, 215)),
+                                );
+                            }
+
+                            if ui
+                                .selectable_label(self.pan_mode, "✋")
+                                .on_hover_text(
+                                    "Pan mode: drag with the left or middle button to move \
+                                     the zoomed view; the remote gets no mouse input meanwhile",
+                                )
+                                .clicked()
+                            {
+                                self.pan_mode = !self.pan_mode;
+                            }
+
+                            let (lock, lock_hint) = if self.view_only {
+                                ("🔒", "View only: click to send input to the remote")
+                            } else {
+                                ("🔓", "Input goes to the remote: click for view only")
+                            };
+                            if ui
+                                .selectable_label(self.view_only, lock)
+                                .on_hover_text(lock_hint)
+                                .clicked()
+                            {
+                                self.set_view_only(!self.view_only);
+                            }
+
+                            if ui
+                                .button("?")
+                                .on_hover_text(format!(
+                                    "Keyboard shortcuts ({})",
+                                    keys::hotkey_label(egui::Key::H)
+                                ))
+                                .clicked()
+                            {
+                                self.show_help = !self.show_help;
+                            }
+
+                            let can_paste_image = !self.view_only
+                                && !self.disable_clipboard
+                                && self.vnc_client.as_ref().is_some_and(|vnc| {
+                                    vnc.clipboard_caps().is_some_and(|caps| {
+                                        caps.supports(vnc::clipboard::FORMAT_DIB)
+                                    })
+                                });
+                            if can_paste_image
+                                && ui
+                                    .button("📋")
+                                    .on_hover_text("Paste clipboard image to remote")
+                                    .clicked()
+                            {
+                                self.paste_clipboard_image();
+                            }
 
                             // Move right-aligned items into the SAME horizontal row
                             ui.with_layout(
@@ -547,75 +1911,154 @@ impl eframe::App for VncApp {
                                     ui.label(format!(
                                         "Scale: {:.2} {}",
                                         self.scale,
-                                        if self.zoom_fit { "(Fit)" } else { "" }
+                                        self.fit_label()
+                                            .map(|label| format!("({})", label))
+                                            .unwrap_or_default()
                                     ));
+                                    ui.add(egui::Separator::default().vertical().spacing(2.0));
+                                    if let Some(encoding) = self.frame_encoding {
+                                        self.encoding_label(ui, encoding);
+                                        ui.add(egui::Separator::default().vertical().spacing(2.0));
+                                    }
+                                    ui.label(egui::RichText::new(&self.status_text).weak());
                                 },
                             );
                         });
                     });
 
+                let background = match self.config.background {
+                    Some([r, g, b]) => Color32::from_rgb(r, g, b),
+                    None if ctx.style().visuals.dark_mode => Color32::from_rgb(30, 30, 30),
+                    None => Color32::WHITE,
+                };
                 egui::CentralPanel::default()
-                    .frame(egui::Frame::none().fill(if ctx.style().visuals.dark_mode {
-                        Color32::from_rgb(30, 30, 30)
-                    } else {
-                        Color32::WHITE
-                    }))
+                    .frame(egui::Frame::none().fill(background))
                     .show(ctx, |ui| {
                         let available_size = ui.available_size();
-                        let texture_size =
-                            Vec2::new(self.screen_size.0 as f32, self.screen_size.1 as f32);
+                        if let Some(wait) =
+                            self.fit_remote_to_window(available_size, ctx.pixels_per_point())
+                        {
+                            ctx.request_repaint_after(wait);
+                        }
+                        let view = self.view_rect();
+                        let texture_size = Vec2::new(view.width as f32, view.height as f32);
 
-                        let display_size = if self.zoom_fit {
-                            let ratio = (available_size.x / texture_size.x)
-                                .min(available_size.y / texture_size.y);
+                        let display_size = if self.fit_to_window && self.stretch_window {
+                            available_size
+                        } else if self.fit_to_window {
+                            let (x_ratio, y_ratio) = (
+                                available_size.x / texture_size.x,
+                                available_size.y / texture_size.y,
+                            );
+                            let ratio = if self.fill_window {
+                                x_ratio.max(y_ratio)
+                            } else {
+                                x_ratio.min(y_ratio)
+                            };
                             texture_size * ratio.max(0.1)
                         } else {
                             texture_size * self.scale.max(0.1)
                         };
 
-                        egui::ScrollArea::both()
-                            .auto_shrink([false, false])
-                            .show(ui, |ui| {
-                                // Center the image in the available space
-                                let (rect, _response) = ui.allocate_at_least(
-                                    Vec2::new(
-                                        display_size.x.max(ui.available_width()),
-                                        display_size.y.max(ui.available_height()),
-                                    ),
-                                    egui::Sense::hover(),
-                                );
+                        let mut scroll_area = egui::ScrollArea::both().auto_shrink([false, false]);
+                        if let Some(offset) = self.pending_scroll.take() {
+                            scroll_area = scroll_area.scroll_offset(offset);
+                        }
+                        let scroll_output = scroll_area.show(ui, |ui| {
+                            // The whole view, or more when the screen is larger
+                            let (rect, _response) = ui.allocate_at_least(
+                                Vec2::new(
+                                    display_size.x.max(ui.available_width()),
+                                    display_size.y.max(ui.available_height()),
+                                ),
+                                egui::Sense::hover(),
+                            );
 
-                                let image_rect = egui::Rect::from_min_size(rect.min, display_size);
+                            let image_rect = if self.config.center_screen {
+                                egui::Rect::from_center_size(rect.center(), display_size)
+                            } else {
+                                egui::Rect::from_min_size(rect.min, display_size)
+                            };
+
+                            let (multi_touch, pointer_down) =
+                                ui.input(|i| (i.multi_touch().is_some(), i.pointer.any_down()));
+                            self.track_touch_gesture(multi_touch, pointer_down);
+                            // We need a response specifically for the image area for input
+                            let image_response = ui.interact(
+                                image_rect,
+                                ui.id().with("vnc_img"),
+                                egui::Sense::click_and_drag(),
+                            );
+                            self.handle_input(ui, &image_response);
+                            if self.pan_mode {
+                                self.pan_view(ui, &image_response);
+                            }
+                            if self.pointer_grabbed && image_response.hovered() {
+                                ui.ctx().set_cursor_icon(egui::CursorIcon::None);
+                            }
 
-                                // We need a response specifically for the image area for input
-                                let image_response = ui.interact(
+                            if let Some(ref texture) = self.screen_texture {
+                                let mut mesh = egui::Mesh::with_texture(texture.id());
+                                let screen = Vec2::new(
+                                    self.screen_size.0.max(1) as f32,
+                                    self.screen_size.1.max(1) as f32,
+                                );
+                                let uv_min = egui::pos2(
+                                    view.left as f32 / screen.x,
+                                    view.top as f32 / screen.y,
+                                );
+                                mesh.add_rect_with_uv(
                                     image_rect,
-                                    ui.id().with("vnc_img"),
-                                    egui::Sense::click_and_drag(),
+                                    egui::Rect::from_min_size(uv_min, texture_size / screen),
+                                    Color32::WHITE,
                                 );
-                                self.handle_input(ui, &image_response);
-
-                                if let Some(ref texture) = self.screen_texture {
-                                    let mut mesh = egui::Mesh::with_texture(texture.id());
-                                    mesh.add_rect_with_uv(
+                                ui.painter().add(egui::Shape::mesh(mesh));
+                                if self.config.grid.enabled {
+                                    paint_grid(
+                                        ui.painter(),
                                         image_rect,
-                                        egui::Rect::from_min_max(
-                                            egui::pos2(0.0, 0.0),
-                                            egui::pos2(1.0, 1.0),
-                                        ),
-                                        Color32::WHITE,
-                                    );
-                                    ui.painter().add(egui::Shape::mesh(mesh));
-                                } else {
-                                    ui.painter().text(
-                                        rect.center(),
-                                        egui::Align2::CENTER_CENTER,
-                                        "Waiting for first frame...",
-                                        egui::FontId::proportional(20.0),
-                                        ui.visuals().text_color(),
+                                        (view.width, view.height),
+                                        self.config.grid.spacing,
                                     );
                                 }
-                            });
+                            } else {
+                                ui.painter().text(
+                                    rect.center(),
+                                    egui::Align2::CENTER_CENTER,
+                                    "Waiting for first frame...",
+                                    egui::FontId::proportional(20.0),
+                                    ui.visuals().text_color(),
+                                );
+                            }
+                        });
+                        self.scroll_offset = scroll_output.state.offset;
+                        self.viewport_size = scroll_output.inner_rect.size();
+                        self.display_scale = display_size.x / texture_size.x.max(1.0);
+                        // Ctrl+wheel and pinch zoom about the pointer, and two fingers
+                        // also pan. Pinches are left alone when touch controls the remote.
+                        let (zoom, touch, pointer) = ui.input(|i| {
+                            let touch = i.multi_touch();
+                            let zoom = match touch {
+                                Some(_) if self.config.touch_controls_remote => 1.0,
+                                Some(ref touch) => touch.zoom_delta,
+                                None => i.zoom_delta(),
+                            };
+                            (zoom, touch, i.pointer.hover_pos())
+                        });
+                        if zoom != 1.0 {
+                            if let Some(pos) =
+                                pointer.filter(|pos| scroll_output.inner_rect.contains(*pos))
+                            {
+                                self.zoom_at(zoom, pos - scroll_output.inner_rect.min);
+                            }
+                        }
+                        if let Some(touch) = touch.filter(|_| self.touch_gesture) {
+                            let offset = self.pending_scroll.unwrap_or(self.scroll_offset);
+                            self.pending_scroll =
+                                Some((offset - touch.translation_delta).max(Vec2::ZERO));
+                        }
+                        self.update_visible_area(scroll_output.inner_rect.size(), display_size);
+                        self.show_minimap(ui, scroll_output.inner_rect, display_size);
                     });
             }
         }
@@ -637,11 +2080,21 @@ impl eframe::App for VncApp {
                                 egui::ComboBox::from_id_source("encoding_pref")
                                     .selected_text(&self.preferred_encoding)
                                     .show_ui(ui, |ui| {
+                                        ui.selectable_value(
+                                            &mut self.preferred_encoding,
+                                            "Tight".to_string(),
+                                            "Tight",
+                                        );
                                         ui.selectable_value(
                                             &mut self.preferred_encoding,
                                             "ZRLE".to_string(),
                                             "ZRLE",
                                         );
+                                        ui.selectable_value(
+                                            &mut self.preferred_encoding,
+                                            "TRLE".to_string(),
+                                            "TRLE",
+                                        );
                                         ui.selectable_value(
                                             &mut self.preferred_encoding,
                                             "Hextile".to_string(),
@@ -658,53 +2111,390 @@ impl eframe::App for VncApp {
 
                             ui.add_space(10.0);
                             ui.label(format!("Compression level: {}", self.compression_level));
-                            ui.add(egui::Slider::new(&mut self.compression_level, 1..=9));
+                            ui.add(egui::Slider::new(&mut self.compression_level, 0..=9))
+                                .on_hover_text(
+                                    "Higher saves bandwidth at the cost of server CPU. \
+                                     Sent to the server on connect and on Apply.",
+                                );
 
                             ui.add_space(5.0);
                             ui.label(format!("JPEG quality level: {}", self.quality_level));
-                            ui.add(egui::Slider::new(&mut self.quality_level, 1..=9));
+                            ui.add(egui::Slider::new(&mut self.quality_level, 0..=9))
+                                .on_hover_text(
+                                    "Lower saves bandwidth with Tight, at the cost of \
+                                     JPEG artifacts. Sent to the server on connect and on Apply.",
+                                );
 
                             ui.add_space(10.0);
                             ui.checkbox(&mut self.allow_copyrect, "Allow CopyRect encoding");
+                            ui.checkbox(
+                                &mut self.benchmark_encodings,
+                                "Benchmark encodings on connect",
+                            )
+                            .on_hover_text(BENCHMARK_HINT);
+                        });
+
+                        ui.add_space(10.0);
+                        ui.group(|ui| {
+                            ui.label(egui::RichText::new("Connection").strong());
+                            ui.separator();
+                            ui.checkbox(
+                                &mut self.auto_reconnect,
+                                "Reconnect when the connection drops",
+                            );
+                            ui.add_enabled(
+                                self.auto_reconnect,
+                                egui::Slider::new(&mut self.reconnect_max_attempts, 0..=20)
+                                    .text("Max attempts"),
+                            )
+                            .on_hover_text("0 keeps trying forever");
+                            ui.add(
+                                egui::Slider::new(&mut self.connect_timeout_secs, 1..=60)
+                                    .text("Connect timeout (s)"),
+                            );
+                            self.timeout_sliders(ui);
+                            ui.checkbox(
+                                &mut self.config.confirm_disconnect,
+                                "Ask before disconnecting",
+                            );
+                            self.bind_address_field(ui);
+                            self.repeater_fields(ui);
+                            self.encryption_options(ui);
                         });
 
                         ui.add_space(10.0);
                         ui.group(|ui| {
                             ui.label(egui::RichText::new("Restrictions").strong());
                             ui.separator();
-                            ui.checkbox(&mut self.view_only, "View only (inputs ignored)");
+                            let mut view_only = self.view_only;
+                            if ui
+                                .checkbox(&mut view_only, "View only (inputs ignored)")
+                                .changed()
+                            {
+                                self.set_view_only(view_only);
+                            }
                             ui.checkbox(&mut self.disable_clipboard, "Disable clipboard transfer");
+                            ui.checkbox(
+                                &mut self.send_cad_on_connect,
+                                "Send Ctrl-Alt-Del on connect",
+                            )
+                            .on_hover_text(
+                                "For Windows login screens. Sent once the first frame arrives.",
+                            );
+                        });
+
+                        ui.add_space(10.0);
+                        ui.group(|ui| {
+                            ui.label(egui::RichText::new("Keyboard").strong());
+                            ui.separator();
+                            ui.checkbox(
+                                &mut self.config.quick_keys.enabled,
+                                "Ctrl+Alt+number sends F-keys",
+                            )
+                            .on_hover_text(
+                                "Ctrl+Alt+1..9 send F1..F9 and Ctrl+Alt+0 sends F10. \
+                                 The mapping can be changed in the config file.",
+                            );
+                            ui.checkbox(&mut self.config.altgr, "Ctrl+Alt is AltGr")
+                                .on_hover_text(
+                                    "Send Ctrl+Alt as AltGr (ISO_Level3_Shift), for keyboard \
+                                     layouts that type symbols with AltGr.",
+                                );
+                            if ui.button("Special keys...").clicked() {
+                                self.show_keysym_tool = true;
+                            }
+                        });
+
+                        ui.add_space(10.0);
+                        ui.group(|ui| {
+                            ui.label(egui::RichText::new("Mouse").strong());
+                            ui.separator();
+                            if ui
+                                .checkbox(&mut self.capture_mouse, "Capture mouse on click")
+                                .on_hover_text(
+                                    "Clicking the screen hides the local cursor. It is \
+                                     released when the window is minimized or loses focus.",
+                                )
+                                .changed()
+                                && !self.capture_mouse
+                            {
+                                self.pointer_grabbed = false;
+                            }
+                            let mut unlimited = self.pointer_max_rate == 0;
+                            if ui
+                                .checkbox(&mut unlimited, "Unlimited pointer motion")
+                                .on_hover_text(
+                                    "Send every movement. A touchpad can send hundreds \
+                                     a second, which a slow link can't keep up with.",
+                                )
+                                .changed()
+                            {
+                                self.pointer_max_rate = if unlimited { 0 } else { 60 };
+                            }
+                            if !unlimited {
+                                ui.add(
+                                    egui::Slider::new(&mut self.pointer_max_rate, 10..=240)
+                                        .text("Max pointer moves/s"),
+                                )
+                                .on_hover_text("Clicks and releases are always sent at once.");
+                            }
+                            ui.checkbox(&mut self.relative_pointer, "Relative pointer")
+                                .on_hover_text(
+                                    "Clicking the screen grabs the pointer, and mouse motion \
+                                     moves the remote pointer instead of placing it. For \
+                                     games that read relative motion.",
+                                );
+                            self.button_map_options(ui);
+                            ui.label("Two-finger touch:");
+                            ui.horizontal(|ui| {
+                                ui.radio_value(
+                                    &mut self.config.touch_controls_remote,
+                                    false,
+                                    "Navigates",
+                                )
+                                .on_hover_text("Pinch to zoom and drag with two fingers to pan");
+                                ui.radio_value(
+                                    &mut self.config.touch_controls_remote,
+                                    true,
+                                    "Controls remote",
+                                )
+                                .on_hover_text(
+                                    "Only the first finger counts, as the remote pointer",
+                                );
+                            });
                         });
 
                         ui.add_space(10.0);
                         ui.group(|ui| {
                             ui.label(egui::RichText::new("Display").strong());
                             ui.separator();
-                            ui.checkbox(&mut self.zoom_fit, "Scale to window size");
+                            self.fit_to_window_checkbox(ui);
+                            let mut scale = self.scale;
+                            if ui
+                                .add(
+                                    egui::Slider::new(&mut scale, MIN_SCALE..=MAX_SCALE)
+                                        .logarithmic(true)
+                                        .text("Manual Scale"),
+                                )
+                                .changed()
+                            {
+                                self.set_scale(scale);
+                            }
+                            ui.checkbox(
+                                &mut self.config.center_screen,
+                                "Center the screen in the window",
+                            );
+                            ui.horizontal(|ui| {
+                                let mut custom = self.config.background.is_some();
+                                if ui.checkbox(&mut custom, "Background colour").changed() {
+                                    self.config.background = custom.then_some([0, 0, 0]);
+                                }
+                                if let Some(ref mut colour) = self.config.background {
+                                    ui.color_edit_button_srgb(colour);
+                                }
+                            })
+                            .response
+                            .on_hover_text("Shown around a screen smaller than the window");
+                            let mut filter = self.display_filter;
+                            egui::ComboBox::from_label("Display filter")
+                                .selected_text(filter_label(filter))
+                                .show_ui(ui, |ui| {
+                                    for option in [
+                                        DisplayFilter::None,
+                                        DisplayFilter::Invert,
+                                        DisplayFilter::Grayscale,
+                                        DisplayFilter::Night,
+                                    ] {
+                                        ui.selectable_value(
+                                            &mut filter,
+                                            option,
+                                            filter_label(option),
+                                        );
+                                    }
+                                });
+                            if filter != self.display_filter {
+                                self.set_display_filter(filter);
+                            }
+                            let mut smooth = self.smooth_scaling;
+                            if ui
+                                .checkbox(&mut smooth, "Smooth scaling")
+                                .on_hover_text(
+                                    "Filters the scaled screen for easier reading; off shows \
+                                     sharp pixels",
+                                )
+                                .changed()
+                            {
+                                self.set_smooth_scaling(smooth);
+                            }
+                            let mut minimap = self.config.minimap;
+                            if ui.checkbox(&mut minimap, "Show minimap").changed() {
+                                self.set_minimap(minimap);
+                            }
+                            ui.add_enabled(
+                                self.state != AppState::Viewing,
+                                egui::Checkbox::new(
+                                    &mut self.start_fullscreen,
+                                    "Start full screen",
+                                ),
+                            )
+                            .on_hover_text(
+                                "The window size and full screen state are saved per host \
+                                 on disconnect",
+                            );
+                            let can_resize = self
+                                .vnc_client
+                                .as_ref()
+                                .is_none_or(|vnc| vnc.can_resize_desktop());
+                            ui.add_enabled(
+                                can_resize,
+                                egui::Checkbox::new(
+                                    &mut self.resize_remote,
+                                    "Resize remote to fit window",
+                                ),
+                            )
+                            .on_hover_text("Needs a server that supports ExtendedDesktopSize")
+                            .on_disabled_hover_text("The server can't be resized");
+                            ui.checkbox(&mut self.config.grid.enabled, "Show pixel grid");
+                            ui.add_enabled(
+                                self.config.grid.enabled,
+                                egui::Slider::new(&mut self.config.grid.spacing, 2..=500)
+                                    .text("Grid spacing (px)"),
+                            );
+                            ui.horizontal(|ui| {
+                                ui.label("Bell notifications:");
+                                for (mode, label) in [
+                                    (BellMode::Audible, "Audible"),
+                                    (BellMode::Visual, "Visual"),
+                                    (BellMode::Off, "Off"),
+                                ] {
+                                    ui.radio_value(&mut self.config.bell, mode, label);
+                                }
+                            })
+                            .response
+                            .on_hover_text(
+                                "Audible uses the system beep on Windows and flashes \
+                                 the window elsewhere",
+                            );
+                            ui.add(
+                                egui::Slider::new(&mut self.config.recording.fps, 1..=30)
+                                    .text("Recording FPS"),
+                            );
+                            ui.horizontal(|ui| {
+                                ui.label("Screenshot folder:");
+                                ui.add(
+                                    egui::TextEdit::singleline(&mut self.config.screenshot_dir)
+                                        .hint_text("Working directory"),
+                                );
+                            });
+                        });
+
+                        ui.add_space(10.0);
+                        ui.group(|ui| {
+                            ui.label(egui::RichText::new("Pixel Format").strong());
+                            ui.separator();
+                            let before = self.format_override;
+                            let format = &mut self.format_override;
+                            ui.checkbox(&mut format.swap_red_blue, "Swap red and blue");
+                            ui.collapsing("Advanced", |ui| {
+                                ui.checkbox(&mut format.enabled, "Override server pixel format");
+                                ui.add_enabled_ui(format.enabled, |ui| {
+                                    egui::Grid::new("format_grid")
+                                        .num_columns(2)
+                                        .show(ui, |ui| {
+                                            for (label, shift) in [
+                                                ("Red shift:", &mut format.red_shift),
+                                                ("Green shift:", &mut format.green_shift),
+                                                ("Blue shift:", &mut format.blue_shift),
+                                            ] {
+                                                ui.label(label);
+                                                ui.add(
+                                                    egui::DragValue::new(shift).clamp_range(0..=31),
+                                                );
+                                                ui.end_row();
+                                            }
+                                            ui.label("Byte order:");
+                                            egui::ComboBox::from_id_source("format_byte_order")
+                                                .selected_text(if format.big_endian {
+                                                    "Big endian"
+                                                } else {
+                                                    "Little endian"
+                                                })
+                                                .show_ui(ui, |ui| {
+                                                    ui.selectable_value(
+                                                        &mut format.big_endian,
+                                                        false,
+                                                        "Little endian",
+                                                    );
+                                                    ui.selectable_value(
+                                                        &mut format.big_endian,
+                                                        true,
+                                                        "Big endian",
+                                                    );
+                                                });
+                                            ui.end_row();
+                                        });
+                                });
+                            });
+                            // Redraw everything in the corrected colours.
+                            if self.format_override != before {
+                                self.request_full_update();
+                            }
+                            self.colour_depth_options(ui);
+                        });
+
+                        ui.add_space(10.0);
+                        ui.group(|ui| {
+                            ui.label(egui::RichText::new("Frame Rate").strong());
+                            ui.separator();
+                            let mut unlimited = self.vnc_max_fps == 0;
+                            if ui
+                                .checkbox(&mut unlimited, "Unlimited frame rate")
+                                .on_hover_text(
+                                    "Lowest latency, but polls the server as fast as it \
+                                     answers. Best on a LAN.",
+                                )
+                                .changed()
+                            {
+                                self.vnc_max_fps = if unlimited { 0 } else { 30 };
+                            }
+                            if !unlimited {
+                                ui.add(
+                                    egui::Slider::new(&mut self.vnc_max_fps, 1..=120)
+                                        .text("Max frame requests/s"),
+                                )
+                                .on_hover_text(
+                                    "Fewer frames save bandwidth and CPU at the cost \
+                                     of a little latency.",
+                                );
+                            }
+                            ui.checkbox(&mut self.update_visible_only, "Update only visible area")
+                                .on_hover_text(
+                                    "When zoomed in, parts of the screen scrolled out of \
+                                     view aren't refreshed until they come back.",
+                                );
                             ui.add(
-                                egui::Slider::new(&mut self.scale, 0.1..=4.0).text("Manual Scale"),
+                                egui::Slider::new(&mut self.config.ui_max_fps, 0..=120)
+                                    .text("Max UI repaints/s"),
+                            )
+                            .on_hover_text(
+                                "0 = unlimited. Frames are requested on their own \
+                                 timer, so this only limits how often they are shown.",
+                            );
+                            ui.checkbox(
+                                &mut self.config.freeze_unfocused,
+                                "Pause updates while in the background",
+                            )
+                            .on_hover_text(
+                                "Keeps the last frame while the window is unfocused or \
+                                 minimized, and refreshes the whole screen on return.",
                             );
                         });
 
                         ui.add_space(20.0);
                         ui.horizontal(|ui| {
                             if ui.button("Apply").clicked() {
-                                // Apply encoding settings if connected
-                                if let Some(ref mut vnc) = self.vnc_client {
-                                    let mut encs = Vec::new();
-                                    match self.preferred_encoding.as_str() {
-                                        "ZRLE" => encs.push(vnc::Encoding::Zrle),
-                                        "Hextile" => encs.push(vnc::Encoding::Hextile),
-                                        _ => (),
-                                    }
-                                    if self.allow_copyrect {
-                                        encs.push(vnc::Encoding::CopyRect);
-                                    }
-                                    encs.push(vnc::Encoding::Raw);
-                                    encs.push(vnc::Encoding::Cursor);
-                                    encs.push(vnc::Encoding::DesktopSize);
-                                    let _ = vnc.set_encodings(&encs);
-                                }
+                                self.apply_encodings();
+                                self.apply_colour_depth();
                             }
                             if ui.button("Close").clicked() {
                                 self.show_options = false;
@@ -721,8 +2511,33 @@ impl eframe::App for VncApp {
                 .resizable(false)
                 .fixed_size([300.0, 400.0])
                 .show(ctx, |ui| {
-                    ui.checkbox(&mut self.view_only, "View-only mode");
-                    ui.checkbox(&mut self.zoom_fit, "Scale to window size");
+                    let mut view_only = self.view_only;
+                    if ui.checkbox(&mut view_only, "View-only mode").changed() {
+                        self.set_view_only(view_only);
+                    }
+                    self.fit_to_window_checkbox(ui);
+                    ui.checkbox(
+                        &mut self.config.auto_connect,
+                        "Connect to last host on startup",
+                    );
+                    ui.checkbox(
+                        &mut self.benchmark_encodings,
+                        "Benchmark encodings on connect",
+                    )
+                    .on_hover_text(BENCHMARK_HINT);
+                    self.colour_depth_options(ui);
+                    ui.checkbox(
+                        &mut self.auto_reconnect,
+                        "Reconnect when the connection drops",
+                    );
+                    ui.add(
+                        egui::Slider::new(&mut self.connect_timeout_secs, 1..=60)
+                            .text("Connect timeout (s)"),
+                    );
+                    self.timeout_sliders(ui);
+                    self.bind_address_field(ui);
+                    self.repeater_fields(ui);
+                    self.encryption_options(ui);
                     if ui.button("Close").clicked() {
                         self.show_options = false;
                     }
@@ -731,18 +2546,195 @@ impl eframe::App for VncApp {
 
         if self.show_info {
             egui::Window::new("Connection Info").show(ctx, |ui| {
-                ui.label(format!("Host: {}", self.host));
-                ui.label(format!(
-                    "Resolution: {}x{}",
-                    self.screen_size.0, self.screen_size.1
+                let mut fields = vec![
+                    ("Host", self.host.clone()),
+                    (
+                        "Resolution",
+                        format!("{}x{}", self.screen_size.0, self.screen_size.1),
+                    ),
+                ];
+                if let Some(ref vnc) = self.vnc_client {
+                    fields.push(("Name", vnc.name().to_string()));
+                    fields.push(("Protocol", version_label(vnc.version()).to_string()));
+                    fields.push(("Security", security_label(vnc.security_type())));
+                    let session = if self.shared { "Shared" } else { "Taken over" };
+                    fields.push(("Session", session.to_string()));
+                    fields.push(("Pixel format", describe_format(&vnc.format())));
+                }
+                if let Some(started) = self.connect_started {
+                    let time_to_first_frame = match self.first_frame_at {
+                        Some(first_frame) => {
+                            format!("{} ms", (first_frame - started).as_millis())
+                        }
+                        None => "waiting...".to_string(),
+                    };
+                    fields.push(("Time to first frame", time_to_first_frame));
+                }
+                if let Some(ref vnc) = self.vnc_client {
+                    fields.push(("FPS", format!("{:.1}", self.frame_rate)));
+                    if let Some(encoding) = self.frame_encoding {
+                        fields.push(("Encoding", encoding_name(encoding)));
+                    }
+                    fields.push(("KB/s in", format!("{:.1}", self.bytes_per_second / 1024.0)));
+                    fields.push(("Frames since connect", vnc.stats().updates.to_string()));
+                    // Keep the rates live while the window is open.
+                    ctx.request_repaint_after(std::time::Duration::from_secs(1));
+                }
+                fields.push((
+                    "Framebuffer memory",
+                    format_mb(self.pixels.capacity() * std::mem::size_of::<Color32>()),
                 ));
+                if let Some(ref texture) = self.screen_texture {
+                    let [width, height] = texture.size();
+                    // RGBA8 on the GPU, plus the copy handed over on each upload.
+                    fields.push(("Texture memory", format_mb(width * height * 4)));
+                }
                 if let Some(ref vnc) = self.vnc_client {
-                    ui.label(format!("Name: {}", vnc.name()));
+                    let stats = vnc.stats();
+                    fields.push((
+                        "Raw buffer (peak)",
+                        format_mb(stats.peak_raw_buffer as usize),
+                    ));
+                    fields.push((
+                        "ZRLE buffer (peak)",
+                        format_mb(stats.peak_zrle_buffer as usize),
+                    ));
+                }
+
+                if let Some(length) = self.clipboard_received {
+                    fields.push(("Last clipboard received", format!("{} chars", length)));
                 }
-                if ui.button("Close").clicked() {
-                    self.show_info = false;
+
+                egui::Grid::new("info_grid").num_columns(3).show(ui, |ui| {
+                    for (label, value) in fields {
+                        ui.label(format!("{}:", label));
+                        ui.label(&value);
+                        if ui.small_button("📋").on_hover_text("Copy").clicked() {
+                            ui.output_mut(|o| o.copied_text = value);
+                        }
+                        ui.end_row();
+                    }
+                });
+                if let Some(ref benchmark) = self.benchmark {
+                    ui.separator();
+                    if benchmark.is_running() {
+                        ui.label("Benchmarking encodings...");
+                    } else {
+                        ui.label(egui::RichText::new("Encoding benchmark").strong());
+                    }
+                    egui::Grid::new("benchmark_grid")
+                        .num_columns(3)
+                        .show(ui, |ui| {
+                            for result in &benchmark.results {
+                                ui.label(format!("{}:", result.name));
+                                ui.label(format!(
+                                    "{:.1} KB/update",
+                                    result.bytes_per_update() / 1024.0
+                                ));
+                                ui.label(format!(
+                                    "{:.1} ms decode",
+                                    result.decode_time_per_update().as_secs_f64() * 1000.0
+                                ));
+                                ui.end_row();
+                            }
+                        });
+                    if !benchmark.is_running() {
+                        ui.label(format!("Using {}", self.preferred_encoding));
+                    }
                 }
+                ui.horizontal(|ui| {
+                    if ui.button("Log").clicked() {
+                        self.show_log = !self.show_log;
+                    }
+                    if ui.button("Close").clicked() {
+                        self.show_info = false;
+                    }
+                });
             });
         }
+
+        if self.show_help {
+            self.show_help_window(ctx);
+        }
+
+        self.show_import_conflicts(ctx);
+        self.show_password_prompt(ctx);
+
+        if self.show_keysym_tool && self.state == AppState::Viewing {
+            self.show_keysym_window(ctx);
+        }
+
+        if self.show_virtual_keyboard && self.state == AppState::Viewing {
+            self.show_virtual_keyboard(ctx);
+        }
+
+        if self.confirm_disconnect && self.state == AppState::Viewing {
+            self.show_disconnect_window(ctx);
+        }
+
+        if self.confirm_quit {
+            self.show_quit_window(ctx, frame);
+        }
+
+        if self.show_log {
+            let mut open = true;
+            egui::Window::new("Log")
+                .open(&mut open)
+                .default_size([500.0, 300.0])
+                .show(ctx, |ui| {
+                    let entries = log_buffer::entries();
+                    ui.horizontal(|ui| {
+                        if ui.button("Copy all").clicked() {
+                            ui.output_mut(|o| o.copied_text = entries.join("\n"));
+                        }
+                        if ui.button("Clear").clicked() {
+                            log_buffer::clear();
+                        }
+                        if ui
+                            .checkbox(&mut self.config.log_to_file, "Write to file")
+                            .on_hover_text(format!(
+                                "Append the log to {} from the next start, for \
+                                 looking into problems afterwards.",
+                                config::log_path().display()
+                            ))
+                            .changed()
+                        {
+                            self.write_config();
+                        }
+                    });
+                    ui.separator();
+                    egui::ScrollArea::vertical()
+                        .stick_to_bottom(true)
+                        .auto_shrink([false, false])
+                        .show(ui, |ui| {
+                            for entry in &entries {
+                                ui.label(egui::RichText::new(entry).monospace());
+                            }
+                        });
+                });
+            self.show_log = open;
+        }
+
+        self.paint_bell_flash(ctx);
+    }
+
+    /// Closing the window mid-session only goes ahead once confirmed.
+    fn on_close_event(&mut self) -> bool {
+        if self.state != AppState::Viewing {
+            return true;
+        }
+        self.confirm_quit = true;
+        false
     }
 }
+
+/// Plays the system beep, where there is one to play.
+#[cfg(windows)]
+fn system_beep() -> bool {
+    unsafe { winapi::um::winuser::MessageBeep(winapi::um::winuser::MB_OK) != 0 }
+}
+
+#[cfg(not(windows))]
+fn system_beep() -> bool {
+    false
+}
@@ -0,0 +1,264 @@
+use crate::app::vnc_handler::send_key;
+use crate::app::VncApp;
+use crate::keys;
+use eframe::egui::{self, Key, Vec2};
+
+/* An on-screen keyboard, for tablets without a physical one. It is laid out as
+US QWERTY and sends the keysym of each key's character, so the remote types
+what the key shows whatever its own layout. Modifiers latch: a tap holds one
+for the next key, as on phone keyboards, and a second tap lets it go. */
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum VirtualKey {
+    /// A key egui knows, sent as `keys::map_key` maps it.
+    Mapped(Key, &'static str),
+    /// A character, and the one it types with Shift.
+    Char(char, char),
+    /// A modifier keysym, held for the next key.
+    Modifier(u32, &'static str),
+}
+
+use VirtualKey::{Char, Mapped, Modifier};
+
+/// Width and height of an ordinary key, in points.
+const KEY_SIZE: f32 = 32.0;
+const KEY_SPACING: f32 = 4.0;
+
+/// The rows of keys, each with its width in ordinary keys.
+const ROWS: &[&[(VirtualKey, f32)]] = &[
+    &[
+        (Mapped(Key::Escape, "Esc"), 1.5),
+        (Mapped(Key::F1, "F1"), 1.0),
+        (Mapped(Key::F2, "F2"), 1.0),
+        (Mapped(Key::F3, "F3"), 1.0),
+        (Mapped(Key::F4, "F4"), 1.0),
+        (Mapped(Key::F5, "F5"), 1.0),
+        (Mapped(Key::F6, "F6"), 1.0),
+        (Mapped(Key::F7, "F7"), 1.0),
+        (Mapped(Key::F8, "F8"), 1.0),
+        (Mapped(Key::F9, "F9"), 1.0),
+        (Mapped(Key::F10, "F10"), 1.0),
+        (Mapped(Key::F11, "F11"), 1.0),
+        (Mapped(Key::F12, "F12"), 1.0),
+        (Mapped(Key::Delete, "Del"), 1.5),
+    ],
+    &[
+        (Char('`', '~'), 1.0),
+        (Mapped(Key::Num1, "1"), 1.0),
+        (Mapped(Key::Num2, "2"), 1.0),
+        (Mapped(Key::Num3, "3"), 1.0),
+        (Mapped(Key::Num4, "4"), 1.0),
+        (Mapped(Key::Num5, "5"), 1.0),
+        (Mapped(Key::Num6, "6"), 1.0),
+        (Mapped(Key::Num7, "7"), 1.0),
+        (Mapped(Key::Num8, "8"), 1.0),
+        (Mapped(Key::Num9, "9"), 1.0),
+        (Mapped(Key::Num0, "0"), 1.0),
+        (Char('-', '_'), 1.0),
+        (Char('=', '+'), 1.0),
+        (Mapped(Key::Backspace, "Backspace"), 2.0),
+    ],
+    &[
+        (Mapped(Key::Tab, "Tab"), 1.5),
+        (Mapped(Key::Q, "q"), 1.0),
+        (Mapped(Key::W, "w"), 1.0),
+        (Mapped(Key::E, "e"), 1.0),
+        (Mapped(Key::R, "r"), 1.0),
+        (Mapped(Key::T, "t"), 1.0),
+        (Mapped(Key::Y, "y"), 1.0),
+        (Mapped(Key::U, "u"), 1.0),
+        (Mapped(Key::I, "i"), 1.0),
+        (Mapped(Key::O, "o"), 1.0),
+        (Mapped(Key::P, "p"), 1.0),
+        (Char('[', '{'), 1.0),
+        (Char(']', '}'), 1.0),
+        (Char('\\', '|'), 1.5),
+    ],
+    &[
+        (Modifier(keys::CONTROL_L, "Ctrl"), 2.0),
+        (Mapped(Key::A, "a"), 1.0),
+        (Mapped(Key::S, "s"), 1.0),
+        (Mapped(Key::D, "d"), 1.0),
+        (Mapped(Key::F, "f"), 1.0),
+        (Mapped(Key::G, "g"), 1.0),
+        (Mapped(Key::H, "h"), 1.0),
+        (Mapped(Key::J, "j"), 1.0),
+        (Mapped(Key::K, "k"), 1.0),
+        (Mapped(Key::L, "l"), 1.0),
+        (Char(';', ':'), 1.0),
+        (Char('\'', '"'), 1.0),
+        (Mapped(Key::Enter, "Enter"), 2.0),
+    ],
+    &[
+        (Modifier(keys::SHIFT_L, "Shift"), 2.5),
+        (Mapped(Key::Z, "z"), 1.0),
+        (Mapped(Key::X, "x"), 1.0),
+        (Mapped(Key::C, "c"), 1.0),
+        (Mapped(Key::V, "v"), 1.0),
+        (Mapped(Key::B, "b"), 1.0),
+        (Mapped(Key::N, "n"), 1.0),
+        (Mapped(Key::M, "m"), 1.0),
+        (Char(',', '<'), 1.0),
+        (Char('.', '>'), 1.0),
+        (Char('/', '?'), 1.0),
+        (Mapped(Key::ArrowUp, "↑"), 1.0),
+        (Mapped(Key::Home, "Home"), 1.5),
+    ],
+    &[
+        (Modifier(keys::SUPER_L, "Win"), 1.5),
+        (Modifier(keys::ALT_L, "Alt"), 1.5),
+        (Mapped(Key::Space, " "), 6.0),
+        (Modifier(keys::ALT_L, "Alt"), 1.5),
+        (Mapped(Key::ArrowLeft, "←"), 1.0),
+        (Mapped(Key::ArrowDown, "↓"), 1.0),
+        (Mapped(Key::ArrowRight, "→"), 1.0),
+        (Mapped(Key::End, "End"), 1.5),
+    ],
+];
+
+/// Characters on the digit keys with Shift, from 0 to 9.
+const SHIFTED_DIGITS: &[u8; 10] = b")!@#$%^&*(";
+
+impl VirtualKey {
+    /// What the key sends, with or without Shift latched.
+    fn keysym(self, shift: bool) -> u32 {
+        match self {
+            Mapped(key, _) => {
+                let keysym = keys::map_key(key).unwrap_or(0);
+                match keysym {
+                    0x61..=0x7A if shift => keysym - 0x20,
+                    0x30..=0x39 if shift => SHIFTED_DIGITS[(keysym - 0x30) as usize] as u32,
+                    _ => keysym,
+                }
+            }
+            // Latin-1 keysyms are the characters' code points.
+            Char(c, shifted) => (if shift { shifted } else { c }) as u32,
+            Modifier(keysym, _) => keysym,
+        }
+    }
+
+    /// Characters show what they type, other keys their name.
+    fn label(self, shift: bool) -> String {
+        let keysym = self.keysym(shift);
+        match self {
+            Mapped(_, label) | Modifier(_, label) if keysym >= 0x100 => label.to_string(),
+            _ => char::from(keysym as u8).to_string(),
+        }
+    }
+}
+
+/// Key events for tapping `keysym` with the `latched` modifiers: they go down
+/// in order, the key goes down and up, and they come up in reverse.
+fn tap_events(keysym: u32, latched: &[u32]) -> Vec<(bool, u32)> {
+    let down = latched.iter().map(|&modifier| (true, modifier));
+    let up = latched.iter().rev().map(|&modifier| (false, modifier));
+    down.chain([(true, keysym), (false, keysym)])
+        .chain(up)
+        .collect()
+}
+
+impl VncApp {
+    pub fn show_virtual_keyboard(&mut self, ctx: &egui::Context) {
+        let mut open = self.show_virtual_keyboard;
+        egui::Window::new("Keyboard")
+            .open(&mut open)
+            .collapsible(false)
+            .resizable(false)
+            .show(ctx, |ui| {
+                ui.add_enabled_ui(!self.view_only, |ui| {
+                    ui.spacing_mut().item_spacing = Vec2::splat(KEY_SPACING);
+                    let shift = self.latched_keys.contains(&keys::SHIFT_L);
+                    let mut tapped = None;
+                    for row in ROWS {
+                        ui.horizontal(|ui| {
+                            for &(key, width) in *row {
+                                let size = Vec2::new(
+                                    KEY_SIZE * width + KEY_SPACING * (width - 1.0),
+                                    KEY_SIZE,
+                                );
+                                let mut button = egui::Button::new(key.label(shift)).min_size(size);
+                                if let Modifier(keysym, _) = key {
+                                    if self.latched_keys.contains(&keysym) {
+                                        button = button.fill(ui.visuals().selection.bg_fill);
+                                    }
+                                }
+                                if ui.add(button).clicked() {
+                                    tapped = Some(key);
+                                }
+                            }
+                        });
+                    }
+                    if let Some(key) = tapped {
+                        self.tap_virtual_key(key);
+                    }
+                });
+            });
+        self.show_virtual_keyboard = open;
+        if !open {
+            self.latched_keys.clear();
+        }
+    }
+
+    fn tap_virtual_key(&mut self, key: VirtualKey) {
+        if let Modifier(keysym, _) = key {
+            match self.latched_keys.iter().position(|&held| held == keysym) {
+                Some(i) => {
+                    self.latched_keys.remove(i);
+                }
+                None => self.latched_keys.push(keysym),
+            }
+            return;
+        }
+        let shift = self.latched_keys.contains(&keys::SHIFT_L);
+        let events = tap_events(key.keysym(shift), &self.latched_keys);
+        self.latched_keys.clear();
+        let Some(ref mut vnc) = self.vnc_client else {
+            return;
+        };
+        for (down, keysym) in events {
+            send_key(vnc, &mut self.pressed_keys, down, keysym);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_keysyms() {
+        let q = Mapped(Key::Q, "q");
+        assert_eq!((q.keysym(false), q.keysym(true)), (0x71, 0x51));
+        assert_eq!((q.label(false), q.label(true)), ("q".into(), "Q".into()));
+        let two = Mapped(Key::Num2, "2");
+        assert_eq!(two.label(true), "@");
+        assert_eq!(Char('/', '?').keysym(true), 0x3F);
+        let enter = Mapped(Key::Enter, "Enter");
+        assert_eq!(
+            (enter.keysym(true), enter.label(true)),
+            (0xFF0D, "Enter".into())
+        );
+        // Every key sends something.
+        for row in ROWS {
+            for &(key, _) in *row {
+                assert_ne!(key.keysym(false), 0, "{:?}", key);
+            }
+        }
+    }
+
+    #[test]
+    fn test_tap_with_latched_modifiers() {
+        assert_eq!(tap_events(0x61, &[]), [(true, 0x61), (false, 0x61)]);
+        assert_eq!(
+            tap_events(0x63, &[keys::CONTROL_L, keys::SHIFT_L]),
+            [
+                (true, keys::CONTROL_L),
+                (true, keys::SHIFT_L),
+                (true, 0x63),
+                (false, 0x63),
+                (false, keys::SHIFT_L),
+                (false, keys::CONTROL_L),
+            ]
+        );
+    }
+}
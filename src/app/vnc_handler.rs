@@ -1,58 +1,153 @@
-use crate::app::{AppState, VncApp};
+use crate::address;
+use crate::app::benchmark::{Benchmark, Step};
+use crate::app::pixels::{self, ColourTables};
+use crate::app::{AppState, PasswordPrompt, VncApp};
+use crate::config::DisplayFilter;
+use crate::keys;
+use crate::repeater;
+use crate::watchdog::{Check, Watchdog};
+use crate::wol;
 use eframe::egui::{self, Color32};
-use log::{error, info};
+use log::{error, info, warn};
+use socket2::{Domain, Protocol, Socket, Type};
+use std::collections::HashSet;
+use std::net::{IpAddr, SocketAddr, TcpStream, ToSocketAddrs};
+use std::sync::mpsc::{Sender, TryRecvError};
 use std::thread;
+use std::time::{Duration, Instant};
 use vnc::{Encoding, PixelFormat, Rect};
 
 impl VncApp {
     pub fn connect(&mut self) {
+        // Another thread would race the one already connecting.
+        if self.is_connecting() {
+            info!("Already connecting, ignoring another attempt");
+            return;
+        }
+        self.wake_connect_at = None;
+        if let Some((host, port)) = address::split_host_port(&self.host) {
+            self.host = host;
+            self.port = port.to_string();
+        }
+        let bind = match parse_bind_address(&self.bind_address) {
+            Ok(bind) => bind,
+            Err(e) => {
+                self.status_text = e.clone();
+                self.connect_error = Some(e);
+                return;
+            }
+        };
+
         let (tx, rx) = std::sync::mpsc::channel();
         self.vnc_rx = Some(rx);
+        let (password_tx, password_rx) = std::sync::mpsc::channel();
+        self.password_rx = Some(password_rx);
 
         let host = self.host.clone();
         let port_str = self.port.clone();
+        let username = self.username.clone();
         let password = self.password.clone();
         let shared = self.shared;
+        let use_vencrypt = self.use_vencrypt;
+        let verify_certificate = !self.trust_any_certificate;
+        let timeout = Duration::from_secs(self.connect_timeout_secs.max(1) as u64);
+        let repeater = match self.repeater.trim() {
+            "" => None,
+            repeater => Some(
+                address::split_host_port(repeater)
+                    .unwrap_or_else(|| (repeater.to_string(), repeater::DEFAULT_PORT)),
+            ),
+        };
+        let repeater_id = self.repeater_id.clone();
 
-        self.status_text = format!("Connecting to {}:{}...", host, port_str);
-
-        // Save config
-        self.config.last_host = self.host.clone();
-        self.config.hosts.insert(
-            self.host.clone(),
-            crate::config::HostConfig {
-                port: self.port.clone(),
-                password: self.password.clone(),
-                shared: self.shared,
-                view_only: self.view_only,
-                zoom_fit: self.zoom_fit,
-                scale: self.scale,
-                preferred_encoding: self.preferred_encoding.clone(),
-                compression_level: self.compression_level,
-                quality_level: self.quality_level,
-                allow_copyrect: self.allow_copyrect,
-                disable_clipboard: self.disable_clipboard,
-            },
-        );
+        let address = if host.contains(':') {
+            format!("[{}]:{}", host, port_str)
+        } else {
+            format!("{}:{}", host, port_str)
+        };
+        self.status_text = match repeater {
+            Some((ref repeater, _)) => format!("Connecting to {} via {}...", address, repeater),
+            None => format!("Connecting to {}...", address),
+        };
+        info!("{}", self.status_text);
+        self.connect_started = Some(Instant::now());
+        self.first_frame_at = None;
 
-        if let Ok(content) = serde_json::to_string_pretty(&self.config) {
-            let _ = std::fs::write("vnc_config.json", content);
-        }
+        self.save_config();
 
         thread::spawn(move || {
             let port: u16 = port_str.parse().unwrap_or(5900);
-            let addr = format!("{}:{}", host, port);
-            match std::net::TcpStream::connect(&addr) {
+            let stream = match repeater {
+                Some((ref repeater, repeater_port)) => connect_via_repeater(
+                    repeater,
+                    repeater_port,
+                    &repeater_id,
+                    &host,
+                    port,
+                    timeout,
+                    bind,
+                ),
+                None => connect_timeout(&host, port, timeout, bind).map_err(|e| {
+                    error!("Connection to {}:{} failed: {}", host, port, e);
+                    connect_error_message(&e, &host)
+                }),
+            };
+            match stream {
                 Ok(stream) => {
+                    let server_name = host.clone();
+                    let mut password_declined = false;
                     let client = vnc::Client::from_tcp_stream(stream, shared, |methods| {
+                        // With encryption required, nothing else will do.
+                        if use_vencrypt {
+                            return methods
+                                .iter()
+                                .any(|method| matches!(method, vnc::client::AuthMethod::VeNCrypt))
+                                .then_some(vnc::client::AuthChoice::VeNCrypt {
+                                    server_name,
+                                    verify_certificate,
+                                    username,
+                                    password,
+                                });
+                        }
+                        // A username means the server wants an account login.
+                        if !username.is_empty()
+                            && methods
+                                .iter()
+                                .any(|method| matches!(method, vnc::client::AuthMethod::MsLogon))
+                        {
+                            return Some(vnc::client::AuthChoice::MsLogon(username, password));
+                        }
                         for method in methods {
                             match method {
                                 vnc::client::AuthMethod::None => {
                                     return Some(vnc::client::AuthChoice::None);
                                 }
                                 vnc::client::AuthMethod::Password => {
-                                    let mut pw = [0u8; 8];
-                                    for (i, b) in password.as_bytes().iter().take(8).enumerate() {
+                                    // Nothing saved: ask, and wait for the answer.
+                                    let password = if password.is_empty() {
+                                        match ask_password(&password_tx) {
+                                            Some(password) => password,
+                                            None => {
+                                                password_declined = true;
+                                                return None;
+                                            }
+                                        }
+                                    } else {
+                                        password
+                                    };
+                                    if password.len() > VNC_PASSWORD_LENGTH {
+                                        warn!(
+                                            "Password truncated to {} characters for VNC authentication",
+                                            VNC_PASSWORD_LENGTH
+                                        );
+                                    }
+                                    let mut pw = [0u8; VNC_PASSWORD_LENGTH];
+                                    for (i, b) in password
+                                        .as_bytes()
+                                        .iter()
+                                        .take(VNC_PASSWORD_LENGTH)
+                                        .enumerate()
+                                    {
                                         pw[i] = *b;
                                     }
                                     return Some(vnc::client::AuthChoice::Password(pw));
@@ -67,39 +162,179 @@ impl VncApp {
                         Ok(vnc) => {
                             let _ = tx.send(Ok(vnc));
                         }
+                        Err(vnc::Error::AuthenticationUnavailable) if password_declined => {
+                            info!("No password given for {}:{}", host, port);
+                            let _ = tx.send(Err("No password given".to_string()));
+                        }
+                        Err(vnc::Error::Io(e))
+                            if repeater.is_some()
+                                && e.kind() == std::io::ErrorKind::UnexpectedEof =>
+                        {
+                            error!("Repeater closed the connection to {}:{}", host, port);
+                            let _ = tx.send(Err(REPEATER_CLOSED.to_string()));
+                        }
                         Err(e) => {
-                            let err_msg = format!("VNC Init Error: {}", e);
-                            error!("{}", err_msg);
-                            let _ = tx.send(Err(err_msg));
+                            error!("VNC handshake with {}:{} failed: {}", host, port, e);
+                            let _ = tx.send(Err(handshake_error_message(&e, use_vencrypt)));
                         }
                     }
                 }
                 Err(e) => {
-                    let err_msg = format!("Connect Error: {}", e);
-                    error!("{}", err_msg);
-                    let _ = tx.send(Err(err_msg));
+                    let _ = tx.send(Err(e));
                 }
             }
         });
     }
 
+    /// Classic VNC authentication only uses the first 8 bytes of the password.
+    /// Account logins take the password whole.
+    pub fn password_truncated(&self) -> bool {
+        self.username.is_empty() && self.password.len() > VNC_PASSWORD_LENGTH
+    }
+
+    /// Lets go of every key the remote has held down, for when the view stops
+    /// getting keyboard input and the releases would otherwise never arrive.
+    pub fn release_held_keys(&mut self) {
+        if let Some(ref mut vnc) = self.vnc_client {
+            release_keys(vnc, &mut self.pressed_keys);
+        }
+        self.remote_modifiers = egui::Modifiers::NONE;
+    }
+
+    /// Switches view-only mode on a live connection. Keys and buttons still held
+    /// on the remote are released first, since no later event would release them.
+    pub fn set_view_only(&mut self, view_only: bool) {
+        if view_only == self.view_only {
+            return;
+        }
+        if view_only {
+            self.release_held_keys();
+            if let (Some(ref mut vnc), Some((x, y))) = (&mut self.vnc_client, self.last_pointer_pos)
+            {
+                if self.last_buttons != 0 {
+                    let _ = vnc.send_pointer_event(0, x, y);
+                }
+            }
+            self.last_buttons = 0;
+            self.pointer_grabbed = false;
+        }
+        self.view_only = view_only;
+        self.status_text = if view_only {
+            "View only: input and clipboard are not sent".to_string()
+        } else {
+            "Input enabled".to_string()
+        };
+    }
+
+    /// Ends the session at the user's request, letting go of anything still held
+    /// on the remote first. Unlike a dropped connection, this never reconnects.
+    pub fn disconnect(&mut self) {
+        let Some(mut vnc) = self.vnc_client.take() else {
+            return;
+        };
+        info!("Disconnecting");
+        if let Some((x, y)) = self.last_pointer_pos {
+            if self.last_buttons != 0 {
+                let _ = vnc.send_pointer_event(0, x, y);
+            }
+        }
+        self.end_session(&mut vnc);
+        if let Err(e) = vnc.disconnect() {
+            warn!("Failed to close the connection: {}", e);
+        }
+        self.cancel_reconnect();
+        self.screen_texture = None;
+        self.pixels.clear();
+        self.confirm_disconnect = false;
+        self.status_text = "Disconnected".to_string();
+    }
+
+    /// Back to the connect screen, for however the session ended.
+    fn end_session(&mut self, vnc: &mut vnc::Client) {
+        release_keys(vnc, &mut self.pressed_keys);
+        self.stop_recording();
+        // Keeps the window size for next time.
+        self.save_config();
+        self.remote_modifiers = egui::Modifiers::NONE;
+        self.state = AppState::Connect;
+        self.pointer_grabbed = false;
+        self.last_pointer_pos = None;
+        self.last_buttons = 0;
+        self.latched_keys.clear();
+        self.watchdog = None;
+        self.confirm_quit = false;
+        self.vnc_client = None;
+    }
+
+    /// A connection attempt is in progress and its result hasn't arrived yet.
+    pub fn is_connecting(&self) -> bool {
+        self.vnc_rx.is_some()
+    }
+
+    /// Gives up on a connection attempt still in progress. The connecting thread
+    /// notices nothing and its result is dropped.
+    pub fn cancel_connect(&mut self) {
+        // Dropping the prompt's reply lets a thread waiting on it give up.
+        self.password_rx = None;
+        self.password_prompt = None;
+        if self.vnc_rx.take().is_some() {
+            info!("Connection attempt cancelled");
+            self.cancel_reconnect();
+            self.status_text = "Connection cancelled".to_string();
+        }
+    }
+
+    /// Hands the typed password to the connecting thread, or `None` to give up.
+    /// A password kept for the host is saved with it from now on.
+    pub fn answer_password_prompt(&mut self, confirmed: bool) {
+        let Some(prompt) = self.password_prompt.take() else {
+            return;
+        };
+        if !confirmed {
+            let _ = prompt.reply.send(None);
+            return;
+        }
+        if prompt.save {
+            self.password = prompt.password.clone();
+            self.save_config();
+        }
+        self.status_text = "Authenticating...".to_string();
+        let _ = prompt.reply.send(Some(prompt.password));
+    }
+
     pub fn handle_vnc_events(&mut self, ctx: &egui::Context) {
+        if let Some(reply) = self.password_rx.as_ref().and_then(|rx| rx.try_recv().ok()) {
+            self.password_prompt = Some(PasswordPrompt {
+                reply,
+                password: String::new(),
+                save: false,
+            });
+            self.status_text = "Password required".to_string();
+        }
         // Check for new connection
         if let Some(ref rx) = self.vnc_rx {
-            if let Ok(result) = rx.try_recv() {
+            let result = match rx.try_recv() {
+                Ok(result) => Some(result),
+                // The connecting thread died without an answer; don't wait forever.
+                Err(TryRecvError::Disconnected) => {
+                    Some(Err("Connection attempt failed".to_string()))
+                }
+                Err(TryRecvError::Empty) => None,
+            };
+            if let Some(result) = result {
                 match result {
                     Ok(mut vnc) => {
                         let (w, h) = vnc.size();
                         info!("Connected: {}x{}", w, h);
 
-                        vnc.set_encodings(&[
-                            Encoding::Zrle,
-                            Encoding::CopyRect,
-                            Encoding::Raw,
-                            Encoding::Cursor,
-                            Encoding::DesktopSize,
-                        ])
-                        .unwrap();
+                        self.server_format = Some(vnc.format());
+                        if let Some(format) = self.requested_format() {
+                            switch_format(&mut vnc, format);
+                        }
+                        // The settings as last applied, also when reconnecting.
+                        if let Err(e) = vnc.set_encodings(&self.encodings()) {
+                            warn!("Failed to set encodings: {}", e);
+                        }
 
                         vnc.request_update(
                             Rect {
@@ -112,108 +347,771 @@ impl VncApp {
                         )
                         .unwrap();
 
+                        self.awaiting_full_update = true;
+                        self.benchmark = self.benchmark_encodings.then(Benchmark::new);
+                        // A reconnect keeps the zoom and scroll position of the session.
+                        if self.reconnecting() {
+                            self.swap_view_state(self.screen_size, (w, h));
+                        } else {
+                            self.view_states.clear();
+                            self.screens.clear();
+                            self.selected_screen = None;
+                            // Back where the last session with this host left off,
+                            // unless the screen is scaled to the window.
+                            if !self.fit_to_window {
+                                self.pending_scroll = Some(self.scroll_offset);
+                            }
+                        }
+                        self.colour_map.clear();
+                        self.clipboard_received = None;
+                        self.clipboard_text = None;
+                        self.clipboard_polled = None;
+                        self.cad_pending = self.send_cad_on_connect;
+                        self.remote_modifiers = egui::Modifiers::NONE;
+                        self.pressed_keys.clear();
+                        self.last_input_at = Some(Instant::now());
+                        self.watchdog =
+                            Some(Watchdog::new(Instant::now(), vnc.stats().bytes_received));
                         self.screen_size = (w, h);
                         self.pixels = vec![Color32::BLACK; (w as usize) * (h as usize)];
+                        self.dirty_rect = None;
+                        self.stats_sample = None;
+                        self.frame_rate = 0.0;
+                        self.bytes_per_second = 0.0;
+                        self.frame_encoding = None;
+                        self.visible_area = None;
+                        self.requested_area = None;
+                        self.pacing = None;
+                        self.mark_dirty(Rect {
+                            left: 0,
+                            top: 0,
+                            width: w,
+                            height: h,
+                        });
                         self.vnc_client = Some(vnc);
                         self.state = AppState::Viewing;
-                        self.status_text = "Connected".to_string();
+                        self.window_prefs_pending = true;
+                        self.remote_size_wanted = None;
+                        self.remote_size_requested = None;
+                        self.status_text = if self.shared {
+                            "Connected".to_string()
+                        } else {
+                            "Connected, taking over the session".to_string()
+                        };
+                        self.cancel_reconnect();
                     }
                     Err(e) => {
-                        self.status_text = e;
+                        self.status_text = e.clone();
+                        self.connect_error = Some(e);
+                        if self.reconnect_attempts > 0 {
+                            self.schedule_reconnect();
+                        }
                     }
                 }
                 self.vnc_rx = None;
+                self.password_rx = None;
+                self.password_prompt = None;
             }
         }
+        self.poll_reconnect(ctx);
+        self.poll_wake_connect(ctx);
+        self.poll_idle_timeout(ctx);
 
         if let Some(mut vnc) = self.vnc_client.take() {
-            let mut updated = false;
-
             while let Some(event) = vnc.poll_event() {
                 match event {
                     vnc::client::Event::Disconnected(e) => {
                         error!("Disconnected: {:?}", e);
-                        self.state = AppState::Connect;
-                        self.vnc_client = None;
+                        self.end_session(&mut vnc);
+                        self.status_text = "Disconnected".to_string();
+                        if self.requested_format().is_some() && self.first_frame_at.is_none() {
+                            self.status_text = "Disconnected before the first frame. The \
+                                server may not take the colour depth asked for; try \
+                                leaving it to the server."
+                                .to_string();
+                        }
+                        if self.auto_reconnect {
+                            self.schedule_reconnect();
+                        }
                         return;
                     }
                     vnc::client::Event::Resize(w, h) => {
                         info!("Resize: {}x{}", w, h);
-                        self.screen_size = (w, h);
-                        self.pixels = vec![Color32::BLACK; (w as usize) * (h as usize)];
-                        updated = true;
+                        self.resize_screen(&mut vnc, (w, h), true);
+                    }
+                    vnc::client::Event::ExtendedResize {
+                        width,
+                        height,
+                        reason,
+                        status,
+                        screens,
+                    } => {
+                        if status != 0 {
+                            warn!(
+                                "Resize to {}x{} refused: {}",
+                                width,
+                                height,
+                                resize_error(status)
+                            );
+                            self.status_text = format!("Resize refused: {}", resize_error(status));
+                        } else {
+                            info!("Resize ({:?}): {}x{}", reason, width, height);
+                            if reason == vnc::client::ResizeReason::Client {
+                                self.status_text =
+                                    format!("Remote resized to {}x{}", width, height);
+                            }
+                            // A resize we asked for keeps the current view.
+                            let restore_view = reason != vnc::client::ResizeReason::Client;
+                            self.resize_screen(&mut vnc, (width, height), restore_view);
+                            if self
+                                .selected_screen
+                                .is_some_and(|id| !screens.iter().any(|screen| screen.id == id))
+                            {
+                                self.select_screen(None);
+                            }
+                            self.screens = screens;
+                        }
                     }
                     vnc::client::Event::PutPixels(rect, pixels) => {
-                        let format = vnc.format();
+                        self.first_frame_at.get_or_insert_with(Instant::now);
+                        let format = self.format_override.apply(vnc.format());
                         self.update_pixels(rect, &pixels, format);
-                        updated = true;
+                        self.mark_dirty(rect);
+                        self.last_pixels_at = Some(Instant::now());
+                    }
+                    vnc::client::Event::SetColourMap {
+                        first_colour,
+                        colours,
+                    } => {
+                        self.set_colour_map(first_colour, &colours);
                     }
                     vnc::client::Event::CopyPixels { src, dst } => {
                         self.copy_pixels(src, dst);
-                        updated = true;
+                        self.mark_dirty(dst);
+                        self.last_pixels_at = Some(Instant::now());
+                    }
+                    // Legacy cut text arrives already decoded from Latin-1, and when the
+                    // extended clipboard is enabled the library hands over its UTF-8 text.
+                    vnc::client::Event::Clipboard(text) if !self.disable_clipboard => {
+                        self.clipboard_received = Some(text.chars().count());
+                        ctx.output_mut(|o| o.copied_text = text.clone());
+                        self.clipboard_text = Some(text);
                     }
                     vnc::client::Event::EndOfFrame => {
+                        self.first_frame_at.get_or_insert_with(Instant::now);
+                        if std::mem::take(&mut self.cad_pending) {
+                            self.send_startup_cad(&mut vnc);
+                        }
+                        self.awaiting_full_update = false;
+                        self.last_pixels_at = None;
+                        if let Some(encoding) = vnc.stats().frame_encoding {
+                            self.frame_encoding = Some(encoding);
+                        }
+                        self.record_frame();
+                        self.schedule_repaint(ctx);
+                    }
+                    vnc::client::Event::Bell => {
+                        self.bell_pending = true;
                         ctx.request_repaint();
-                        vnc.request_update(
-                            Rect {
-                                left: 0,
-                                top: 0,
-                                width: self.screen_size.0,
-                                height: self.screen_size.1,
-                            },
-                            true,
-                        )
-                        .unwrap();
                     }
                     _ => {}
                 }
             }
 
-            if updated {
+            if self.dirty_rect.is_some() {
                 self.update_texture(ctx);
-                ctx.request_repaint();
+                self.minimap_dirty = true;
+                self.schedule_repaint(ctx);
+            }
+            self.refresh_minimap(ctx);
+            self.sample_rates(&vnc);
+            self.run_benchmark(&mut vnc, ctx);
+            if let Some(wait) = self.end_quiet_frame(Instant::now()) {
+                ctx.request_repaint_after(wait);
             }
-            self.vnc_client = Some(vnc);
+            self.update_pacing(&mut vnc);
+            self.vnc_client = self.watch_connection(vnc, ctx);
         }
     }
 
-    pub fn copy_pixels(&mut self, src: Rect, dst: Rect) {
-        let width = src.width as usize;
-        let height = src.height as usize;
-        let screen_w = self.screen_size.0 as usize;
-
-        if dst.top < src.top {
-            // Copy from top to bottom
-            for y in 0..height {
-                let src_y = src.top as usize + y;
-                let dst_y = dst.top as usize + y;
-                for x in 0..width {
-                    let src_idx = src_y * screen_w + (src.left as usize + x);
-                    let dst_idx = dst_y * screen_w + (dst.left as usize + x);
-                    self.pixels[dst_idx] = self.pixels[src_idx];
+    /// Probes a server that has gone quiet and ends the session if it stays
+    /// quiet. Hands the client back while the connection is alive.
+    fn watch_connection(
+        &mut self,
+        mut vnc: vnc::Client,
+        ctx: &egui::Context,
+    ) -> Option<vnc::Client> {
+        let Some(ref mut watchdog) = self.watchdog else {
+            return Some(vnc);
+        };
+        if self.server_timeout_secs == 0 {
+            return Some(vnc);
+        }
+        let timeout = Duration::from_secs(self.server_timeout_secs as u64);
+        let now = Instant::now();
+        let check = watchdog.check(now, vnc.stats().bytes_received, timeout);
+        ctx.request_repaint_after(watchdog.next_check(timeout).saturating_duration_since(now));
+        match check {
+            Check::Alive => {}
+            Check::Probe => {
+                // A non-incremental request always gets an answer.
+                info!("Nothing from the server for a while, probing");
+                let pixel = Rect {
+                    left: 0,
+                    top: 0,
+                    width: 1,
+                    height: 1,
+                };
+                let _ = vnc.request_update(pixel, false);
+            }
+            Check::Stale => {
+                error!(
+                    "Nothing from the server for {}s, giving the connection up",
+                    self.server_timeout_secs
+                );
+                self.end_session(&mut vnc);
+                if let Err(e) = vnc.disconnect() {
+                    warn!("Failed to close the connection: {}", e);
                 }
+                self.status_text = "Connection lost (timeout)".to_string();
+                if self.auto_reconnect {
+                    self.schedule_reconnect();
+                }
+                return None;
+            }
+        }
+        Some(vnc)
+    }
+
+    /// Sends Ctrl-Alt-Del for a fresh connection. A host that keeps dropping the
+    /// connection only gets it once per `CAD_MIN_INTERVAL`.
+    fn send_startup_cad(&mut self, vnc: &mut vnc::Client) {
+        if self.view_only {
+            return;
+        }
+        if let Some(last) = self.last_auto_cad {
+            if last.elapsed() < CAD_MIN_INTERVAL {
+                info!("Not repeating Ctrl-Alt-Del on connect");
+                return;
             }
+        }
+        info!("Sending Ctrl-Alt-Del on connect");
+        send_keys(vnc, keys::CTRL_ALT_DEL);
+        self.last_auto_cad = Some(Instant::now());
+    }
+
+    /// Updates the frame and data rates, smoothed over a few seconds.
+    fn sample_rates(&mut self, vnc: &vnc::Client) {
+        let now = Instant::now();
+        let stats = vnc.stats();
+        let Some((sampled_at, sample)) = self.stats_sample else {
+            self.stats_sample = Some((now, stats));
+            return;
+        };
+        let elapsed = (now - sampled_at).as_secs_f32();
+        if elapsed < RATE_SAMPLE_INTERVAL.as_secs_f32() {
+            return;
+        }
+        let frames = (stats.updates - sample.updates) as f32 / elapsed;
+        let bytes = (stats.bytes_received - sample.bytes_received) as f32 / elapsed;
+        self.frame_rate = (self.frame_rate + frames) / 2.0;
+        self.bytes_per_second = (self.bytes_per_second + bytes) / 2.0;
+        self.stats_sample = Some((now, stats));
+    }
+
+    /// Queues the next reconnect attempt, or gives up once `reconnect_max_attempts`
+    /// have failed (0 retries forever).
+    fn schedule_reconnect(&mut self) {
+        let attempts = self.reconnect_attempts;
+        if self.reconnect_max_attempts != 0 && attempts >= self.reconnect_max_attempts {
+            info!("Giving up after {} reconnect attempts", attempts);
+            self.cancel_reconnect();
+            self.status_text = format!("Reconnection failed after {} attempts.", attempts);
+            return;
+        }
+        self.reconnect_at = Some(Instant::now() + reconnect_delay(attempts));
+        self.update_reconnect_status();
+    }
+
+    fn update_reconnect_status(&mut self) {
+        let Some(reconnect_at) = self.reconnect_at else {
+            return;
+        };
+        let remaining = reconnect_at.saturating_duration_since(Instant::now());
+        self.status_text = format!(
+            "Connection lost, reconnecting in {}s (attempt {})...",
+            remaining.as_secs_f32().ceil(),
+            self.reconnect_attempts + 1
+        );
+    }
+
+    fn poll_reconnect(&mut self, ctx: &egui::Context) {
+        let Some(reconnect_at) = self.reconnect_at else {
+            return;
+        };
+        let now = Instant::now();
+        if now < reconnect_at {
+            // Wake up at least every second to count down.
+            ctx.request_repaint_after((reconnect_at - now).min(Duration::from_secs(1)));
+            self.update_reconnect_status();
+            return;
+        }
+        self.reconnect_at = None;
+        self.reconnect_attempts += 1;
+        self.connect();
+    }
+
+    /// Sends a Wake-on-LAN packet to the host's MAC address, and with
+    /// `connect_after_wake` connects once it has had time to start.
+    pub fn wake(&mut self) {
+        let Some(mac) = wol::parse_mac(&self.mac_address) else {
+            let e = format!("Invalid MAC address {:?}", self.mac_address.trim());
+            self.status_text = e.clone();
+            self.connect_error = Some(e);
+            return;
+        };
+        if let Err(e) = wol::wake(mac) {
+            warn!("Failed to send the wake-up packet: {}", e);
+            let e = format!("Failed to send the wake-up packet: {}", e);
+            self.status_text = e.clone();
+            self.connect_error = Some(e);
+            return;
+        }
+        info!("Sent wake-up packet to {}", self.mac_address.trim());
+        self.save_config();
+        if self.config.connect_after_wake {
+            self.wake_connect_at = Some(Instant::now() + WAKE_CONNECT_DELAY);
+            self.update_wake_status();
         } else {
-            // Copy from bottom to top to handle overlap correctly
-            for y in (0..height).rev() {
-                let src_y = src.top as usize + y;
-                let dst_y = dst.top as usize + y;
-                for x in 0..width {
-                    let src_idx = src_y * screen_w + (src.left as usize + x);
-                    let dst_idx = dst_y * screen_w + (dst.left as usize + x);
-                    self.pixels[dst_idx] = self.pixels[src_idx];
+            self.status_text = format!("Sent wake-up packet to {}", self.mac_address.trim());
+        }
+    }
+
+    fn update_wake_status(&mut self) {
+        let Some(connect_at) = self.wake_connect_at else {
+            return;
+        };
+        let remaining = connect_at.saturating_duration_since(Instant::now());
+        self.status_text = format!(
+            "Sent wake-up packet, connecting in {}s...",
+            remaining.as_secs_f32().ceil()
+        );
+    }
+
+    fn poll_wake_connect(&mut self, ctx: &egui::Context) {
+        let Some(connect_at) = self.wake_connect_at else {
+            return;
+        };
+        let now = Instant::now();
+        if now < connect_at {
+            ctx.request_repaint_after((connect_at - now).min(Duration::from_secs(1)));
+            self.update_wake_status();
+            return;
+        }
+        self.connect();
+    }
+
+    /// When the session times out for lack of input, if it does.
+    pub fn idle_deadline(&self) -> Option<Instant> {
+        if self.idle_timeout_mins == 0 {
+            return None;
+        }
+        let timeout = Duration::from_secs(self.idle_timeout_mins as u64 * 60);
+        Some(self.last_input_at? + timeout)
+    }
+
+    fn poll_idle_timeout(&mut self, ctx: &egui::Context) {
+        if self.state != AppState::Viewing || self.vnc_client.is_none() {
+            return;
+        }
+        let Some(deadline) = self.idle_deadline() else {
+            return;
+        };
+        let now = Instant::now();
+        if now < deadline {
+            ctx.request_repaint_after(deadline - now);
+            return;
+        }
+        info!("No input for {} minutes", self.idle_timeout_mins);
+        self.disconnect();
+        self.status_text = format!(
+            "Disconnected after {} minutes without input",
+            self.idle_timeout_mins
+        );
+    }
+
+    /// Stops any pending reconnect and starts the attempt count over.
+    pub fn cancel_reconnect(&mut self) {
+        self.reconnect_at = None;
+        self.reconnect_attempts = 0;
+    }
+
+    pub fn reconnecting(&self) -> bool {
+        self.reconnect_at.is_some() || self.reconnect_attempts > 0
+    }
+
+    /// Encodings to offer the server, most preferred first.
+    pub fn encodings(&self) -> Vec<Encoding> {
+        let mut encodings = Vec::new();
+        match self.preferred_encoding.as_str() {
+            "Tight" => encodings.push(Encoding::Tight),
+            "ZRLE" => encodings.push(Encoding::Zrle),
+            "TRLE" => encodings.push(Encoding::Trle),
+            "Hextile" => encodings.push(Encoding::Hextile),
+            _ => (),
+        }
+        if self.allow_copyrect {
+            encodings.push(Encoding::CopyRect);
+        }
+        encodings.push(Encoding::Raw);
+        encodings.extend(self.pseudo_encodings());
+        encodings
+    }
+
+    fn pseudo_encodings(&self) -> Vec<Encoding> {
+        let mut encodings = vec![
+            Encoding::Cursor,
+            Encoding::DesktopSize,
+            Encoding::ExtendedDesktopSize,
+            Encoding::LastRect,
+            Encoding::CompressionLevel(self.compression_level),
+            Encoding::QualityLevel(self.quality_level),
+        ];
+        if !self.disable_clipboard {
+            encodings.push(Encoding::ExtendedClipboard);
+        }
+        encodings
+    }
+
+    /// Advances the encoding benchmark, if one is running, and switches to the
+    /// winning encoding once it completes.
+    fn run_benchmark(&mut self, vnc: &mut vnc::Client, ctx: &egui::Context) {
+        let step = match self.benchmark {
+            Some(ref mut benchmark) if benchmark.is_running() => benchmark.step(vnc.stats()),
+            _ => return,
+        };
+        match step {
+            Step::Start(encoding) => {
+                let mut encodings = vec![encoding];
+                encodings.extend(self.pseudo_encodings());
+                let _ = vnc.set_encodings(&encodings);
+                let _ = vnc.request_update(
+                    Rect {
+                        left: 0,
+                        top: 0,
+                        width: self.screen_size.0,
+                        height: self.screen_size.1,
+                    },
+                    false,
+                );
+                self.awaiting_full_update = true;
+                ctx.request_repaint();
+            }
+            Step::Wait(wait) => ctx.request_repaint_after(wait),
+            Step::Done(best) => {
+                info!("Encoding benchmark picked {}", best);
+                self.preferred_encoding = best.to_string();
+                let _ = vnc.set_encodings(&self.encodings());
+            }
+        }
+    }
+
+    /// The format to ask the server for, `None` to keep the one it offered.
+    pub fn requested_format(&self) -> Option<PixelFormat> {
+        pixels::requested_format(self.colour_depth, self.force_32bit)
+    }
+
+    /// Switches the session to the chosen colour depth, or back to the server's
+    /// own format, and redraws the screen in it.
+    pub fn apply_colour_depth(&mut self) {
+        let Some(server_format) = self.server_format else {
+            return;
+        };
+        let format = self.requested_format().unwrap_or(server_format);
+        let Some(ref mut vnc) = self.vnc_client else {
+            return;
+        };
+        if switch_format(vnc, format) {
+            // Any update in flight was swallowed by the switch.
+            self.awaiting_full_update = false;
+            self.request_full_update();
+        }
+    }
+
+    /// Sends the encoding settings and redraws the whole screen, so the change
+    /// shows at once rather than with whatever changes next.
+    pub fn apply_encodings(&mut self) {
+        let encodings = self.encodings();
+        let Some(ref mut vnc) = self.vnc_client else {
+            return;
+        };
+        info!("Encodings: {:?}", encodings);
+        if let Err(e) = vnc.set_encodings(&encodings) {
+            self.status_text = format!("Failed to apply encodings: {}", e);
+            return;
+        }
+        self.status_text = format!(
+            "Encoding settings applied (compression {}, quality {})",
+            self.compression_level, self.quality_level
+        );
+        self.request_full_update();
+    }
+
+    /// Requests a non-incremental update of the whole screen. At most one is kept
+    /// outstanding; further requests are dropped until that frame completes.
+    pub fn request_full_update(&mut self) {
+        if self.awaiting_full_update {
+            return;
+        }
+        let Some(ref mut vnc) = self.vnc_client else {
+            return;
+        };
+        let _ = vnc.request_update(
+            Rect {
+                left: 0,
+                top: 0,
+                width: self.screen_size.0,
+                height: self.screen_size.1,
+            },
+            false,
+        );
+        self.awaiting_full_update = true;
+    }
+
+    /// Follows a change of the remote screen size. What is still on screen is kept
+    /// until the full update asked for here replaces it, so nothing flashes black.
+    fn resize_screen(&mut self, vnc: &mut vnc::Client, size: (u16, u16), restore_view: bool) {
+        if size == self.screen_size {
+            return;
+        }
+        if restore_view {
+            self.swap_view_state(self.screen_size, size);
+        }
+        self.pixels = resized_pixels(&self.pixels, self.screen_size, size);
+        self.screen_size = size;
+        let screen = Rect {
+            left: 0,
+            top: 0,
+            width: size.0,
+            height: size.1,
+        };
+        self.mark_dirty(screen);
+        let _ = vnc.request_update(screen, false);
+        self.awaiting_full_update = true;
+    }
+
+    /// With "Resize remote to fit window" on, asks the server for a screen the
+    /// size of `available` (in points) once the window has stopped changing size
+    /// for `REMOTE_RESIZE_DELAY`. Returns how long to wait before checking again.
+    pub fn fit_remote_to_window(
+        &mut self,
+        available: egui::Vec2,
+        pixels_per_point: f32,
+    ) -> Option<Duration> {
+        if !self.resize_remote || self.view_only {
+            return None;
+        }
+        let vnc = self.vnc_client.as_mut()?;
+        if !vnc.can_resize_desktop() {
+            return None;
+        }
+        let size = desktop_size_for(available, pixels_per_point);
+        if size == self.screen_size || Some(size) == self.remote_size_requested {
+            self.remote_size_wanted = None;
+            return None;
+        }
+        let since = match self.remote_size_wanted {
+            Some((wanted, since)) if wanted == size => since,
+            _ => {
+                self.remote_size_wanted = Some((size, Instant::now()));
+                return Some(REMOTE_RESIZE_DELAY);
+            }
+        };
+        let waited = since.elapsed();
+        if waited < REMOTE_RESIZE_DELAY {
+            return Some(REMOTE_RESIZE_DELAY - waited);
+        }
+        info!("Asking the server to resize to {}x{}", size.0, size.1);
+        if let Err(e) = vnc.set_desktop_size(size.0, size.1) {
+            warn!("Failed to send the resize request: {}", e);
+        }
+        self.remote_size_wanted = None;
+        self.remote_size_requested = Some(size);
+        None
+    }
+
+    /// Repaints for new frame content, no more often than `ui_max_fps` allows.
+    fn schedule_repaint(&self, ctx: &egui::Context) {
+        match remaining_interval(Some(self.last_repaint), self.config.ui_max_fps) {
+            Some(wait) => ctx.request_repaint_after(wait),
+            None => ctx.request_repaint(),
+        }
+    }
+
+    /// Some servers never mark the end of an update, which would leave us waiting
+    /// forever. Once pixels have arrived and then gone quiet for `FRAME_QUIET_TIME`,
+    /// the frame is taken as complete. Returns how long to wait otherwise.
+    pub fn end_quiet_frame(&mut self, now: Instant) -> Option<Duration> {
+        let last_pixels_at = self.last_pixels_at?;
+        let quiet = now.saturating_duration_since(last_pixels_at);
+        if quiet < FRAME_QUIET_TIME {
+            return Some(FRAME_QUIET_TIME - quiet);
+        }
+        info!(
+            "No end of frame after {:?}, requesting the next update",
+            quiet
+        );
+        self.last_pixels_at = None;
+        self.awaiting_full_update = false;
+        self.update_request_pending = true;
+        None
+    }
+
+    /// Tells the client which area to keep updating and how often. It asks for
+    /// each update as soon as the last one is in, from a thread of its own, so
+    /// the frame rate doesn't depend on how often the window repaints.
+    fn update_pacing(&mut self, vnc: &mut vnc::Client) {
+        if std::mem::take(&mut self.update_request_pending) {
+            let _ = vnc.assume_frame_end();
+        }
+        let screen = Rect {
+            left: 0,
+            top: 0,
+            width: self.screen_size.0,
+            height: self.screen_size.1,
+        };
+        let area = match self.visible_area.filter(|_| self.update_visible_only) {
+            Some(area) => {
+                // Whatever scrolled into view may have changed while it wasn't
+                // being updated, so it is refreshed in full once.
+                if self.requested_area != Some(area) {
+                    let _ = vnc.request_update(area, false);
+                    self.requested_area = Some(area);
                 }
+                area
             }
+            None => {
+                self.requested_area = None;
+                screen
+            }
+        };
+        let pacing = vnc::UpdatePacing {
+            area,
+            max_fps: self.vnc_max_fps,
+            paused: self.frozen(),
+        };
+        if self.pacing != Some(pacing) && vnc.set_update_pacing(Some(pacing)).is_ok() {
+            self.pacing = Some(pacing);
+        }
+    }
+
+    /// Works out which remote pixels are in view, given the size of the window
+    /// onto the screen and the scaled size the screen is drawn at.
+    pub fn update_visible_area(&mut self, viewport: egui::Vec2, display_size: egui::Vec2) {
+        let view = self.view_rect();
+        self.visible_area = visible_area(
+            (view.width, view.height),
+            self.scroll_offset,
+            viewport,
+            display_size,
+        )
+        .map(|area| Rect {
+            left: area.left + view.left,
+            top: area.top + view.top,
+            ..area
+        });
+    }
+
+    /// Copies a rectangle of the screen, clipping both sides to the screen so a
+    /// bad CopyRect from the server loses pixels rather than panicking.
+    pub fn copy_pixels(&mut self, src: Rect, dst: Rect) {
+        let (screen_w, screen_h) = (self.screen_size.0 as usize, self.screen_size.1 as usize);
+        if self.pixels.len() < screen_w * screen_h {
+            return;
+        }
+        let (src_x, src_y) = (src.left as usize, src.top as usize);
+        let (dst_x, dst_y) = (dst.left as usize, dst.top as usize);
+        let width = (src.width as usize)
+            .min(screen_w.saturating_sub(src_x))
+            .min(screen_w.saturating_sub(dst_x));
+        let height = (src.height as usize)
+            .min(screen_h.saturating_sub(src_y))
+            .min(screen_h.saturating_sub(dst_y));
+        if width == 0 {
+            return;
+        }
+
+        let copy_row = |pixels: &mut Vec<Color32>, y: usize| {
+            let src_idx = (src_y + y) * screen_w + src_x;
+            let dst_idx = (dst_y + y) * screen_w + dst_x;
+            pixels.copy_within(src_idx..src_idx + width, dst_idx);
+        };
+        // Rows go in the order that doesn't overwrite source rows before they
+        // are read; `copy_within` takes care of overlap within a row.
+        if dst_y < src_y {
+            (0..height).for_each(|y| copy_row(&mut self.pixels, y));
+        } else {
+            (0..height)
+                .rev()
+                .for_each(|y| copy_row(&mut self.pixels, y));
+        }
+    }
+
+    /// Stores palette entries for indexed pixel formats. Pixels already on screen
+    /// keep their colour until they are updated again.
+    pub fn set_colour_map(&mut self, first_colour: u16, colours: &[vnc::Colour]) {
+        let first = first_colour as usize;
+        if self.colour_map.len() < first + colours.len() {
+            self.colour_map
+                .resize(first + colours.len(), Color32::BLACK);
+        }
+        for (entry, colour) in self.colour_map[first..].iter_mut().zip(colours) {
+            *entry = Color32::from_rgb(
+                (colour.red >> 8) as u8,
+                (colour.green >> 8) as u8,
+                (colour.blue >> 8) as u8,
+            );
         }
     }
 
     pub fn update_pixels(&mut self, rect: Rect, pixels: &[u8], format: PixelFormat) {
+        match pixels::byte_offsets(&format) {
+            Some(offsets) => self.update_pixels_32(rect, pixels, offsets),
+            None => self.decode_pixels(rect, pixels, format),
+        }
+    }
+
+    /// The fast path for 32bpp formats with a byte per channel, taking the
+    /// channels straight from the bytes at `offsets` within each pixel.
+    pub fn update_pixels_32(&mut self, rect: Rect, pixels: &[u8], offsets: [usize; 3]) {
+        let [r, g, b] = offsets;
+        let width = rect.width as usize;
+        if width == 0 {
+            return;
+        }
+        let rows = pixels.chunks(width * 4).take(rect.height as usize);
+        for (y, row) in rows.enumerate() {
+            let row_start =
+                (rect.top as usize + y) * self.screen_size.0 as usize + rect.left as usize;
+            let Some(screen_row) = self.pixels.get_mut(row_start..) else {
+                break;
+            };
+            for (pixel, bytes) in screen_row.iter_mut().zip(row.chunks_exact(4)) {
+                *pixel = Color32::from_rgb(bytes[r], bytes[g], bytes[b]);
+            }
+        }
+    }
+
+    /// Decodes pixels of any format the server may send.
+    pub fn decode_pixels(&mut self, rect: Rect, pixels: &[u8], format: PixelFormat) {
         let bpp = format.bits_per_pixel as usize / 8;
         let mut i = 0;
 
-        let r_max = format.red_max as u32;
-        let g_max = format.green_max as u32;
-        let b_max = format.blue_max as u32;
+        if format.true_colour
+            && self.colour_tables.as_ref().map(ColourTables::format) != Some(format)
+        {
+            self.colour_tables = Some(ColourTables::new(format));
+        }
 
         for y in 0..rect.height {
             let row_start =
@@ -230,6 +1128,17 @@ impl VncApp {
                                 (pixels[i + 1] as u32) << 8 | (pixels[i] as u32)
                             }
                         }
+                        3 => {
+                            if format.big_endian {
+                                (pixels[i] as u32) << 16
+                                    | (pixels[i + 1] as u32) << 8
+                                    | (pixels[i + 2] as u32)
+                            } else {
+                                (pixels[i + 2] as u32) << 16
+                                    | (pixels[i + 1] as u32) << 8
+                                    | (pixels[i] as u32)
+                            }
+                        }
                         4 => {
                             if format.big_endian {
                                 (pixels[i] as u32) << 24
@@ -247,54 +1156,593 @@ impl VncApp {
                     };
                     i += bpp;
 
-                    let r_raw = (val >> format.red_shift) & r_max;
-                    let g_raw = (val >> format.green_shift) & g_max;
-                    let b_raw = (val >> format.blue_shift) & b_max;
-
-                    let r = if r_max == 255 {
-                        r_raw as u8
-                    } else if r_max > 0 {
-                        (r_raw * 255 / r_max) as u8
-                    } else {
-                        0
-                    };
-                    let g = if g_max == 255 {
-                        g_raw as u8
-                    } else if g_max > 0 {
-                        (g_raw * 255 / g_max) as u8
-                    } else {
-                        0
-                    };
-                    let b = if b_max == 255 {
-                        b_raw as u8
-                    } else if b_max > 0 {
-                        (b_raw * 255 / b_max) as u8
-                    } else {
-                        0
-                    };
+                    if !format.true_colour {
+                        self.pixels[pixel_idx] = self
+                            .colour_map
+                            .get(val as usize)
+                            .copied()
+                            .unwrap_or(Color32::BLACK);
+                        continue;
+                    }
 
-                    self.pixels[pixel_idx] = Color32::from_rgb(r, g, b);
+                    if let Some(ref tables) = self.colour_tables {
+                        self.pixels[pixel_idx] = tables.colour(val);
+                    }
                 }
             }
         }
     }
 
+    /// Adds `rect` to the region uploaded with the next texture update.
+    pub fn mark_dirty(&mut self, rect: Rect) {
+        self.dirty_rect = Some(match self.dirty_rect {
+            Some(dirty) => {
+                let left = dirty.left.min(rect.left);
+                let top = dirty.top.min(rect.top);
+                let right = (dirty.left as u32 + dirty.width as u32)
+                    .max(rect.left as u32 + rect.width as u32);
+                let bottom = (dirty.top as u32 + dirty.height as u32)
+                    .max(rect.top as u32 + rect.height as u32);
+                Rect {
+                    left,
+                    top,
+                    width: (right - left as u32).min(u16::MAX as u32) as u16,
+                    height: (bottom - top as u32).min(u16::MAX as u32) as u16,
+                }
+            }
+            None => rect,
+        });
+    }
+
+    /// Uploads the pixels changed since the last call. Only a new screen or a
+    /// resize uploads the whole screen.
     pub fn update_texture(&mut self, ctx: &egui::Context) {
+        let Some(dirty) = self.dirty_rect.take() else {
+            return;
+        };
         if self.pixels.is_empty() {
             return;
         }
 
         let size = [self.screen_size.0 as usize, self.screen_size.1 as usize];
-        let color_image = egui::ColorImage {
-            size,
-            pixels: self.pixels.clone(),
+        let options = if self.smooth_scaling {
+            egui::TextureOptions::LINEAR
+        } else {
+            egui::TextureOptions::NEAREST
         };
+        let filter = self.display_filter;
+        let filtered = |pixel: &Color32| filter_pixel(filter, *pixel);
+        match self.screen_texture {
+            Some(ref mut handle) if handle.size() == size => {
+                let left = (dirty.left as usize).min(size[0]);
+                let top = (dirty.top as usize).min(size[1]);
+                let right = (left + dirty.width as usize).min(size[0]);
+                let bottom = (top + dirty.height as usize).min(size[1]);
+                if left == right || top == bottom {
+                    return;
+                }
+                let pixels = (top..bottom)
+                    .flat_map(|y| &self.pixels[y * size[0] + left..y * size[0] + right])
+                    .map(filtered)
+                    .collect();
+                let color_image = egui::ColorImage {
+                    size: [right - left, bottom - top],
+                    pixels,
+                };
+                handle.set_partial([left, top], color_image, options);
+            }
+            _ => {
+                let color_image = egui::ColorImage {
+                    size,
+                    pixels: self.pixels.iter().map(filtered).collect(),
+                };
+                match self.screen_texture {
+                    Some(ref mut handle) => handle.set(color_image, options),
+                    None => {
+                        self.screen_texture =
+                            Some(ctx.load_texture("vnc_screen", color_image, options));
+                    }
+                }
+            }
+        }
+    }
+}
 
-        if let Some(ref mut handle) = self.screen_texture {
-            handle.set(color_image, Default::default());
-        } else {
-            self.screen_texture =
-                Some(ctx.load_texture("vnc_screen", color_image, Default::default()));
+/// Switches the session to `format`. Before any update is requested this is
+/// immediate; later it waits for a full frame in the old format, dropping other
+/// events meanwhile. Servers can't refuse a format, only convert to it, so the
+/// old one stays only if the request can't be sent. Returns whether it changed.
+fn switch_format(vnc: &mut vnc::Client, format: PixelFormat) -> bool {
+    let old = vnc.format();
+    if old == format {
+        return false;
+    }
+    match vnc.set_format(format) {
+        Ok(()) => {
+            info!("Pixel format {:?} instead of {:?}", format, old);
+            true
         }
+        Err(e) => {
+            warn!(
+                "Failed to change the pixel format, keeping the old one: {}",
+                e
+            );
+            false
+        }
+    }
+}
+
+/// VNC authentication is DES with the password as the key.
+const VNC_PASSWORD_LENGTH: usize = 8;
+
+/// Reconnect attempts back off exponentially from the first delay up to the cap.
+const RECONNECT_DELAY: Duration = Duration::from_secs(1);
+const RECONNECT_DELAY_MAX: Duration = Duration::from_secs(30);
+
+const RATE_SAMPLE_INTERVAL: Duration = Duration::from_secs(1);
+
+const FRAME_QUIET_TIME: Duration = Duration::from_millis(250);
+
+const CAD_MIN_INTERVAL: Duration = Duration::from_secs(60);
+
+/// How long the window size has to hold before the remote is resized to it.
+const REMOTE_RESIZE_DELAY: Duration = Duration::from_millis(500);
+/// Time a machine gets to wake up before it is connected to. Resuming from
+/// sleep is usually quicker, but a cold boot takes longer still.
+const WAKE_CONNECT_DELAY: Duration = Duration::from_secs(20);
+
+/// The remote screen size for a viewport of `available` points: in device
+/// pixels, rounded down to a multiple of 8 and kept to sizes servers take.
+fn desktop_size_for(available: egui::Vec2, pixels_per_point: f32) -> (u16, u16) {
+    let pixels = |points: f32, min: u16| {
+        let pixels = (points * pixels_per_point).clamp(0.0, 8192.0) as u16;
+        (pixels / 8 * 8).max(min)
+    };
+    (pixels(available.x, 320), pixels(available.y, 200))
+}
+
+/// A `new_size` screen with the part it shares with the `old_size` one copied
+/// over, and black where it is new.
+fn resized_pixels(pixels: &[Color32], old_size: (u16, u16), new_size: (u16, u16)) -> Vec<Color32> {
+    let (old_w, old_h) = (old_size.0 as usize, old_size.1 as usize);
+    let (new_w, new_h) = (new_size.0 as usize, new_size.1 as usize);
+    let mut resized = vec![Color32::BLACK; new_w * new_h];
+    if pixels.len() < old_w * old_h {
+        return resized;
+    }
+    let width = old_w.min(new_w);
+    for y in 0..old_h.min(new_h) {
+        resized[y * new_w..y * new_w + width]
+            .copy_from_slice(&pixels[y * old_w..y * old_w + width]);
+    }
+    resized
+}
+
+/// `pixel` as the display filter shows it.
+fn filter_pixel(filter: DisplayFilter, pixel: Color32) -> Color32 {
+    let [r, g, b, a] = pixel.to_array();
+    match filter {
+        DisplayFilter::None => pixel,
+        DisplayFilter::Invert => Color32::from_rgba_premultiplied(255 - r, 255 - g, 255 - b, a),
+        DisplayFilter::Grayscale => {
+            let luma = ((r as u32 * 77 + g as u32 * 150 + b as u32 * 29) >> 8) as u8;
+            Color32::from_rgba_premultiplied(luma, luma, luma, a)
+        }
+        DisplayFilter::Night => Color32::from_rgba_premultiplied(
+            r,
+            (g as u32 * 180 / 255) as u8,
+            (b as u32 * 100 / 255) as u8,
+            a,
+        ),
+    }
+}
+
+/// Why the server refused a resize, from the ExtendedDesktopSize status.
+fn resize_error(status: u16) -> &'static str {
+    match status {
+        1 => "resizing is not allowed",
+        2 => "the server is out of resources",
+        3 => "invalid screen layout",
+        _ => "unknown error",
+    }
+}
+
+/// Presses `keysyms` in order and releases them in reverse, like a key combination.
+pub fn send_keys(vnc: &mut vnc::Client, keysyms: &[u32]) {
+    for &keysym in keysyms {
+        let _ = vnc.send_key_event(true, keysym);
+    }
+    for &keysym in keysyms.iter().rev() {
+        let _ = vnc.send_key_event(false, keysym);
+    }
+}
+
+/// Sends a key event, keeping track of the keys the remote has held down.
+pub fn send_key(
+    vnc: &mut vnc::Client,
+    pressed_keys: &mut HashSet<u32>,
+    pressed: bool,
+    keysym: u32,
+) {
+    if pressed {
+        pressed_keys.insert(keysym);
+    } else {
+        pressed_keys.remove(&keysym);
+    }
+    let _ = vnc.send_key_event(pressed, keysym);
+}
+
+/// Releases every key the remote still has held down.
+pub fn release_keys(vnc: &mut vnc::Client, pressed_keys: &mut HashSet<u32>) {
+    for keysym in pressed_keys.drain() {
+        let _ = vnc.send_key_event(false, keysym);
+    }
+}
+
+/// Time left until `fps` allows the next event after `last`, or `None` if it
+/// may happen now. An `fps` of 0 means unlimited.
+pub fn remaining_interval(last: Option<Instant>, fps: u32) -> Option<Duration> {
+    let last = last?;
+    if fps == 0 {
+        return None;
+    }
+    let interval = Duration::from_secs(1) / fps;
+    interval
+        .checked_sub(last.elapsed())
+        .filter(|wait| !wait.is_zero())
+}
+
+/// The source address to connect from, `None` for any. Only an IP address is
+/// taken; the OS picks the port.
+fn parse_bind_address(text: &str) -> Result<Option<IpAddr>, String> {
+    let text = text.trim();
+    if text.is_empty() {
+        return Ok(None);
+    }
+    let text = text
+        .strip_prefix('[')
+        .and_then(|rest| rest.strip_suffix(']'))
+        .unwrap_or(text);
+    text.parse().map(Some).map_err(|_| {
+        format!(
+            "Invalid source address {:?}. Enter an IP address of this machine, or leave it empty.",
+            text
+        )
+    })
+}
+
+/// `TcpStream::connect_timeout` only takes a single resolved address, so try each
+/// address the host resolves to in turn, like `TcpStream::connect` does. With a
+/// `bind` address, only addresses of its family can be reached.
+fn connect_timeout(
+    host: &str,
+    port: u16,
+    timeout: Duration,
+    bind: Option<IpAddr>,
+) -> std::io::Result<TcpStream> {
+    let mut last_error = None;
+    let addrs = (host, port)
+        .to_socket_addrs()
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::NotFound, e))?;
+    for addr in addrs {
+        let result = match bind {
+            Some(bind) if bind.is_ipv4() != addr.is_ipv4() => continue,
+            Some(bind) => connect_from(bind, addr, timeout),
+            None => TcpStream::connect_timeout(&addr, timeout),
+        };
+        match result {
+            Ok(stream) => return Ok(stream),
+            Err(e) => last_error = Some(e),
+        }
+    }
+    Err(last_error.unwrap_or_else(|| match bind {
+        Some(bind) => std::io::Error::new(
+            std::io::ErrorKind::AddrNotAvailable,
+            format!(
+                "host has no {} address",
+                if bind.is_ipv4() { "IPv4" } else { "IPv6" }
+            ),
+        ),
+        None => std::io::Error::new(
+            std::io::ErrorKind::NotFound,
+            "host did not resolve to any address",
+        ),
+    }))
+}
+
+const REPEATER_CLOSED: &str = "The repeater closed the connection. Check the ID or \
+     address of the server, and that the server is connected to the repeater.";
+
+/// Opens a connection through an UltraVNC repeater, ready for the server's
+/// handshake. Errors are messages for the status bar, saying it was the repeater.
+fn connect_via_repeater(
+    repeater: &str,
+    repeater_port: u16,
+    id: &str,
+    host: &str,
+    port: u16,
+    timeout: Duration,
+    bind: Option<IpAddr>,
+) -> Result<TcpStream, String> {
+    let mut stream = connect_timeout(repeater, repeater_port, timeout, bind).map_err(|e| {
+        error!(
+            "Connection to repeater {}:{} failed: {}",
+            repeater, repeater_port, e
+        );
+        format!("Repeater: {}", connect_error_message(&e, repeater))
+    })?;
+    // A repeater that never answers would otherwise hang the attempt.
+    let _ = stream.set_read_timeout(Some(timeout));
+    let target = repeater::Target::new(id, host, port);
+    info!("Asking repeater {} for {:?}", repeater, target);
+    match repeater::handshake(&mut stream, &target) {
+        Ok(()) => {
+            // Waiting for the server is up to the repeater, and may take a while.
+            let _ = stream.set_read_timeout(None);
+            Ok(stream)
+        }
+        Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => {
+            error!("Repeater {} closed the connection: {}", repeater, e);
+            Err(REPEATER_CLOSED.to_string())
+        }
+        Err(e) => {
+            error!("Repeater handshake with {} failed: {}", repeater, e);
+            Err(format!("Repeater handshake failed: {}", e))
+        }
+    }
+}
+
+/// Connects from a given local address, which std can't do.
+fn connect_from(bind: IpAddr, addr: SocketAddr, timeout: Duration) -> std::io::Result<TcpStream> {
+    let socket = Socket::new(Domain::for_address(addr), Type::STREAM, Some(Protocol::TCP))?;
+    socket
+        .bind(&SocketAddr::new(bind, 0).into())
+        .map_err(|e| std::io::Error::new(e.kind(), format!("can't bind to {}: {}", bind, e)))?;
+    socket.connect_timeout(&addr.into(), timeout)?;
+    Ok(socket.into())
+}
+
+/// What went wrong opening the TCP connection, and what to check.
+fn connect_error_message(error: &std::io::Error, host: &str) -> String {
+    use std::io::ErrorKind;
+    match error.kind() {
+        ErrorKind::NotFound => format!(
+            "Host {} not found. Check the spelling of the address.",
+            host
+        ),
+        ErrorKind::AddrNotAvailable => format!(
+            "Could not connect from the source address: {}. Check it belongs to a local \
+             interface, or clear it in the options.",
+            error
+        ),
+        ErrorKind::ConnectionRefused => {
+            "Connection refused. Check the port and that the VNC server is running.".to_string()
+        }
+        ErrorKind::TimedOut => {
+            "Connection timed out. Check the address and that no firewall blocks the port."
+                .to_string()
+        }
+        _ => format!("Could not connect: {}", error),
+    }
+}
+
+/// Asks the UI thread for a password and waits for it. `None` when the prompt
+/// was cancelled.
+fn ask_password(requests: &Sender<Sender<Option<String>>>) -> Option<String> {
+    let (reply, answer) = std::sync::mpsc::channel();
+    requests.send(reply).ok()?;
+    answer.recv().ok().flatten()
+}
+
+/// What went wrong between connecting and the first frame, and what to check.
+fn handshake_error_message(error: &vnc::Error, use_vencrypt: bool) -> String {
+    match error {
+        vnc::Error::AuthenticationFailure(reason) if reason.is_empty() => {
+            "Authentication failed. Check the password.".to_string()
+        }
+        vnc::Error::AuthenticationFailure(reason) => {
+            format!("Authentication failed: {}. Check the password.", reason)
+        }
+        vnc::Error::AuthenticationUnavailable if use_vencrypt => {
+            "Server does not support encryption (VeNCrypt). Untick \"Use encryption\" \
+             to connect without it."
+                .to_string()
+        }
+        vnc::Error::AuthenticationUnavailable => {
+            "The server offers no security type this viewer supports.".to_string()
+        }
+        vnc::Error::Unexpected("protocol version") => {
+            "Not a VNC server, or an unsupported protocol version. Check the port.".to_string()
+        }
+        vnc::Error::Server(reason) => format!("The server refused the connection: {}", reason),
+        vnc::Error::Tls(e) => format!("TLS handshake failed: {}", e),
+        vnc::Error::Io(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => {
+            "The server closed the connection during the handshake. It may only allow one \
+             viewer, or be blocking this address."
+                .to_string()
+        }
+        e => format!("VNC handshake failed: {}", e),
+    }
+}
+
+fn visible_area(
+    screen_size: (u16, u16),
+    scroll_offset: egui::Vec2,
+    viewport: egui::Vec2,
+    display_size: egui::Vec2,
+) -> Option<Rect> {
+    if screen_size.0 == 0 || screen_size.1 == 0 || display_size.x <= 0.0 || display_size.y <= 0.0 {
+        return None;
+    }
+    let screen = egui::vec2(screen_size.0 as f32, screen_size.1 as f32);
+    let to_screen = screen / display_size;
+    // Round outwards so partly visible pixels are included.
+    let min = (scroll_offset * to_screen).floor().max(egui::Vec2::ZERO);
+    let max = ((scroll_offset + viewport) * to_screen).ceil().min(screen);
+    if max.x <= min.x || max.y <= min.y {
+        return None;
+    }
+    Some(Rect {
+        left: min.x as u16,
+        top: min.y as u16,
+        width: (max.x - min.x) as u16,
+        height: (max.y - min.y) as u16,
+    })
+}
+
+fn reconnect_delay(failed_attempts: u32) -> Duration {
+    RECONNECT_DELAY
+        .saturating_mul(1 << failed_attempts.min(16))
+        .min(RECONNECT_DELAY_MAX)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bind_address() {
+        assert_eq!(parse_bind_address(" "), Ok(None));
+        assert_eq!(
+            parse_bind_address("10.8.0.2"),
+            Ok(Some(IpAddr::from([10, 8, 0, 2])))
+        );
+        assert_eq!(
+            parse_bind_address("[::1]"),
+            Ok(Some(IpAddr::from(std::net::Ipv6Addr::LOCALHOST)))
+        );
+        assert!(parse_bind_address("vpn0").is_err());
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let bind = IpAddr::from([127, 0, 0, 1]);
+        let stream = connect_timeout("127.0.0.1", port, Duration::from_secs(5), Some(bind));
+        assert_eq!(stream.unwrap().local_addr().unwrap().ip(), bind);
+        // An IPv6 source can't reach an IPv4 address.
+        let ipv6 = IpAddr::from(std::net::Ipv6Addr::LOCALHOST);
+        let error = connect_timeout("127.0.0.1", port, Duration::from_secs(5), Some(ipv6));
+        assert_eq!(
+            error.unwrap_err().kind(),
+            std::io::ErrorKind::AddrNotAvailable
+        );
+    }
+
+    #[test]
+    fn test_error_messages() {
+        use std::io::{Error, ErrorKind};
+        let connect = |kind| connect_error_message(&Error::new(kind, "x"), "pc");
+        assert!(connect(ErrorKind::NotFound).starts_with("Host pc not found"));
+        assert!(connect(ErrorKind::ConnectionRefused).starts_with("Connection refused"));
+        assert!(connect(ErrorKind::TimedOut).starts_with("Connection timed out"));
+
+        let handshake = |error| handshake_error_message(&error, false);
+        assert_eq!(
+            handshake(vnc::Error::AuthenticationFailure(String::new())),
+            "Authentication failed. Check the password."
+        );
+        assert_eq!(
+            handshake(vnc::Error::AuthenticationFailure(
+                "too many attempts".to_string()
+            )),
+            "Authentication failed: too many attempts. Check the password."
+        );
+        assert!(handshake(vnc::Error::Unexpected("protocol version")).starts_with("Not a VNC"));
+        assert!(
+            handshake_error_message(&vnc::Error::AuthenticationUnavailable, true)
+                .contains("VeNCrypt")
+        );
+        assert!(handshake(Error::from(ErrorKind::UnexpectedEof).into()).contains("closed"));
+    }
+
+    #[test]
+    fn test_reconnect_delay() {
+        let delays: Vec<u64> = (0..7).map(|n| reconnect_delay(n).as_secs()).collect();
+        assert_eq!(delays, [1, 2, 4, 8, 16, 30, 30]);
+        assert_eq!(reconnect_delay(u32::MAX), RECONNECT_DELAY_MAX);
+    }
+
+    #[test]
+    fn test_visible_area() {
+        let rect = |left, top, width, height| Rect {
+            left,
+            top,
+            width,
+            height,
+        };
+        let vec2 = egui::vec2;
+
+        // Zoomed to 200%, scrolled to the middle.
+        assert_eq!(
+            visible_area(
+                (1000, 800),
+                vec2(500.0, 401.0),
+                vec2(400.0, 300.0),
+                vec2(2000.0, 1600.0)
+            ),
+            Some(rect(250, 200, 200, 151))
+        );
+        // Zoomed out, the whole screen fits and more.
+        assert_eq!(
+            visible_area(
+                (1000, 800),
+                vec2(0.0, 0.0),
+                vec2(800.0, 800.0),
+                vec2(500.0, 400.0)
+            ),
+            Some(rect(0, 0, 1000, 800))
+        );
+        assert_eq!(
+            visible_area((0, 0), vec2(0.0, 0.0), vec2(800.0, 800.0), vec2(0.0, 0.0)),
+            None
+        );
+    }
+
+    #[test]
+    fn test_resized_pixels() {
+        let pixels: Vec<Color32> = (1..=6).map(Color32::from_gray).collect();
+        let gray = |values: &[u8]| -> Vec<Color32> {
+            values.iter().copied().map(Color32::from_gray).collect()
+        };
+        // 3x2 grown to 4x3: the old pixels stay put, the rest is black.
+        assert_eq!(
+            resized_pixels(&pixels, (3, 2), (4, 3)),
+            gray(&[1, 2, 3, 0, 4, 5, 6, 0, 0, 0, 0, 0])
+        );
+        // Shrunk to 2x1.
+        assert_eq!(resized_pixels(&pixels, (3, 2), (2, 1)), gray(&[1, 2]));
+        assert_eq!(resized_pixels(&[], (3, 2), (1, 1)), gray(&[0]));
+    }
+
+    #[test]
+    fn test_filter_pixel() {
+        let pixel = Color32::from_rgb(255, 128, 0);
+        assert_eq!(filter_pixel(DisplayFilter::None, pixel), pixel);
+        assert_eq!(
+            filter_pixel(DisplayFilter::Invert, pixel),
+            Color32::from_rgb(0, 127, 255)
+        );
+        assert_eq!(
+            filter_pixel(DisplayFilter::Grayscale, Color32::WHITE),
+            Color32::from_rgb(255, 255, 255)
+        );
+        assert_eq!(
+            filter_pixel(DisplayFilter::Grayscale, pixel),
+            Color32::from_gray(151)
+        );
+        assert_eq!(
+            filter_pixel(DisplayFilter::Night, Color32::WHITE),
+            Color32::from_rgb(255, 180, 100)
+        );
+    }
+
+    #[test]
+    fn test_desktop_size_for() {
+        let size =
+            |x, y, pixels_per_point| desktop_size_for(egui::Vec2::new(x, y), pixels_per_point);
+        assert_eq!(size(1280.0, 720.0, 1.0), (1280, 720));
+        assert_eq!(size(1283.5, 717.0, 1.0), (1280, 712));
+        assert_eq!(size(800.0, 600.0, 1.5), (1200, 896));
+        assert_eq!(size(100.0, 0.0, 1.0), (320, 200));
+        assert_eq!(size(10000.0, 9000.0, 2.0), (8192, 8192));
     }
 }
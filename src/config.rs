@@ -1,41 +1,682 @@
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
 
-#[derive(Serialize, Deserialize, Clone)]
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+#[serde(default)]
 pub struct HostConfig {
     pub port: String,
+    /// For servers that log in with an account (MS-Logon), empty otherwise.
+    pub username: String,
     pub password: String,
+    /// Share the screen with other viewers. Off takes the session over, and the
+    /// server disconnects the others.
     pub shared: bool,
     pub view_only: bool,
-    pub zoom_fit: bool,
+    #[serde(alias = "zoom_fit")]
+    pub fit_to_window: bool,
+    /// Scale to cover the window rather than fit inside it.
+    pub fill_window: bool,
+    /// Scale to the window's exact size, ignoring the aspect ratio.
+    pub stretch_window: bool,
     pub scale: f32,
+    /// Filter the scaled screen linearly rather than show hard pixel edges.
+    pub smooth_scaling: bool,
+    pub display_filter: DisplayFilter,
     pub preferred_encoding: String,
     pub compression_level: u8,
     pub quality_level: u8,
     pub allow_copyrect: bool,
     pub disable_clipboard: bool,
+    /// Upper bound on framebuffer update requests per second, 0 for unlimited.
+    /// A cap saves bandwidth and CPU at the cost of up to one frame interval of
+    /// extra latency; unlimited is best on a fast LAN.
+    pub vnc_max_fps: u32,
+    /// Only ask for updates to the part of the screen that is scrolled into view.
+    pub update_visible_only: bool,
+    /// Measure the available encodings after connecting and keep the cheapest.
+    pub benchmark_encodings: bool,
+    /// Hide the local cursor while the pointer is grabbed by a click on the screen.
+    pub capture_mouse: bool,
+    /// While the pointer is grabbed, send how far the mouse moved rather than
+    /// where it is, for games that read relative motion.
+    pub relative_pointer: bool,
+    /// Upper bound on pointer motion events per second, 0 for unlimited. Button
+    /// changes are always sent at once.
+    pub pointer_max_rate: u32,
+    pub button_map: ButtonMap,
+    /// Send Ctrl-Alt-Del once the first frame arrives, for Windows login screens.
+    pub send_cad_on_connect: bool,
+    pub format_override: FormatOverride,
+    /// Ask the server for 32-bit true colour on connecting, which is quickest to
+    /// draw, instead of taking the format it offers. Only while `colour_depth`
+    /// is left to the server; a chosen depth wins.
+    pub force_32bit: bool,
+    pub colour_depth: ColourDepth,
+    /// Try to reconnect when the connection drops unexpectedly.
+    pub auto_reconnect: bool,
+    /// Reconnect attempts before giving up, 0 for no limit.
+    pub reconnect_max_attempts: u32,
+    /// How long to wait for the TCP connection to be established, in seconds.
+    pub connect_timeout_secs: u32,
+    /// Disconnect after this many minutes without mouse or keyboard input, 0
+    /// to stay connected. For shared kiosks.
+    pub idle_timeout_mins: u32,
+    /// Give the connection up as lost after this many seconds without hearing
+    /// from the server, 0 to wait forever.
+    pub server_timeout_secs: u32,
+    /// Local address to connect from, for picking an interface such as a VPN
+    /// adapter. Empty to let the OS choose.
+    pub bind_address: String,
+    /// UltraVNC repeater to go through, as `host[:port]`. Empty to connect directly.
+    pub repeater: String,
+    /// ID the server registered at the repeater under (mode II). Empty to have
+    /// the repeater connect to the host and port itself (mode I).
+    pub repeater_id: String,
+    /// For waking the machine with Wake-on-LAN before connecting.
+    pub mac_address: String,
+    /// Require a TLS encrypted session (VeNCrypt).
+    pub use_vencrypt: bool,
+    /// Accept the server's certificate without checking it, for self-signed ones.
+    pub trust_any_certificate: bool,
+    /// Window size while viewing this host, in points. Zero leaves it as it is.
+    pub window_width: f32,
+    pub window_height: f32,
+    /// Where the screen was scrolled to when the last session ended, in points.
+    /// Not used when scaling to the window.
+    pub scroll_x: f32,
+    pub scroll_y: f32,
+    /// Go full screen as soon as the session starts.
+    pub start_fullscreen: bool,
+    /// Ask the server to change its screen size to match the window.
+    pub resize_remote: bool,
+}
+
+/// Corrections for servers that misreport their pixel format.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(default)]
+pub struct FormatOverride {
+    /// Use the shifts and byte order below instead of the server's.
+    pub enabled: bool,
+    pub red_shift: u8,
+    pub green_shift: u8,
+    pub blue_shift: u8,
+    pub big_endian: bool,
+    /// Exchange the red and blue channels, the usual symptom of a bad format.
+    pub swap_red_blue: bool,
+}
+
+impl Default for FormatOverride {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            red_shift: 16,
+            green_shift: 8,
+            blue_shift: 0,
+            big_endian: false,
+            swap_red_blue: false,
+        }
+    }
+}
+
+impl FormatOverride {
+    pub fn apply(&self, mut format: vnc::PixelFormat) -> vnc::PixelFormat {
+        if self.enabled {
+            format.red_shift = self.red_shift;
+            format.green_shift = self.green_shift;
+            format.blue_shift = self.blue_shift;
+            format.big_endian = self.big_endian;
+        }
+        if self.swap_red_blue {
+            std::mem::swap(&mut format.red_shift, &mut format.blue_shift);
+            std::mem::swap(&mut format.red_max, &mut format.blue_max);
+        }
+        format
+    }
+}
+
+/// How local mouse buttons reach the remote.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Default)]
+#[serde(default)]
+pub struct ButtonMap {
+    /// Left and right exchanged, for left-handed use.
+    pub swap_left_right: bool,
+    pub middle: MiddleEmulation,
+    /// Remote buttons never pressed.
+    pub disable_left: bool,
+    pub disable_middle: bool,
+    pub disable_right: bool,
+}
+
+/// A middle click for mice and touchpads without a middle button.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Default)]
+pub enum MiddleEmulation {
+    #[default]
+    Off,
+    /// Left and right held together. The one pressed first is briefly seen on
+    /// its own.
+    Chord,
+    /// Left with Alt held. The remote sees Alt held too.
+    AltClick,
+}
+
+impl ButtonMap {
+    /// The RFB button mask for the local `buttons` (left 1, middle 2, right 4),
+    /// with `alt` held or not.
+    pub fn apply(&self, mut buttons: u8, alt: bool) -> u8 {
+        const LEFT: u8 = 0x01;
+        const MIDDLE: u8 = 0x02;
+        const RIGHT: u8 = 0x04;
+        if self.swap_left_right {
+            // Left is bit 0 and right bit 2.
+            buttons = (buttons & MIDDLE) | (buttons & LEFT) << 2 | (buttons & RIGHT) >> 2;
+        }
+        match self.middle {
+            MiddleEmulation::Chord if buttons & (LEFT | RIGHT) == LEFT | RIGHT => {
+                buttons = (buttons & !(LEFT | RIGHT)) | MIDDLE;
+            }
+            MiddleEmulation::AltClick if alt && buttons & LEFT != 0 => {
+                buttons = (buttons & !LEFT) | MIDDLE;
+            }
+            _ => {}
+        }
+        for (disabled, button) in [
+            (self.disable_left, LEFT),
+            (self.disable_middle, MIDDLE),
+            (self.disable_right, RIGHT),
+        ] {
+            if disabled {
+                buttons &= !button;
+            }
+        }
+        buttons
+    }
 }
 
 #[derive(Serialize, Deserialize, Default)]
+#[serde(default)]
 pub struct Config {
     pub last_host: String,
     pub hosts: HashMap<String, HostConfig>,
+    /// Upper bound on repaints triggered by incoming frames, 0 for unlimited.
+    /// Frames are requested on their own timer, paced by `vnc_max_fps`, so this
+    /// only caps how often they are shown and doesn't slow the requests down.
+    pub ui_max_fps: u32,
+    /// Connect to `last_host` on startup instead of showing the connect screen.
+    pub auto_connect: bool,
+    /// Stop requesting frames while the window is in the background.
+    pub freeze_unfocused: bool,
+    pub quick_keys: QuickKeys,
+    /// Send Ctrl+Alt as AltGr (ISO_Level3_Shift), for layouts that type symbols
+    /// with AltGr. Windows reports AltGr as Ctrl+Alt.
+    pub altgr: bool,
+    pub grid: GridOverlay,
+    /// Overview of the whole screen while zoomed in past the window.
+    pub minimap: bool,
+    /// Where screenshots and recordings are saved, the working directory if empty.
+    pub screenshot_dir: String,
+    pub recording: RecordingConfig,
+    pub bell: BellMode,
+    /// Colour around a screen smaller than the window, the theme's if unset.
+    pub background: Option<[u8; 3]>,
+    /// Put a screen smaller than the window in the middle, not the top left.
+    pub center_screen: bool,
+    /// Ask before the toolbar's Disconnect button ends the session.
+    pub confirm_disconnect: bool,
+    /// Leave two-finger touches alone instead of zooming and panning the view
+    /// with them. Either way one finger works the remote pointer.
+    pub touch_controls_remote: bool,
+    /// Also write the log to `log_path()`, for builds without a console.
+    pub log_to_file: bool,
+    /// Connect a little while after sending a Wake-on-LAN packet.
+    pub connect_after_wake: bool,
+    /// The file saved hosts were last exported to or imported from.
+    pub hosts_file: String,
+}
+
+/// Saved hosts in a file of their own, for backing them up or moving them to
+/// another machine. A whole config file reads as one too.
+#[derive(Serialize, Deserialize, Default)]
+#[serde(default)]
+pub struct HostList {
+    pub hosts: HashMap<String, HostConfig>,
+}
+
+/// Hosts read from an export, waiting on what to do about those saved here
+/// with other settings.
+pub struct HostImport {
+    /// Hosts not saved here yet. Those saved with the same settings are left out.
+    pub added: HashMap<String, HostConfig>,
+    /// Hosts saved here with other settings, with whether to replace them.
+    pub conflicts: Vec<(String, HostConfig, bool)>,
+}
+
+/// Colour change applied to the screen as shown, not to screenshots.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Default)]
+pub enum DisplayFilter {
+    #[default]
+    None,
+    Invert,
+    Grayscale,
+    /// Less blue and green, for working in the dark.
+    Night,
+}
+
+/// Pixel format asked of the server. Fewer colours take less bandwidth.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Default)]
+pub enum ColourDepth {
+    /// The format the server offers.
+    #[default]
+    Server,
+    /// 32-bit true colour.
+    Full,
+    /// 16-bit high colour.
+    High,
+    /// 256 colours, 8 bits a pixel.
+    Low,
+}
+
+/// What a bell from the server does.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Default)]
+pub enum BellMode {
+    /// The system beep on Windows; elsewhere there is none, so the window flashes.
+    #[default]
+    Audible,
+    /// Flash the edge of the window, and the taskbar entry if it's in the background.
+    Visual,
+    Off,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct RecordingConfig {
+    /// Frames captured per second at most, to keep recordings a sensible size.
+    pub fps: u32,
+}
+
+impl Default for RecordingConfig {
+    fn default() -> Self {
+        Self { fps: 5 }
+    }
+}
+
+/// Alignment grid drawn over the remote screen.
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct GridOverlay {
+    pub enabled: bool,
+    /// Distance between grid lines, in remote pixels.
+    pub spacing: u32,
+}
+
+impl Default for GridOverlay {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            spacing: 50,
+        }
+    }
+}
+
+/// Ctrl+Alt+<digit> shortcuts that send a fixed keysym, for keyboards without F-keys.
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct QuickKeys {
+    pub enabled: bool,
+    /// Keysym to send, keyed by digit.
+    pub keys: BTreeMap<u8, u32>,
+}
+
+impl Default for QuickKeys {
+    fn default() -> Self {
+        // 1-9 send F1-F9, 0 sends F10.
+        let mut keys: BTreeMap<u8, u32> = (1..=9)
+            .map(|digit| (digit, 0xFFBD + digit as u32))
+            .collect();
+        keys.insert(0, 0xFFC7);
+        Self {
+            enabled: false,
+            keys,
+        }
+    }
+}
+
+const CONFIG_FILE: &str = "vnc_config.json";
+
+static CONFIG_PATH: OnceLock<PathBuf> = OnceLock::new();
+
+/// Uses `path` for the config instead of the per-user one. Only the first call
+/// counts, and it has to come before the config is first used.
+pub fn set_config_path(path: PathBuf) {
+    let _ = CONFIG_PATH.set(path);
+}
+
+/// The config file: `--config` if given, otherwise in the per-user config
+/// directory, or the working directory if there is none.
+pub fn config_path() -> &'static Path {
+    CONFIG_PATH.get_or_init(|| {
+        config_dir()
+            .map(|dir| dir.join(CONFIG_FILE))
+            .unwrap_or_else(|| PathBuf::from(CONFIG_FILE))
+    })
+}
+
+#[cfg(windows)]
+fn config_dir() -> Option<PathBuf> {
+    std::env::var_os("APPDATA").map(|dir| Path::new(&dir).join("vnc-client"))
+}
+
+#[cfg(target_os = "macos")]
+fn config_dir() -> Option<PathBuf> {
+    std::env::var_os("HOME")
+        .map(|home| Path::new(&home).join("Library/Application Support/vnc-client"))
+}
+
+#[cfg(not(any(windows, target_os = "macos")))]
+fn config_dir() -> Option<PathBuf> {
+    std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".config")))
+        .map(|dir| dir.join("vnc-client"))
+}
+
+/// Where the log goes with `log_to_file`, next to the config file.
+pub fn log_path() -> PathBuf {
+    config_path().with_file_name("vnc_client.log")
+}
+
+impl Config {
+    /// Reads the config file. An old one in the working directory is moved to
+    /// the config directory first, unless this is a private session, which
+    /// reads it where it is.
+    pub fn load(private: bool) -> Config {
+        let old = Path::new(CONFIG_FILE);
+        let mut path = config_path();
+        if !private {
+            migrate(old, path);
+        } else if !path.exists() {
+            path = old;
+        }
+        if let Ok(content) = std::fs::read_to_string(path) {
+            serde_json::from_str(&content).unwrap_or_default()
+        } else {
+            Config::default()
+        }
+    }
+
+    pub fn save(&self) {
+        let path = config_path();
+        if let Ok(content) = serde_json::to_string_pretty(self) {
+            if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+                let _ = std::fs::create_dir_all(dir);
+            }
+            if let Err(e) = std::fs::write(path, content) {
+                log::warn!("Failed to save config to {}: {}", path.display(), e);
+            }
+        }
+    }
+
+    /// Moves a saved host's settings under a new name, which must not be taken.
+    pub fn rename_host(&mut self, old: &str, new: &str) -> Result<(), String> {
+        let new = new.trim();
+        if new.is_empty() {
+            return Err("The host name can't be empty".to_string());
+        }
+        if new == old {
+            return Ok(());
+        }
+        if self.hosts.contains_key(new) {
+            return Err(format!("{} is already saved", new));
+        }
+        let host_config = self
+            .hosts
+            .remove(old)
+            .ok_or_else(|| format!("{} isn't saved", old))?;
+        self.hosts.insert(new.to_string(), host_config);
+        if self.last_host == old {
+            self.last_host = new.to_string();
+        }
+        Ok(())
+    }
+
+    /// Saved hosts containing `query`, ignoring case, in alphabetical order.
+    pub fn matching_hosts(&self, query: &str) -> Vec<String> {
+        let query = query.trim().to_lowercase();
+        let mut hosts: Vec<_> = self
+            .hosts
+            .keys()
+            .filter(|host| host.to_lowercase().contains(&query))
+            .cloned()
+            .collect();
+        hosts.sort();
+        hosts
+    }
+
+    pub fn remove_host(&mut self, host: &str) {
+        self.hosts.remove(host);
+        if self.last_host == host {
+            self.last_host.clear();
+        }
+    }
+
+    /// The saved hosts as JSON, leaving their passwords out unless `passwords`.
+    pub fn export_hosts(&self, passwords: bool) -> serde_json::Result<String> {
+        let mut list = HostList {
+            hosts: self.hosts.clone(),
+        };
+        if !passwords {
+            for host_config in list.hosts.values_mut() {
+                host_config.password.clear();
+            }
+        }
+        serde_json::to_string_pretty(&list)
+    }
+
+    /// Sorts the hosts of an export into those that can simply be added and
+    /// those that would replace different settings. Hosts saved here exactly as
+    /// exported are skipped. A host exported without its password keeps the one
+    /// saved here.
+    pub fn prepare_import(&self, list: HostList) -> HostImport {
+        let mut import = HostImport {
+            added: HashMap::new(),
+            conflicts: Vec::new(),
+        };
+        for (host, mut host_config) in list.hosts {
+            match self.hosts.get(&host) {
+                Some(saved) => {
+                    if host_config.password.is_empty() {
+                        host_config.password = saved.password.clone();
+                    }
+                    if *saved == host_config {
+                        continue;
+                    }
+                    import.conflicts.push((host, host_config, false));
+                }
+                None => {
+                    import.added.insert(host, host_config);
+                }
+            }
+        }
+        import.conflicts.sort_by(|a, b| a.0.cmp(&b.0));
+        import
+    }
+
+    /// Saves the imported hosts, replacing only the conflicting ones marked so.
+    /// Returns how many were added or replaced.
+    pub fn import_hosts(&mut self, import: HostImport) -> usize {
+        let replaced = import
+            .conflicts
+            .into_iter()
+            .filter(|(_, _, replace)| *replace)
+            .map(|(host, host_config, _)| (host, host_config));
+        let hosts: Vec<_> = import.added.into_iter().chain(replaced).collect();
+        let count = hosts.len();
+        self.hosts.extend(hosts);
+        count
+    }
 }
 
 impl Default for HostConfig {
     fn default() -> Self {
         Self {
             port: "5900".to_string(),
+            username: "".to_string(),
             password: "".to_string(),
             shared: true,
             view_only: false,
-            zoom_fit: false,
+            fit_to_window: false,
+            fill_window: false,
+            stretch_window: false,
+            smooth_scaling: true,
+            display_filter: DisplayFilter::None,
             scale: 1.0,
             preferred_encoding: "ZRLE".to_string(),
             compression_level: 6,
             quality_level: 6,
             allow_copyrect: true,
             disable_clipboard: false,
+            vnc_max_fps: 30,
+            update_visible_only: false,
+            benchmark_encodings: false,
+            capture_mouse: false,
+            relative_pointer: false,
+            pointer_max_rate: 60,
+            button_map: ButtonMap::default(),
+            send_cad_on_connect: false,
+            format_override: FormatOverride::default(),
+            force_32bit: false,
+            colour_depth: ColourDepth::Server,
+            auto_reconnect: false,
+            reconnect_max_attempts: 5,
+            connect_timeout_secs: 10,
+            idle_timeout_mins: 0,
+            server_timeout_secs: 30,
+            bind_address: String::new(),
+            repeater: String::new(),
+            repeater_id: String::new(),
+            mac_address: String::new(),
+            use_vencrypt: false,
+            trust_any_certificate: false,
+            window_width: 0.0,
+            window_height: 0.0,
+            scroll_x: 0.0,
+            scroll_y: 0.0,
+            start_fullscreen: false,
+            resize_remote: false,
         }
     }
 }
+
+/// Moves a config left in the working directory by older versions to `path`,
+/// unless there is one there already.
+fn migrate(old: &Path, path: &Path) {
+    if old == path || path.exists() || !old.exists() {
+        return;
+    }
+    if let Some(dir) = path.parent() {
+        let _ = std::fs::create_dir_all(dir);
+    }
+    // Copied rather than renamed, which fails across file systems.
+    match std::fs::copy(old, path).and_then(|_| std::fs::remove_file(old)) {
+        Ok(()) => log::info!("Moved {} to {}", old.display(), path.display()),
+        Err(e) => log::warn!(
+            "Failed to move {} to {}: {}",
+            old.display(),
+            path.display(),
+            e
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rename_and_remove_host() {
+        let mut config = Config {
+            last_host: "old".to_string(),
+            ..Config::default()
+        };
+        config
+            .hosts
+            .insert("old".to_string(), HostConfig::default());
+        config
+            .hosts
+            .insert("other".to_string(), HostConfig::default());
+
+        assert!(config.rename_host("old", " ").is_err());
+        assert!(config.rename_host("old", "other").is_err());
+        assert!(config.rename_host("old", "new").is_ok());
+        assert!(config.hosts.contains_key("new") && !config.hosts.contains_key("old"));
+        assert_eq!(config.last_host, "new");
+
+        assert_eq!(config.matching_hosts("NE"), vec!["new".to_string()]);
+        assert_eq!(config.matching_hosts("").len(), 2);
+
+        config.remove_host("new");
+        assert!(!config.hosts.contains_key("new"));
+        assert!(config.last_host.is_empty());
+    }
+
+    #[test]
+    fn test_button_map() {
+        let default = ButtonMap::default();
+        assert_eq!(default.apply(0x05, true), 0x05);
+
+        let swapped = ButtonMap {
+            swap_left_right: true,
+            ..ButtonMap::default()
+        };
+        assert_eq!(swapped.apply(0x01, false), 0x04);
+        assert_eq!(swapped.apply(0x06, false), 0x03);
+
+        let chord = ButtonMap {
+            middle: MiddleEmulation::Chord,
+            ..ButtonMap::default()
+        };
+        assert_eq!(chord.apply(0x01, false), 0x01);
+        assert_eq!(chord.apply(0x05, false), 0x02);
+
+        let alt_click = ButtonMap {
+            middle: MiddleEmulation::AltClick,
+            disable_right: true,
+            ..ButtonMap::default()
+        };
+        assert_eq!(alt_click.apply(0x01, false), 0x01);
+        assert_eq!(alt_click.apply(0x01, true), 0x02);
+        assert_eq!(alt_click.apply(0x04, false), 0x00);
+    }
+
+    #[test]
+    fn test_export_and_import_hosts() {
+        let mut config = Config::default();
+        let saved = HostConfig {
+            password: "secret".to_string(),
+            ..HostConfig::default()
+        };
+        config.hosts.insert("a".to_string(), saved.clone());
+        config.hosts.insert("b".to_string(), saved.clone());
+
+        let json = config.export_hosts(false).unwrap();
+        assert!(!json.contains("secret"));
+        assert!(config.export_hosts(true).unwrap().contains("secret"));
+
+        // Here "a" is the same but for the password left out, "b" differs.
+        let mut list: HostList = serde_json::from_str(&json).unwrap();
+        list.hosts.get_mut("b").unwrap().port = "5901".to_string();
+        list.hosts.insert("c".to_string(), HostConfig::default());
+        let mut import = config.prepare_import(list);
+        assert_eq!(import.added.keys().collect::<Vec<_>>(), ["c"]);
+        assert_eq!(import.conflicts.len(), 1);
+        assert_eq!(import.conflicts[0].0, "b");
+        assert_eq!(import.conflicts[0].1.password, "secret");
+
+        import.conflicts[0].2 = true;
+        assert_eq!(config.import_hosts(import), 2);
+        assert_eq!(config.hosts.len(), 3);
+        assert_eq!(config.hosts["a"].password, "secret");
+        assert_eq!(config.hosts["b"].port, "5901");
+    }
+}
@@ -0,0 +1,107 @@
+/* System shortcuts like Alt+Tab and the Windows key are acted on by the OS before
+the window ever sees them. On Windows a low level keyboard hook can take them
+first; elsewhere there is no such hook, and only the combinations egui does see
+reach the remote. */
+
+use eframe::egui;
+
+/// A key taken from the OS while the grab was active.
+pub struct GrabbedKey {
+    pub pressed: bool,
+    pub keysym: u32,
+}
+
+#[cfg(windows)]
+mod imp {
+    use super::GrabbedKey;
+    use crate::keys;
+    use eframe::egui;
+    use std::ptr;
+    use std::sync::atomic::{AtomicBool, AtomicPtr, Ordering};
+    use std::sync::{Mutex, OnceLock};
+    use winapi::shared::minwindef::{LPARAM, LRESULT, WPARAM};
+    use winapi::shared::windef::HHOOK__;
+    use winapi::um::winuser::{
+        CallNextHookEx, GetAsyncKeyState, SetWindowsHookExW, HC_ACTION, KBDLLHOOKSTRUCT,
+        LLKHF_ALTDOWN, VK_APPS, VK_CONTROL, VK_ESCAPE, VK_F4, VK_LWIN, VK_RWIN, VK_TAB,
+        WH_KEYBOARD_LL, WM_KEYDOWN, WM_SYSKEYDOWN,
+    };
+
+    static ACTIVE: AtomicBool = AtomicBool::new(false);
+    static HOOK: AtomicPtr<HHOOK__> = AtomicPtr::new(ptr::null_mut());
+    static KEYS: Mutex<Vec<GrabbedKey>> = Mutex::new(Vec::new());
+    static CONTEXT: OnceLock<egui::Context> = OnceLock::new();
+
+    pub fn set_active(ctx: &egui::Context, active: bool) {
+        ACTIVE.store(active, Ordering::Relaxed);
+        if active && HOOK.load(Ordering::Relaxed).is_null() {
+            let _ = CONTEXT.set(ctx.clone());
+            // The hook runs on this thread, from the window's message loop.
+            let hook = unsafe {
+                SetWindowsHookExW(WH_KEYBOARD_LL, Some(keyboard_proc), ptr::null_mut(), 0)
+            };
+            if hook.is_null() {
+                log::warn!("Could not install the keyboard hook");
+            }
+            HOOK.store(hook, Ordering::Relaxed);
+        }
+    }
+
+    pub fn take_keys() -> Vec<GrabbedKey> {
+        std::mem::take(&mut *KEYS.lock().unwrap())
+    }
+
+    /// The keysym for keys the OS would otherwise act on. Alt and Ctrl themselves
+    /// are let through, the window sees those.
+    fn grabbed_keysym(vk: i32, alt: bool, ctrl: bool) -> Option<u32> {
+        match vk {
+            VK_LWIN => Some(keys::SUPER_L),
+            VK_RWIN => Some(keys::SUPER_R),
+            VK_APPS => Some(0xFF67),
+            VK_TAB if alt => Some(0xFF09),
+            VK_ESCAPE if alt || ctrl => Some(0xFF1B),
+            VK_F4 if alt => Some(0xFFC1),
+            _ => None,
+        }
+    }
+
+    unsafe extern "system" fn keyboard_proc(code: i32, wparam: WPARAM, lparam: LPARAM) -> LRESULT {
+        if code == HC_ACTION && ACTIVE.load(Ordering::Relaxed) {
+            let info = &*(lparam as *const KBDLLHOOKSTRUCT);
+            let alt = info.flags & LLKHF_ALTDOWN != 0;
+            let ctrl = GetAsyncKeyState(VK_CONTROL) < 0;
+            if let Some(keysym) = grabbed_keysym(info.vkCode as i32, alt, ctrl) {
+                let pressed = matches!(wparam as u32, WM_KEYDOWN | WM_SYSKEYDOWN);
+                KEYS.lock().unwrap().push(GrabbedKey { pressed, keysym });
+                if let Some(ctx) = CONTEXT.get() {
+                    ctx.request_repaint();
+                }
+                // Swallowed, so the OS doesn't act on it.
+                return 1;
+            }
+        }
+        CallNextHookEx(ptr::null_mut(), code, wparam, lparam)
+    }
+}
+
+#[cfg(not(windows))]
+mod imp {
+    use super::GrabbedKey;
+    use eframe::egui;
+
+    pub fn set_active(_ctx: &egui::Context, _active: bool) {}
+
+    pub fn take_keys() -> Vec<GrabbedKey> {
+        Vec::new()
+    }
+}
+
+/// Turns the grab on or off. Only keep it on while the remote view has focus.
+pub fn set_active(ctx: &egui::Context, active: bool) {
+    imp::set_active(ctx, active)
+}
+
+/// Keys grabbed since the last call, in order.
+pub fn take_keys() -> Vec<GrabbedKey> {
+    imp::take_keys()
+}
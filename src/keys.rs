@@ -1,4 +1,5 @@
-use egui::Key;
+use crate::config::QuickKeys;
+use egui::{Key, Modifiers};
 
 pub fn map_key(key: Key) -> Option<u32> {
     match key {
@@ -72,3 +73,331 @@ pub fn map_key(key: Key) -> Option<u32> {
         _ => None,
     }
 }
+
+pub const SHIFT_L: u32 = 0xFFE1;
+pub const CONTROL_L: u32 = 0xFFE3;
+pub const ALT_L: u32 = 0xFFE9;
+pub const SUPER_L: u32 = 0xFFEB;
+/// Only the keyboard grab on Windows tells the right Super key apart.
+#[cfg(windows)]
+pub const SUPER_R: u32 = 0xFFEC;
+/// AltGr, on layouts that use it for a third symbol per key.
+const ISO_LEVEL3_SHIFT: u32 = 0xFE03;
+
+/// The keysyms held on the remote for `modifiers`, in the order they're pressed.
+/// egui can't tell left from right, and Windows reports AltGr as Ctrl+Alt, so
+/// with `altgr` set Ctrl+Alt goes to the remote as AltGr.
+fn modifier_keysyms(modifiers: Modifiers, altgr: bool) -> Vec<u32> {
+    let level3 = altgr && modifiers.ctrl && modifiers.alt;
+    [
+        (modifiers.shift, SHIFT_L),
+        (modifiers.ctrl && !level3, CONTROL_L),
+        (modifiers.alt && !level3, ALT_L),
+        (level3, ISO_LEVEL3_SHIFT),
+        (modifiers.mac_cmd, SUPER_L),
+    ]
+    .into_iter()
+    .filter(|&(held, _)| held)
+    .map(|(_, keysym)| keysym)
+    .collect()
+}
+
+/// Key events that take the remote's modifiers from `old` to `new`: releases
+/// first, in reverse order, then presses.
+pub fn modifier_events(old: Modifiers, new: Modifiers, altgr: bool) -> Vec<(bool, u32)> {
+    let old = modifier_keysyms(old, altgr);
+    let new = modifier_keysyms(new, altgr);
+    let released = old
+        .iter()
+        .rev()
+        .filter(|keysym| !new.contains(keysym))
+        .map(|&keysym| (false, keysym));
+    let pressed = new
+        .iter()
+        .filter(|keysym| !old.contains(keysym))
+        .map(|&keysym| (true, keysym));
+    released.chain(pressed).collect()
+}
+
+/// The keysym of function key `n`, from F1 up to F35.
+pub fn function_key(n: u32) -> u32 {
+    0xFFBD + n
+}
+
+pub const CTRL_ALT_DEL: &[u32] = &[CONTROL_L, ALT_L, 0xFFFF];
+/// Opens the Start menu on Windows.
+pub const CTRL_ESC: &[u32] = &[CONTROL_L, 0xFF1B];
+
+/// A key, or keys held together, that a desktop keyboard can't easily send.
+pub struct SpecialKey {
+    pub label: &'static str,
+    /// Pressed in order and released in reverse.
+    pub keysyms: &'static [u32],
+}
+
+pub const SPECIAL_KEYS: &[SpecialKey] = &[
+    SpecialKey {
+        label: "Super",
+        keysyms: &[SUPER_L],
+    },
+    SpecialKey {
+        label: "Menu",
+        keysyms: &[0xFF67],
+    },
+    SpecialKey {
+        label: "Print",
+        keysyms: &[0xFF61],
+    },
+    SpecialKey {
+        label: "Pause",
+        keysyms: &[0xFF13],
+    },
+    SpecialKey {
+        label: "Scroll Lock",
+        keysyms: &[0xFF14],
+    },
+    SpecialKey {
+        label: "Num Lock",
+        keysyms: &[0xFF7F],
+    },
+    SpecialKey {
+        label: "Caps Lock",
+        keysyms: &[0xFFE5],
+    },
+    SpecialKey {
+        label: "Ctrl-Alt-Del",
+        keysyms: CTRL_ALT_DEL,
+    },
+    SpecialKey {
+        label: "Ctrl-Esc",
+        keysyms: CTRL_ESC,
+    },
+    SpecialKey {
+        label: "Alt-Tab",
+        keysyms: &[ALT_L, 0xFF09],
+    },
+    SpecialKey {
+        label: "Alt-F4",
+        keysyms: &[ALT_L, 0xFFC1],
+    },
+];
+
+/// XF86 media keys.
+pub const MEDIA_KEYS: &[SpecialKey] = &[
+    SpecialKey {
+        label: "Mute",
+        keysyms: &[0x1008FF12],
+    },
+    SpecialKey {
+        label: "Volume down",
+        keysyms: &[0x1008FF11],
+    },
+    SpecialKey {
+        label: "Volume up",
+        keysyms: &[0x1008FF13],
+    },
+    SpecialKey {
+        label: "Play",
+        keysyms: &[0x1008FF14],
+    },
+    SpecialKey {
+        label: "Stop",
+        keysyms: &[0x1008FF15],
+    },
+    SpecialKey {
+        label: "Previous",
+        keysyms: &[0x1008FF16],
+    },
+    SpecialKey {
+        label: "Next",
+        keysyms: &[0x1008FF17],
+    },
+];
+
+pub fn digit(key: Key) -> Option<u8> {
+    match key {
+        Key::Num0 => Some(0),
+        Key::Num1 => Some(1),
+        Key::Num2 => Some(2),
+        Key::Num3 => Some(3),
+        Key::Num4 => Some(4),
+        Key::Num5 => Some(5),
+        Key::Num6 => Some(6),
+        Key::Num7 => Some(7),
+        Key::Num8 => Some(8),
+        Key::Num9 => Some(9),
+        _ => None,
+    }
+}
+
+/// Returns the keysym a Ctrl+Alt+<digit> quick key stands for, if enabled and mapped.
+pub fn quick_key(quick_keys: &QuickKeys, key: Key, modifiers: Modifiers) -> Option<u32> {
+    if !quick_keys.enabled || !modifiers.ctrl || !modifiers.alt {
+        return None;
+    }
+    quick_keys.keys.get(&digit(key)?).copied()
+}
+
+/// Something the viewer does locally instead of forwarding the key.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Action {
+    ShowHelp,
+    ToggleFullscreen,
+    ExitFullscreen,
+    ZoomIn,
+    ZoomOut,
+    ZoomReset,
+    ToggleFitToWindow,
+    SendCtrlAltDel,
+    ReleasePointer,
+    Screenshot,
+}
+
+pub struct Hotkey {
+    pub key: Key,
+    pub action: Action,
+    pub description: &'static str,
+}
+
+/// Local hotkeys. They all sit on Ctrl+Alt, away from the remote's own shortcuts,
+/// and avoid the digits used by quick keys.
+pub const HOTKEYS: &[Hotkey] = &[
+    Hotkey {
+        key: Key::H,
+        action: Action::ShowHelp,
+        description: "Show this help",
+    },
+    Hotkey {
+        key: Key::Enter,
+        action: Action::ToggleFullscreen,
+        description: "Toggle full screen",
+    },
+    Hotkey {
+        key: Key::PlusEquals,
+        action: Action::ZoomIn,
+        description: "Zoom in",
+    },
+    Hotkey {
+        key: Key::Minus,
+        action: Action::ZoomOut,
+        description: "Zoom out",
+    },
+    Hotkey {
+        key: Key::Backspace,
+        action: Action::ZoomReset,
+        description: "Zoom to 100%",
+    },
+    Hotkey {
+        key: Key::F,
+        action: Action::ToggleFitToWindow,
+        description: "Toggle fit to window",
+    },
+    Hotkey {
+        key: Key::End,
+        action: Action::SendCtrlAltDel,
+        description: "Send Ctrl-Alt-Del",
+    },
+    Hotkey {
+        key: Key::G,
+        action: Action::ReleasePointer,
+        description: "Release the captured mouse and keyboard",
+    },
+    Hotkey {
+        key: Key::S,
+        action: Action::Screenshot,
+        description: "Save a screenshot",
+    },
+];
+
+pub fn hotkey(key: Key, modifiers: Modifiers) -> Option<Action> {
+    if !modifiers.ctrl || !modifiers.alt {
+        return None;
+    }
+    HOTKEYS
+        .iter()
+        .find(|hotkey| hotkey.key == key)
+        .map(|hotkey| hotkey.action)
+}
+
+/// Keys that work without Ctrl+Alt. Escape is only taken while full screen,
+/// otherwise it goes to the remote like any other key. With the keyboard grabbed
+/// they all go to the remote.
+pub fn window_key(
+    key: Key,
+    modifiers: Modifiers,
+    fullscreen: bool,
+    keyboard_grabbed: bool,
+) -> Option<Action> {
+    if keyboard_grabbed || !modifiers.is_none() {
+        return None;
+    }
+    match key {
+        Key::F11 => Some(Action::ToggleFullscreen),
+        Key::Escape if fullscreen => Some(Action::ExitFullscreen),
+        _ => None,
+    }
+}
+
+pub fn hotkey_label(key: Key) -> String {
+    format!("Ctrl+Alt+{}", key.name())
+}
+
+/// A readable name for the keysyms quick keys typically send.
+pub fn keysym_name(keysym: u32) -> String {
+    match keysym {
+        0xFFBE..=0xFFD5 => format!("F{}", keysym - 0xFFBD),
+        0x20..=0x7E => char::from_u32(keysym).unwrap().to_string(),
+        _ => format!("keysym 0x{:04X}", keysym),
+    }
+}
+
+/// Parses a keysym typed as hex, with or without a `0x` prefix.
+pub fn parse_keysym(text: &str) -> Option<u32> {
+    let text = text.trim();
+    let digits = text
+        .strip_prefix("0x")
+        .or_else(|| text.strip_prefix("0X"))
+        .unwrap_or(text);
+    u32::from_str_radix(digits, 16).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_modifier_events() {
+        let ctrl_alt = Modifiers::CTRL | Modifiers::ALT;
+        assert_eq!(
+            modifier_events(Modifiers::NONE, ctrl_alt, false),
+            vec![(true, CONTROL_L), (true, ALT_L)]
+        );
+        assert_eq!(
+            modifier_events(ctrl_alt, Modifiers::NONE, false),
+            vec![(false, ALT_L), (false, CONTROL_L)]
+        );
+
+        // AltGr is reported as Ctrl+Alt, one modifier after the other.
+        assert_eq!(
+            modifier_events(Modifiers::NONE, Modifiers::CTRL, true),
+            vec![(true, CONTROL_L)]
+        );
+        assert_eq!(
+            modifier_events(Modifiers::CTRL, ctrl_alt, true),
+            vec![(false, CONTROL_L), (true, ISO_LEVEL3_SHIFT)]
+        );
+        assert_eq!(
+            modifier_events(ctrl_alt, Modifiers::NONE, true),
+            vec![(false, ISO_LEVEL3_SHIFT)]
+        );
+
+        assert_eq!(
+            modifier_events(
+                Modifiers::NONE,
+                Modifiers::MAC_CMD | Modifiers::SHIFT,
+                false
+            ),
+            vec![(true, SHIFT_L), (true, SUPER_L)]
+        );
+    }
+}
@@ -0,0 +1,183 @@
+use crate::app::capture::timestamp;
+use log::{Level, Log, Metadata, Record};
+use std::collections::VecDeque;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
+
+/* Keeps the most recent log lines in memory for the Log window, since stderr
+is not visible with the Windows GUI subsystem. Everything is still passed on to
+env_logger as before, and optionally written to a file too. */
+
+const CAPACITY: usize = 100;
+
+/// A log file past this size is moved aside and a new one started.
+const MAX_FILE_SIZE: u64 = 1024 * 1024;
+
+static ENTRIES: Mutex<VecDeque<String>> = Mutex::new(VecDeque::new());
+static FILE: Mutex<Option<LogFile>> = Mutex::new(None);
+static CONSOLE: AtomicBool = AtomicBool::new(true);
+
+/// The log file, moved to `<name>.1` once it gets too big or on the first
+/// start of a new day, so there is at most one old file.
+struct LogFile {
+    path: PathBuf,
+    file: File,
+    size: u64,
+}
+
+impl LogFile {
+    fn open(path: &Path) -> std::io::Result<LogFile> {
+        let stale = std::fs::metadata(path).is_ok_and(|metadata| {
+            metadata.len() >= MAX_FILE_SIZE
+                || metadata
+                    .modified()
+                    .is_ok_and(|modified| day(modified) != day(SystemTime::now()))
+        });
+        if stale {
+            std::fs::rename(path, rotated(path))?;
+        }
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let size = file.metadata()?.len();
+        Ok(LogFile {
+            path: path.to_path_buf(),
+            file,
+            size,
+        })
+    }
+
+    fn write_line(&mut self, line: &str) -> std::io::Result<()> {
+        if self.size >= MAX_FILE_SIZE {
+            std::fs::rename(&self.path, rotated(&self.path))?;
+            *self = LogFile::open(&self.path)?;
+        }
+        writeln!(self.file, "{}", line)?;
+        self.size += line.len() as u64 + 1;
+        Ok(())
+    }
+}
+
+fn rotated(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".1");
+    PathBuf::from(name)
+}
+
+/// Days since 1970, in UTC.
+fn day(time: SystemTime) -> u64 {
+    time.duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or(Duration::ZERO)
+        .as_secs()
+        / 86400
+}
+
+struct BufferedLogger {
+    inner: env_logger::Logger,
+}
+
+impl Log for BufferedLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        self.inner.enabled(metadata)
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.inner.matches(record) {
+            return;
+        }
+        if let Some(ref mut file) = *FILE.lock().unwrap() {
+            let line = format!(
+                "{} {:<5} {}: {}",
+                timestamp(SystemTime::now()),
+                record.level(),
+                record.target(),
+                record.args()
+            );
+            // Nowhere to report a failure to log; the line is only lost.
+            let _ = file.write_line(&line);
+        }
+        // Debug output is per packet, which would flush out everything useful.
+        if record.level() <= Level::Info {
+            let seconds = SystemTime::now()
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .map_or(0, |elapsed| elapsed.as_secs());
+            let line = format!(
+                "{:02}:{:02}:{:02} {:<5} {}",
+                seconds / 3600 % 24,
+                seconds / 60 % 60,
+                seconds % 60,
+                record.level(),
+                record.args()
+            );
+            let mut entries = ENTRIES.lock().unwrap();
+            if entries.len() == CAPACITY {
+                entries.pop_front();
+            }
+            entries.push_back(line);
+        }
+        if CONSOLE.load(Ordering::Relaxed) {
+            self.inner.log(record);
+        }
+    }
+
+    fn flush(&self) {
+        if let Some(ref mut file) = *FILE.lock().unwrap() {
+            let _ = file.file.flush();
+        }
+        self.inner.flush();
+    }
+}
+
+/// Installs the logger, configured from `RUST_LOG` like `env_logger::init`.
+pub fn init() {
+    let inner = env_logger::Builder::from_default_env().build();
+    log::set_max_level(inner.filter());
+    let _ = log::set_boxed_logger(Box::new(BufferedLogger { inner }));
+}
+
+/// Also writes the log to `path`, appending to what is there. The console only
+/// keeps getting it with `console` set.
+pub fn log_to_file(path: &Path, console: bool) -> std::io::Result<()> {
+    let file = LogFile::open(path)?;
+    *FILE.lock().unwrap() = Some(file);
+    CONSOLE.store(console, Ordering::Relaxed);
+    Ok(())
+}
+
+/// Recent log lines, oldest first. Times are UTC.
+pub fn entries() -> Vec<String> {
+    ENTRIES.lock().unwrap().iter().cloned().collect()
+}
+
+pub fn clear() {
+    ENTRIES.lock().unwrap().clear();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_log_file_rotates() {
+        let dir = std::env::temp_dir().join(format!("vnc-log-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("test.log");
+        let _ = std::fs::remove_file(&path);
+        let _ = std::fs::remove_file(rotated(&path));
+
+        let mut file = LogFile::open(&path).unwrap();
+        file.write_line("first").unwrap();
+        file.size = MAX_FILE_SIZE;
+        file.write_line("second").unwrap();
+        assert_eq!(std::fs::read_to_string(rotated(&path)).unwrap(), "first\n");
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "second\n");
+
+        // A file from an earlier run is appended to while it is small and recent.
+        drop(file);
+        LogFile::open(&path).unwrap().write_line("third").unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "second\nthird\n");
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -1,26 +1,65 @@
 #![windows_subsystem = "windows"]
 
+mod address;
 mod app;
 mod config;
+mod keyboard_grab;
 mod keys;
+mod log_buffer;
+mod relative_pointer;
+mod repeater;
+mod scroll;
+mod startup;
+mod uri;
+mod vnc_file;
+mod watchdog;
+mod wol;
 
 use app::{get_app_icon, VncApp};
+use config::Config;
+use startup::Startup;
 
 fn main() {
-    if std::env::var("RUST_LOG").is_err() {
+    // Asking for a log level is asking for it on the console.
+    let console = std::env::var("RUST_LOG").is_ok();
+    if !console {
         std::env::set_var("RUST_LOG", "info");
     }
-    env_logger::init();
+    log_buffer::init();
+
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if let Some(path) = startup::config_override(&args) {
+        config::set_config_path(path);
+    }
+    let config = Config::load(startup::private(&args));
+    let startup = Startup::resolve(args, &config);
+    if config.log_to_file || startup.log_file {
+        let path = config::log_path();
+        if let Err(e) = log_buffer::log_to_file(&path, console) {
+            log::warn!("Failed to open log file {}: {}", path.display(), e);
+        }
+    }
+    if startup.register_uri_handler {
+        match uri::register_handler() {
+            Ok(message) => log::info!("{}", message),
+            Err(e) => {
+                log::error!("{}", e);
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
 
     let options = eframe::NativeOptions {
         initial_window_size: Some(eframe::egui::vec2(800.0, 600.0)),
         icon_data: get_app_icon(),
+        fullscreen: startup.fullscreen,
         ..Default::default()
     };
 
     let _ = eframe::run_native(
         "VNC Remote Desktop",
         options,
-        Box::new(|_cc| Box::new(VncApp::default())),
+        Box::new(|_cc| Box::new(VncApp::new(config, startup))),
     );
 }
@@ -0,0 +1,155 @@
+/* Relative pointer mode: the remote pointer is moved by how far the local one
+moved, not placed where it is, so it can travel as far as it likes in any
+direction. On Windows the local cursor is put back in the middle of the view
+whenever it strays towards an edge; elsewhere eframe can't move it, and it
+stops at the edge of the local screen. */
+
+use eframe::egui::{Pos2, Rect, Vec2};
+
+/// The remote pointer position, driven by local motion.
+#[derive(Default)]
+pub struct RelativePointer {
+    /// In remote pixels, kept fractional so slow motion isn't lost to rounding.
+    remote: Option<(f32, f32)>,
+    /// Where the local cursor was last seen.
+    last: Option<Pos2>,
+    /// Set after a warp until the cursor is seen at its new place.
+    warped_to: Option<Pos2>,
+}
+
+impl RelativePointer {
+    /// Forgets all positions, for when the pointer is released.
+    pub fn reset(&mut self) {
+        *self = RelativePointer::default();
+    }
+
+    /// Moves the remote pointer by the local motion since the last call and
+    /// returns its new position. `start` is where it is now, `scale` how many
+    /// points one remote pixel takes and `size` the remote screen.
+    pub fn motion(
+        &mut self,
+        pos: Pos2,
+        start: (u16, u16),
+        scale: f32,
+        size: (u16, u16),
+    ) -> (u16, u16) {
+        let delta = match (self.last, self.warped_to) {
+            // The warp hasn't been seen yet.
+            (Some(last), Some(_)) if pos == last => Vec2::ZERO,
+            (_, Some(warped_to)) => {
+                self.warped_to = None;
+                pos - warped_to
+            }
+            (Some(last), None) => pos - last,
+            (None, None) => Vec2::ZERO,
+        };
+        self.last = Some(pos);
+
+        let (x, y) = self.remote.unwrap_or((start.0 as f32, start.1 as f32));
+        let scale = scale.max(0.01);
+        let x = (x + delta.x / scale).clamp(0.0, size.0.saturating_sub(1) as f32);
+        let y = (y + delta.y / scale).clamp(0.0, size.1.saturating_sub(1) as f32);
+        self.remote = Some((x, y));
+        (x as u16, y as u16)
+    }
+
+    /// Puts the local cursor back in the middle of `rect` once it is outside the
+    /// middle half, so it never reaches an edge. `ppp` is pixels per point.
+    pub fn recenter(&mut self, rect: Rect, ppp: f32) {
+        let Some(last) = self.last else {
+            return;
+        };
+        if rect.shrink2(rect.size() / 4.0).contains(last) {
+            return;
+        }
+        let center = rect.center();
+        if imp::warp(center, ppp) {
+            self.warped_to = Some(center);
+        }
+    }
+}
+
+#[cfg(windows)]
+mod imp {
+    use eframe::egui::Pos2;
+    use winapi::shared::windef::POINT;
+    use winapi::um::winuser::{ClientToScreen, GetActiveWindow, SetCursorPos};
+
+    /// Moves the cursor to `pos`, in points within the active window.
+    pub fn warp(pos: Pos2, ppp: f32) -> bool {
+        let mut point = POINT {
+            x: (pos.x * ppp) as i32,
+            y: (pos.y * ppp) as i32,
+        };
+        unsafe {
+            let window = GetActiveWindow();
+            !window.is_null()
+                && ClientToScreen(window, &mut point) != 0
+                && SetCursorPos(point.x, point.y) != 0
+        }
+    }
+}
+
+#[cfg(not(windows))]
+mod imp {
+    use eframe::egui::Pos2;
+
+    pub fn warp(_pos: Pos2, _ppp: f32) -> bool {
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use eframe::egui::pos2;
+
+    #[test]
+    fn test_motion() {
+        let mut pointer = RelativePointer::default();
+        // The first sighting only sets where motion is measured from.
+        assert_eq!(
+            pointer.motion(pos2(50.0, 50.0), (100, 100), 2.0, (800, 600)),
+            (100, 100)
+        );
+        assert_eq!(
+            pointer.motion(pos2(60.0, 45.0), (0, 0), 2.0, (800, 600)),
+            (105, 97)
+        );
+        // Half pixels add up.
+        assert_eq!(
+            pointer.motion(pos2(61.0, 45.0), (0, 0), 2.0, (800, 600)),
+            (105, 97)
+        );
+        assert_eq!(
+            pointer.motion(pos2(62.0, 45.0), (0, 0), 2.0, (800, 600)),
+            (106, 97)
+        );
+        // It stops at the remote edges.
+        assert_eq!(
+            pointer.motion(pos2(-2000.0, 2000.0), (0, 0), 2.0, (800, 600)),
+            (0, 599)
+        );
+    }
+
+    #[test]
+    fn test_motion_after_warp() {
+        let mut pointer = RelativePointer::default();
+        pointer.motion(pos2(90.0, 10.0), (100, 100), 1.0, (800, 600));
+        pointer.warped_to = Some(pos2(50.0, 50.0));
+        // Until the cursor shows up at the warp target nothing moves.
+        assert_eq!(
+            pointer.motion(pos2(90.0, 10.0), (0, 0), 1.0, (800, 600)),
+            (100, 100)
+        );
+        // The jump to the target isn't motion, anything past it is.
+        assert_eq!(
+            pointer.motion(pos2(52.0, 50.0), (0, 0), 1.0, (800, 600)),
+            (102, 100)
+        );
+        assert_eq!(
+            pointer.motion(pos2(53.0, 50.0), (0, 0), 1.0, (800, 600)),
+            (103, 100)
+        );
+    }
+}
@@ -0,0 +1,119 @@
+use std::io::{Error, ErrorKind, Read, Write};
+
+/* UltraVNC repeater: the viewer connects to the repeater, which greets it with a
+fake protocol version, and then names the server it wants in a fixed size
+buffer. In mode I that is a host:port the repeater connects to itself; in mode
+II an ID that a server has registered under by connecting out to the repeater.
+After that the repeater just passes bytes on, and the server's own handshake
+follows. */
+
+/// Port the repeater takes viewers on, when the address doesn't say.
+pub const DEFAULT_PORT: u16 = 5901;
+
+const GREETING: &[u8; 12] = b"RFB 000.000\n";
+const TARGET_LENGTH: usize = 250;
+
+#[derive(Debug, PartialEq)]
+pub enum Target {
+    /// Mode I: the repeater connects to this `host:port`.
+    Host(String, u16),
+    /// Mode II: a server waiting at the repeater under this ID.
+    Id(String),
+}
+
+impl Target {
+    /// Mode II when there is an ID, mode I to `host` and `port` otherwise.
+    pub fn new(id: &str, host: &str, port: u16) -> Target {
+        match id.trim() {
+            "" => Target::Host(host.to_string(), port),
+            id => Target::Id(id.trim_start_matches("ID:").to_string()),
+        }
+    }
+
+    fn encode(&self) -> Result<[u8; TARGET_LENGTH], Error> {
+        let text = match self {
+            Target::Host(host, port) if host.contains(':') => format!("[{}]:{}", host, port),
+            Target::Host(host, port) => format!("{}:{}", host, port),
+            Target::Id(id) => format!("ID:{}", id),
+        };
+        // Zero terminated, so one byte short of the buffer at most.
+        if text.len() >= TARGET_LENGTH {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "repeater target too long",
+            ));
+        }
+        let mut buffer = [0u8; TARGET_LENGTH];
+        buffer[..text.len()].copy_from_slice(text.as_bytes());
+        Ok(buffer)
+    }
+}
+
+/// Asks the repeater on `stream` for `target`. Afterwards the stream carries
+/// the server's RFB handshake.
+pub fn handshake<S: Read + Write>(stream: &mut S, target: &Target) -> Result<(), Error> {
+    let mut greeting = [0u8; 12];
+    stream.read_exact(&mut greeting)?;
+    if &greeting != GREETING {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            "not an UltraVNC repeater, it answered with something else",
+        ));
+    }
+    stream.write_all(&target.encode()?)?;
+    stream.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    /// The repeater's side: what it sent first, then what the viewer wrote.
+    struct Repeater {
+        input: Cursor<Vec<u8>>,
+        output: Vec<u8>,
+    }
+
+    impl Read for Repeater {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            self.input.read(buf)
+        }
+    }
+
+    impl Write for Repeater {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.output.write(buf)
+        }
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn handshake_with(greeting: &[u8], target: &Target) -> Result<Vec<u8>, Error> {
+        let mut repeater = Repeater {
+            input: Cursor::new(greeting.to_vec()),
+            output: Vec::new(),
+        };
+        handshake(&mut repeater, target)?;
+        Ok(repeater.output)
+    }
+
+    #[test]
+    fn test_handshake() {
+        let sent = handshake_with(GREETING, &Target::new("ID:1234", "ignored", 5900)).unwrap();
+        assert_eq!(sent.len(), TARGET_LENGTH);
+        assert_eq!(&sent[..8], b"ID:1234\0");
+        assert_eq!(Target::new(" 1234 ", "", 0), Target::Id("1234".to_string()));
+
+        let sent = handshake_with(GREETING, &Target::new("", "fe80::1", 5901)).unwrap();
+        assert_eq!(&sent[..15], b"[fe80::1]:5901\0");
+
+        let error = handshake_with(b"RFB 003.008\n", &Target::Id("1".to_string()));
+        assert_eq!(error.unwrap_err().kind(), ErrorKind::InvalidData);
+        let error = handshake_with(b"RFB", &Target::Id("1".to_string()));
+        assert_eq!(error.unwrap_err().kind(), ErrorKind::UnexpectedEof);
+        let error = Target::Id("9".repeat(TARGET_LENGTH)).encode();
+        assert_eq!(error.unwrap_err().kind(), ErrorKind::InvalidInput);
+    }
+}
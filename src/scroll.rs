@@ -0,0 +1,82 @@
+use egui::Vec2;
+
+/// egui turns one mouse wheel notch into this many points, so a notch maps to
+/// exactly one wheel click while touchpad scrolling accumulates towards one.
+const POINTS_PER_CLICK: f32 = 50.0;
+
+/// A fast fling can't flood the server; the excess is dropped.
+const MAX_CLICKS_PER_FRAME: i32 = 10;
+
+// Wheel buttons in the RFB pointer event button mask.
+pub const WHEEL_UP: u8 = 1 << 3;
+pub const WHEEL_DOWN: u8 = 1 << 4;
+pub const WHEEL_LEFT: u8 = 1 << 5;
+pub const WHEEL_RIGHT: u8 = 1 << 6;
+
+/// Turns smooth scroll deltas into discrete wheel clicks, keeping the
+/// fractional remainder of each axis between frames.
+#[derive(Default)]
+pub struct ScrollAccumulator {
+    remainder: Vec2,
+}
+
+impl ScrollAccumulator {
+    /// Returns the wheel button to click and how often, for each axis.
+    pub fn clicks(&mut self, delta: Vec2) -> impl Iterator<Item = (u8, u32)> {
+        let x = take_clicks(&mut self.remainder.x, delta.x);
+        let y = take_clicks(&mut self.remainder.y, delta.y);
+        // egui deltas are positive when scrolling towards the top left.
+        [
+            (
+                if x > 0 { WHEEL_LEFT } else { WHEEL_RIGHT },
+                x.unsigned_abs(),
+            ),
+            (if y > 0 { WHEEL_UP } else { WHEEL_DOWN }, y.unsigned_abs()),
+        ]
+        .into_iter()
+        .filter(|(_, count)| *count > 0)
+    }
+}
+
+fn take_clicks(remainder: &mut f32, delta: f32) -> i32 {
+    // Changing direction starts over rather than first cancelling out the
+    // partial scroll in the old direction.
+    if delta * *remainder < 0.0 {
+        *remainder = 0.0;
+    }
+    *remainder += delta;
+    let clicks = (*remainder / POINTS_PER_CLICK).trunc();
+    *remainder -= clicks * POINTS_PER_CLICK;
+    (clicks as i32).clamp(-MAX_CLICKS_PER_FRAME, MAX_CLICKS_PER_FRAME)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn clicks(scroll: &mut ScrollAccumulator, x: f32, y: f32) -> Vec<(u8, u32)> {
+        scroll.clicks(Vec2::new(x, y)).collect()
+    }
+
+    #[test]
+    fn test_clicks() {
+        let mut scroll = ScrollAccumulator::default();
+        // Wheel notches.
+        assert_eq!(clicks(&mut scroll, 0.0, 50.0), [(WHEEL_UP, 1)]);
+        assert_eq!(clicks(&mut scroll, 0.0, -100.0), [(WHEEL_DOWN, 2)]);
+
+        // Touchpad: small deltas add up, per axis.
+        assert!(clicks(&mut scroll, 30.0, -30.0).is_empty());
+        assert_eq!(
+            clicks(&mut scroll, 30.0, -30.0),
+            [(WHEEL_LEFT, 1), (WHEEL_DOWN, 1)]
+        );
+
+        // Reversing drops the partial scroll in the old direction.
+        assert!(clicks(&mut scroll, 0.0, 40.0).is_empty());
+        assert_eq!(clicks(&mut scroll, 0.0, 10.0), [(WHEEL_UP, 1)]);
+
+        // Flings are capped.
+        assert_eq!(clicks(&mut scroll, -5000.0, 0.0), [(WHEEL_RIGHT, 10)]);
+    }
+}
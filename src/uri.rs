@@ -0,0 +1,233 @@
+use std::path::Path;
+
+/* vnc:// URIs (RFC 7869), as passed by a browser to the registered handler:
+vnc://[user[:password]@]host[:port][/...]. Unlike the host field, the port in a
+URI is always a TCP port and never a display number. */
+
+const SCHEME: &str = "vnc://";
+
+#[derive(Debug, Default, PartialEq)]
+pub struct VncUri {
+    pub host: String,
+    pub port: Option<u16>,
+    pub username: Option<String>,
+    pub password: Option<String>,
+}
+
+pub fn is_vnc_uri(arg: &str) -> bool {
+    arg.get(..SCHEME.len())
+        .is_some_and(|scheme| scheme.eq_ignore_ascii_case(SCHEME))
+}
+
+pub fn parse(uri: &str) -> Result<VncUri, String> {
+    let invalid = |reason: &str| format!("Invalid VNC address {:?}: {}", uri, reason);
+    if !is_vnc_uri(uri) {
+        return Err(invalid("it doesn't start with vnc://"));
+    }
+    let rest = &uri[SCHEME.len()..];
+    // Anything after the authority (a path, query or fragment) means nothing here.
+    let authority = rest.split(['/', '?', '#']).next().unwrap_or_default();
+
+    let (userinfo, address) = match authority.rsplit_once('@') {
+        Some((userinfo, address)) => (Some(userinfo), address),
+        None => (None, authority),
+    };
+    let (username, password) = match userinfo {
+        Some(userinfo) => {
+            let (username, password) = match userinfo.split_once(':') {
+                Some((username, password)) => (username, Some(password)),
+                None => (userinfo, None),
+            };
+            let username = decode(username).ok_or_else(|| invalid("bad escape in user"))?;
+            let password = password
+                .map(decode)
+                .map(|password| password.ok_or_else(|| invalid("bad escape in password")))
+                .transpose()?;
+            (Some(username).filter(|name| !name.is_empty()), password)
+        }
+        None => (None, None),
+    };
+
+    let (host, port) = if let Some(rest) = address.strip_prefix('[') {
+        let (host, rest) = rest
+            .split_once(']')
+            .ok_or_else(|| invalid("unclosed [ around the IPv6 address"))?;
+        let port = match rest {
+            "" => None,
+            rest => Some(
+                rest.strip_prefix(':')
+                    .ok_or_else(|| invalid("junk after the IPv6 address"))?,
+            ),
+        };
+        (host, port)
+    } else {
+        match address.split_once(':') {
+            Some((host, port)) => (host, Some(port)),
+            None => (address, None),
+        }
+    };
+    let host = decode(host).ok_or_else(|| invalid("bad escape in host"))?;
+    if host.is_empty() {
+        return Err(invalid("no host"));
+    }
+    let port = match port {
+        Some("") | None => None,
+        Some(port) => Some(port.parse().map_err(|_| invalid("bad port"))?),
+    };
+
+    Ok(VncUri {
+        host,
+        port,
+        username,
+        password,
+    })
+}
+
+/// Undoes %XX escapes. `None` if an escape is malformed or the result isn't UTF-8.
+fn decode(text: &str) -> Option<String> {
+    let mut bytes = Vec::with_capacity(text.len());
+    let mut rest = text.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        if byte == b'%' {
+            let hex = std::str::from_utf8(tail.get(..2)?).ok()?;
+            bytes.push(u8::from_str_radix(hex, 16).ok()?);
+            rest = &tail[2..];
+        } else {
+            bytes.push(byte);
+            rest = tail;
+        }
+    }
+    String::from_utf8(bytes).ok()
+}
+
+/// Makes this executable the handler for vnc:// links, for the current user.
+pub fn register_handler() -> Result<String, String> {
+    let exe = std::env::current_exe().map_err(|e| e.to_string())?;
+    register(&exe)
+}
+
+#[cfg(windows)]
+fn register(exe: &Path) -> Result<String, String> {
+    let key = r"HKCU\Software\Classes\vnc";
+    let command = format!("\"{}\" \"%1\"", exe.display());
+    let entries = [
+        (key.to_string(), None, "URL:VNC Protocol"),
+        (key.to_string(), Some("URL Protocol"), ""),
+        (
+            format!(r"{}\shell\open\command", key),
+            None,
+            command.as_str(),
+        ),
+    ];
+    for (key, name, data) in entries {
+        let mut reg = std::process::Command::new("reg");
+        reg.args(["add", key.as_str(), "/f", "/d", data]);
+        match name {
+            Some(name) => reg.args(["/v", name]),
+            None => reg.arg("/ve"),
+        };
+        let status = reg
+            .status()
+            .map_err(|e| format!("Failed to run reg: {}", e))?;
+        if !status.success() {
+            return Err(format!("reg add {} failed", key));
+        }
+    }
+    Ok(format!("Registered {} for vnc:// links", exe.display()))
+}
+
+#[cfg(all(unix, not(target_os = "macos")))]
+fn register(exe: &Path) -> Result<String, String> {
+    let data_home = std::env::var_os("XDG_DATA_HOME")
+        .map(std::path::PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".local/share")))
+        .ok_or("Neither XDG_DATA_HOME nor HOME is set")?;
+    let applications = data_home.join("applications");
+    let path = applications.join("vnc-client.desktop");
+    std::fs::create_dir_all(&applications)
+        .and_then(|()| std::fs::write(&path, desktop_entry(exe)))
+        .map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+
+    let status = std::process::Command::new("xdg-mime")
+        .args(["default", "vnc-client.desktop", "x-scheme-handler/vnc"])
+        .status()
+        .map_err(|e| format!("Failed to run xdg-mime: {}", e))?;
+    if !status.success() {
+        return Err("xdg-mime failed to set the vnc:// handler".to_string());
+    }
+    Ok(format!(
+        "Wrote {} and registered it for vnc:// links",
+        path.display()
+    ))
+}
+
+#[cfg(not(any(windows, all(unix, not(target_os = "macos")))))]
+fn register(_exe: &Path) -> Result<String, String> {
+    Err("Registering a vnc:// handler isn't supported on this platform".to_string())
+}
+
+#[cfg(all(unix, not(target_os = "macos")))]
+fn desktop_entry(exe: &Path) -> String {
+    format!(
+        "[Desktop Entry]\n\
+         Type=Application\n\
+         Name=VNC Remote Desktop\n\
+         Exec=\"{}\" %u\n\
+         Terminal=false\n\
+         NoDisplay=true\n\
+         MimeType=x-scheme-handler/vnc;\n",
+        exe.display()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn uri(
+        host: &str,
+        port: Option<u16>,
+        username: Option<&str>,
+        password: Option<&str>,
+    ) -> VncUri {
+        VncUri {
+            host: host.to_string(),
+            port,
+            username: username.map(str::to_string),
+            password: password.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(parse("vnc://server"), Ok(uri("server", None, None, None)));
+        assert_eq!(
+            parse("VNC://admin@server:5901/"),
+            Ok(uri("server", Some(5901), Some("admin"), None))
+        );
+        // A port in a URI is never a display number.
+        assert_eq!(
+            parse("vnc://server:1"),
+            Ok(uri("server", Some(1), None, None))
+        );
+        assert_eq!(
+            parse("vnc://j%C3%B6rg:p%40ss%3Aword@[fe80::1]:5900?x=1"),
+            Ok(uri("fe80::1", Some(5900), Some("jörg"), Some("p@ss:word")))
+        );
+        assert_eq!(
+            parse("vnc://:secret@server"),
+            Ok(uri("server", None, None, Some("secret")))
+        );
+
+        assert!(parse("server:5901").is_err());
+        assert!(parse("vnc://").is_err());
+        assert!(parse("vnc://admin@").is_err());
+        assert!(parse("vnc://server:port").is_err());
+        assert!(parse("vnc://server:70000").is_err());
+        assert!(parse("vnc://[fe80::1:5900").is_err());
+        assert!(parse("vnc://[fe80::1]5900").is_err());
+        assert!(parse("vnc://fe80::1").is_err());
+        assert!(parse("vnc://ser%2").is_err());
+        assert!(parse("vnc://%FF").is_err());
+    }
+}
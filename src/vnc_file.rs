@@ -0,0 +1,197 @@
+use crate::address;
+use crate::config::HostConfig;
+use std::path::Path;
+
+/* Connection files as saved by TightVNC and RealVNC viewers: INI text with a
+[Connection] section for where to connect and an [Options] section. Key names
+differ in case and underscores between the two, so they are compared without
+either. Anything not understood is skipped. */
+
+#[derive(Debug, Default, PartialEq)]
+pub struct VncFile {
+    pub host: String,
+    pub port: Option<u16>,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    pub preferred_encoding: Option<String>,
+    pub compression_level: Option<u8>,
+    pub quality_level: Option<u8>,
+    pub view_only: Option<bool>,
+    pub shared: Option<bool>,
+    pub fullscreen: Option<bool>,
+}
+
+pub fn is_vnc_file(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("vnc"))
+}
+
+pub fn read(path: &Path) -> Result<VncFile, String> {
+    let text =
+        std::fs::read(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    parse(&String::from_utf8_lossy(&text))
+        .map_err(|e| format!("Failed to open {}: {}", path.display(), e))
+}
+
+pub fn parse(text: &str) -> Result<VncFile, String> {
+    let mut file = VncFile::default();
+    let mut port = None;
+    for line in text.lines() {
+        let line = line.trim();
+        if line.starts_with(['[', ';', '#']) {
+            continue;
+        }
+        let Some((key, value)) = line.split_once('=') else {
+            continue;
+        };
+        let key = key.trim().to_ascii_lowercase().replace('_', "");
+        let value = value.trim();
+        match key.as_str() {
+            "host" => file.host = value.to_string(),
+            "port" => port = value.parse().ok(),
+            "username" => file.username = Some(value.to_string()),
+            "password" => file.password = unobfuscate(value),
+            "preferredencoding" => file.preferred_encoding = encoding(value),
+            "compresslevel" => file.compression_level = value.parse().ok(),
+            "quality" | "qualitylevel" => file.quality_level = value.parse().ok(),
+            "viewonly" => file.view_only = flag(value),
+            "shared" => file.shared = flag(value),
+            "fullscreen" => file.fullscreen = flag(value),
+            _ => {}
+        }
+    }
+    // The host can carry a display or port of its own, which a Port line beats.
+    if let Some((host, host_port)) = address::split_host_port(&file.host) {
+        file.host = host;
+        file.port = Some(host_port);
+    }
+    file.port = port.or(file.port);
+    if file.host.is_empty() {
+        return Err("no host in the file".to_string());
+    }
+    Ok(file)
+}
+
+impl VncFile {
+    /// Overrides `config` with what the file sets.
+    pub fn apply(&self, config: &mut HostConfig) {
+        if let Some(port) = self.port {
+            config.port = port.to_string();
+        }
+        if let Some(ref username) = self.username {
+            config.username = username.clone();
+        }
+        if let Some(ref password) = self.password {
+            config.password = password.clone();
+        }
+        if let Some(ref encoding) = self.preferred_encoding {
+            config.preferred_encoding = encoding.clone();
+        }
+        if let Some(level) = self.compression_level {
+            config.compression_level = level.min(9);
+        }
+        if let Some(level) = self.quality_level {
+            config.quality_level = level.min(9);
+        }
+        if let Some(view_only) = self.view_only {
+            config.view_only = view_only;
+        }
+        if let Some(shared) = self.shared {
+            config.shared = shared;
+        }
+        if let Some(fullscreen) = self.fullscreen {
+            config.start_fullscreen = fullscreen;
+        }
+    }
+}
+
+/// Passwords are saved as the hex of their DES encryption under a fixed key.
+fn unobfuscate(hex: &str) -> Option<String> {
+    if hex.is_empty() || !hex.len().is_multiple_of(2) || !hex.is_ascii() {
+        return None;
+    }
+    let saved = (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).ok())
+        .collect::<Option<Vec<u8>>>()?;
+    let password = vnc::unobfuscate_password(&saved);
+    Some(String::from_utf8_lossy(&password).into_owned())
+}
+
+/// The encoding by name, as RealVNC writes it, or by its RFB number, as
+/// TightVNC does. `None` for one this viewer doesn't prefer.
+fn encoding(value: &str) -> Option<String> {
+    let name = match value.to_ascii_lowercase().as_str() {
+        "0" | "raw" => "Raw",
+        "5" | "hextile" => "Hextile",
+        "7" | "tight" => "Tight",
+        "15" | "trle" => "TRLE",
+        "16" | "zrle" => "ZRLE",
+        _ => return None,
+    };
+    Some(name.to_string())
+}
+
+fn flag(value: &str) -> Option<bool> {
+    match value.to_ascii_lowercase().as_str() {
+        "1" | "true" | "yes" => Some(true),
+        "0" | "false" | "no" => Some(false),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_tightvnc() {
+        let text = "[connection]\r\n\
+            host=192.168.1.5\r\n\
+            port=5901\r\n\
+            password=dbd83cfd727a1458\r\n\
+            [options]\r\n\
+            use_encoding_1=1\r\n\
+            preferred_encoding=7\r\n\
+            viewonly=0\r\n\
+            fullscreen=1\r\n\
+            compresslevel=6\r\n\
+            quality=8\r\n";
+        assert_eq!(
+            parse(text),
+            Ok(VncFile {
+                host: "192.168.1.5".to_string(),
+                port: Some(5901),
+                password: Some("password".to_string()),
+                preferred_encoding: Some("Tight".to_string()),
+                compression_level: Some(6),
+                quality_level: Some(8),
+                view_only: Some(false),
+                fullscreen: Some(true),
+                ..VncFile::default()
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_realvnc() {
+        let text = "[Connection]\nHost=server:2\nUserName=admin\n\
+            [Options]\nPreferredEncoding=ZRLE\nShared=0\nFullScreen=false\n";
+        let file = parse(text).unwrap();
+        assert_eq!((file.host.as_str(), file.port), ("server", Some(5902)));
+        assert_eq!(file.preferred_encoding.as_deref(), Some("ZRLE"));
+        assert_eq!((file.shared, file.fullscreen), (Some(false), Some(false)));
+
+        let mut config = HostConfig::default();
+        file.apply(&mut config);
+        assert_eq!(config.port, "5902");
+        assert_eq!(config.username, "admin");
+        assert!(!config.shared);
+        // What the file leaves out stays as it was.
+        assert_eq!(config.password, HostConfig::default().password);
+
+        assert!(parse("[Connection]\nPort=5900\n").is_err());
+        assert!(is_vnc_file(Path::new("saved/office.VNC")));
+        assert!(!is_vnc_file(Path::new("office.vnc.txt")));
+    }
+}
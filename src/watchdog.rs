@@ -0,0 +1,111 @@
+use std::time::{Duration, Instant};
+
+/* Notices a connection that died without closing, such as when the network
+drops silently: the socket stays open and nothing more arrives. A server with
+an unchanging screen is quiet too, so before giving up it is probed with a
+request it has to answer. Any bytes received count, so a large frame crawling
+over a slow link keeps the connection alive. The connection is only given up
+once a probe went unanswered, so a stalled UI or a laptop coming back from
+sleep asks the server first rather than dropping a healthy session. */
+
+#[derive(Debug, PartialEq)]
+pub enum Check {
+    Alive,
+    /// Quiet for half the timeout; ask the server for something.
+    Probe,
+    /// The probe went unanswered for the other half.
+    Stale,
+}
+
+pub struct Watchdog {
+    heard_at: Instant,
+    bytes_received: u64,
+    probed_at: Option<Instant>,
+}
+
+impl Watchdog {
+    pub fn new(now: Instant, bytes_received: u64) -> Watchdog {
+        Watchdog {
+            heard_at: now,
+            bytes_received,
+            probed_at: None,
+        }
+    }
+
+    /// Checks the client's received byte count as of `now`.
+    pub fn check(&mut self, now: Instant, bytes_received: u64, timeout: Duration) -> Check {
+        if bytes_received != self.bytes_received {
+            *self = Watchdog::new(now, bytes_received);
+            return Check::Alive;
+        }
+        match self.probed_at {
+            Some(probed_at) if now.saturating_duration_since(probed_at) >= timeout / 2 => {
+                Check::Stale
+            }
+            Some(_) => Check::Alive,
+            None if now.saturating_duration_since(self.heard_at) >= timeout / 2 => {
+                self.probed_at = Some(now);
+                Check::Probe
+            }
+            None => Check::Alive,
+        }
+    }
+
+    /// When the next check could change anything.
+    pub fn next_check(&self, timeout: Duration) -> Instant {
+        self.probed_at.unwrap_or(self.heard_at) + timeout / 2
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_probe_then_stale() {
+        let start = Instant::now();
+        let timeout = Duration::from_secs(30);
+        let at = |secs| start + Duration::from_secs(secs);
+        let mut watchdog = Watchdog::new(start, 100);
+
+        assert_eq!(watchdog.check(at(10), 100, timeout), Check::Alive);
+        assert_eq!(watchdog.next_check(timeout), at(15));
+        assert_eq!(watchdog.check(at(15), 100, timeout), Check::Probe);
+        assert_eq!(watchdog.check(at(20), 100, timeout), Check::Alive);
+        assert_eq!(watchdog.next_check(timeout), at(30));
+        assert_eq!(watchdog.check(at(30), 100, timeout), Check::Stale);
+    }
+
+    #[test]
+    fn test_bytes_reset_the_clock() {
+        let start = Instant::now();
+        let timeout = Duration::from_secs(30);
+        let at = |secs| start + Duration::from_secs(secs);
+        let mut watchdog = Watchdog::new(start, 0);
+
+        assert_eq!(watchdog.check(at(15), 0, timeout), Check::Probe);
+        // The answer to the probe.
+        assert_eq!(watchdog.check(at(16), 12, timeout), Check::Alive);
+        assert_eq!(watchdog.check(at(40), 12, timeout), Check::Probe);
+        // The probe gets half the timeout from when it went out.
+        assert_eq!(watchdog.check(at(46), 12, timeout), Check::Alive);
+        assert_eq!(watchdog.next_check(timeout), at(55));
+        assert_eq!(watchdog.check(at(55), 12, timeout), Check::Stale);
+    }
+
+    #[test]
+    fn test_stall_probes_first() {
+        let start = Instant::now();
+        let timeout = Duration::from_secs(30);
+        let at = |secs| start + Duration::from_secs(secs);
+        let mut watchdog = Watchdog::new(start, 0);
+
+        // Not checked for longer than the timeout, say after sleeping.
+        assert_eq!(watchdog.check(at(100), 0, timeout), Check::Probe);
+        assert_eq!(watchdog.check(at(101), 7, timeout), Check::Alive);
+
+        assert_eq!(watchdog.check(at(200), 7, timeout), Check::Probe);
+        assert_eq!(watchdog.check(at(214), 7, timeout), Check::Alive);
+        assert_eq!(watchdog.check(at(215), 7, timeout), Check::Stale);
+    }
+}
@@ -0,0 +1,64 @@
+use std::net::{Ipv4Addr, UdpSocket};
+
+/* Wake-on-LAN: six 0xFF bytes and then the MAC address sixteen times, sent as a
+UDP broadcast. Only reaches machines on the local network segment. */
+
+const PORT: u16 = 9;
+
+/// Reads a MAC address written as six hex pairs, separated by `:` or `-` or
+/// not at all.
+pub fn parse_mac(text: &str) -> Option<[u8; 6]> {
+    let hex: String = text
+        .trim()
+        .chars()
+        .filter(|c| !matches!(c, ':' | '-'))
+        .collect();
+    if hex.len() != 12 || !hex.is_ascii() {
+        return None;
+    }
+    let mut mac = [0u8; 6];
+    for (i, byte) in mac.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16).ok()?;
+    }
+    Some(mac)
+}
+
+fn magic_packet(mac: [u8; 6]) -> [u8; 102] {
+    let mut packet = [0xFF; 102];
+    for copy in packet[6..].chunks_exact_mut(6) {
+        copy.copy_from_slice(&mac);
+    }
+    packet
+}
+
+/// Broadcasts the wake-up packet for `mac`.
+pub fn wake(mac: [u8; 6]) -> std::io::Result<()> {
+    let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0))?;
+    socket.set_broadcast(true)?;
+    socket.send_to(&magic_packet(mac), (Ipv4Addr::BROADCAST, PORT))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_mac() {
+        let mac = [0x00, 0x1A, 0x2B, 0x3C, 0x4D, 0x5E];
+        assert_eq!(parse_mac("00:1a:2b:3c:4d:5e"), Some(mac));
+        assert_eq!(parse_mac(" 00-1A-2B-3C-4D-5E "), Some(mac));
+        assert_eq!(parse_mac("001A2B3C4D5E"), Some(mac));
+        assert_eq!(parse_mac("00:1a:2b:3c:4d"), None);
+        assert_eq!(parse_mac("00:1a:2b:3c:4d:5g"), None);
+        assert_eq!(parse_mac("00:1a:2b:3c:4d:é"), None);
+    }
+
+    #[test]
+    fn test_magic_packet() {
+        let mac = [1, 2, 3, 4, 5, 6];
+        let packet = magic_packet(mac);
+        assert_eq!(packet[..6], [0xFF; 6]);
+        assert!(packet[6..].chunks(6).all(|copy| copy == mac));
+    }
+}
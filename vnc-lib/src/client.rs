@@ -1,16 +1,24 @@
 use log::{debug, info, trace};
-use std::io::{Read, Write};
+use std::io::{BufWriter, Read, Write};
 
-use crate::security::des;
-use crate::{protocol, zrle, Colour, Error, Rect, Result};
-use byteorder::{BigEndian, ReadBytesExt};
+use crate::clipboard::{self, ExtendedClipboard};
+use crate::security::{des, mslogon};
+#[cfg(feature = "vencrypt")]
+use crate::security::{vencrypt, TlsStream, X509_VNC};
+use crate::{hextile, protocol, tight, zrle, Colour, Error, Rect, Result};
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
 use protocol::Message;
 #[cfg(feature = "apple-auth")]
 use security::apple_auth;
 use std::net::{Shutdown, TcpStream};
-use std::sync::mpsc::{channel, Receiver, Sender, TryRecvError};
+use std::sync::atomic::{AtomicU64, AtomicU8, Ordering};
+use std::sync::mpsc::{
+    channel, sync_channel, Receiver, RecvTimeoutError, Sender, SyncSender, TryRecvError,
+    TrySendError,
+};
 use std::sync::{Arc, Mutex};
 use std::thread;
+use std::time::{Duration, Instant};
 
 #[derive(Debug)]
 #[non_exhaustive]
@@ -18,6 +26,10 @@ pub enum AuthMethod {
     None,
     Password,
     AppleRemoteDesktop,
+    /// UltraVNC MS-Logon II, with a Windows or Unix account.
+    MsLogon,
+    /// TLS with a server certificate, then a login inside the encrypted session.
+    VeNCrypt,
     /* more to come */
 }
 
@@ -27,13 +39,43 @@ pub enum AuthChoice {
     None,
     Password([u8; 8]),
     AppleRemoteDesktop(String, String),
+    /// Username and password.
+    MsLogon(String, String),
+    /// Needs the `vencrypt` feature. The username is only sent if the server
+    /// accepts a plain login; otherwise the password is used for VNC authentication.
+    VeNCrypt {
+        /// Host name the certificate must be valid for.
+        server_name: String,
+        /// Off to accept any certificate, such as a self-signed one.
+        verify_certificate: bool,
+        username: String,
+        password: String,
+    },
     /* more to come */
 }
 
+/// Who changed the screen size, as told by an ExtendedDesktopSize update.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResizeReason {
+    Server,
+    /// This client asked for it.
+    Client,
+    OtherClient,
+}
+
 #[derive(Debug)]
 pub enum Event {
     Disconnected(Option<Error>),
     Resize(u16, u16),
+    /// A resize from ExtendedDesktopSize, with the screen layout. When this client
+    /// asked for the resize, a nonzero `status` means it was refused.
+    ExtendedResize {
+        width: u16,
+        height: u16,
+        reason: ResizeReason,
+        status: u16,
+        screens: Vec<protocol::Screen>,
+    },
     SetColourMap {
         first_colour: u16,
         colours: Vec<Colour>,
@@ -52,13 +94,102 @@ pub enum Event {
     },
     Clipboard(String),
     Bell,
+    ExtendedClipboard(ExtendedClipboard),
+}
+
+/// Running totals for the incoming side of a connection, see `Client::stats`.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Stats {
+    pub bytes_received: u64,
+    pub updates: u64,
+    /// Time spent decoding rectangles, which excludes waiting on the network.
+    pub decode_time: Duration,
+    /// Largest receive buffer allocated for a single rectangle, per encoding.
+    pub peak_raw_buffer: u64,
+    pub peak_zrle_buffer: u64,
+    /// The encoding that brought most of the pixel data of the last update, to
+    /// tell whether the server honours the preferred one.
+    pub frame_encoding: Option<protocol::Encoding>,
+}
+
+/// Encodings that carry pixel data, numbered from 1 in `Counters::frame_encoding`.
+const PIXEL_ENCODINGS: [protocol::Encoding; 5] = [
+    protocol::Encoding::Raw,
+    protocol::Encoding::Tight,
+    protocol::Encoding::Trle,
+    protocol::Encoding::Zrle,
+    protocol::Encoding::Hextile,
+];
+
+#[derive(Default)]
+struct Counters {
+    bytes_received: AtomicU64,
+    updates: AtomicU64,
+    decode_nanos: AtomicU64,
+    peak_raw_buffer: AtomicU64,
+    peak_zrle_buffer: AtomicU64,
+    frame_encoding: AtomicU8,
+}
+
+impl Counters {
+    fn snapshot(&self) -> Stats {
+        Stats {
+            bytes_received: self.bytes_received.load(Ordering::Relaxed),
+            updates: self.updates.load(Ordering::Relaxed),
+            decode_time: Duration::from_nanos(self.decode_nanos.load(Ordering::Relaxed)),
+            peak_raw_buffer: self.peak_raw_buffer.load(Ordering::Relaxed),
+            peak_zrle_buffer: self.peak_zrle_buffer.load(Ordering::Relaxed),
+            frame_encoding: match self.frame_encoding.load(Ordering::Relaxed) {
+                0 => None,
+                number => Some(PIXEL_ENCODINGS[number as usize - 1]),
+            },
+        }
+    }
+}
+
+struct CountingReader<R> {
+    inner: R,
+    counters: Arc<Counters>,
+    /// Time spent in reads, so decoders reading straight from the connection
+    /// can leave the network out of their decode time.
+    read_time: Duration,
+}
+
+impl<R: Read> CountingReader<R> {
+    /// Runs a decoder that reads from this stream, adding the time it took,
+    /// less any spent reading, to the decode time.
+    fn decode<T>(&mut self, decoder: impl FnOnce(&mut Self) -> Result<T>) -> Result<T> {
+        let started = Instant::now();
+        let read_time = self.read_time;
+        let result = decoder(self);
+        let decode_time = started.elapsed().saturating_sub(self.read_time - read_time);
+        self.counters
+            .decode_nanos
+            .fetch_add(decode_time.as_nanos() as u64, Ordering::Relaxed);
+        result
+    }
+}
+
+impl<R: Read> Read for CountingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let started = Instant::now();
+        let result = self.inner.read(buf);
+        self.read_time += started.elapsed();
+        let count = result?;
+        self.counters
+            .bytes_received
+            .fetch_add(count as u64, Ordering::Relaxed);
+        Ok(count)
+    }
 }
 
 impl Event {
-    fn pump(
-        mut stream: TcpStream,
+    fn pump<S: Read>(
+        stream: S,
         format: Arc<Mutex<protocol::PixelFormat>>,
+        counters: Arc<Counters>,
         tx_events: &mut Sender<Event>,
+        tx_pacer: &Sender<Pace>,
     ) -> Result<()> {
         macro_rules! send {
             ($chan:expr, $data:expr) => {{
@@ -69,7 +200,13 @@ impl Event {
             }};
         }
 
+        let mut stream = CountingReader {
+            inner: stream,
+            counters: counters.clone(),
+            read_time: Duration::ZERO,
+        };
         let mut zrle_decoder = zrle::Decoder::new();
+        let mut tight_decoder = tight::Decoder::new();
         loop {
             let packet = match protocol::S2C::read_from(&mut stream) {
                 Ok(packet) => packet,
@@ -96,7 +233,9 @@ impl Event {
                     )
                 }
                 protocol::S2C::FramebufferUpdate { count } => {
+                    let mut frame_bytes = [0u64; PIXEL_ENCODINGS.len()];
                     for _ in 0..count {
+                        let started_at = counters.bytes_received.load(Ordering::Relaxed);
                         let rectangle = protocol::Rectangle::read_from(&mut stream)?;
                         debug!("<- {:?}", rectangle);
 
@@ -111,6 +250,9 @@ impl Event {
                                 let length = (rectangle.width as usize)
                                     * (rectangle.height as usize)
                                     * (format.bits_per_pixel as usize / 8);
+                                counters
+                                    .peak_raw_buffer
+                                    .fetch_max(length as u64, Ordering::Relaxed);
                                 let mut pixels = Vec::with_capacity(length);
                                 unsafe { pixels.set_len(length as usize) }
                                 stream.read_exact(&mut pixels)?;
@@ -129,18 +271,52 @@ impl Event {
                             }
                             protocol::Encoding::Zrle => {
                                 let length = stream.read_u32::<BigEndian>()?;
+                                counters
+                                    .peak_zrle_buffer
+                                    .fetch_max(length as u64, Ordering::Relaxed);
                                 let mut data = Vec::with_capacity(length as usize);
                                 unsafe { data.set_len(length as usize) }
                                 stream.read_exact(&mut data)?;
                                 debug!("<- ...compressed pixels");
+                                let started = Instant::now();
                                 let result =
                                     zrle_decoder.decode(format, dst, &data, |tile, pixels| {
                                         Ok(tx_events.send(Event::PutPixels(tile, pixels)).is_ok())
                                     })?;
+                                counters.decode_nanos.fetch_add(
+                                    started.elapsed().as_nanos() as u64,
+                                    Ordering::Relaxed,
+                                );
+                                if !result {
+                                    break;
+                                }
+                            }
+                            protocol::Encoding::Trle => {
+                                let result = stream.decode(|stream| {
+                                    zrle::decode_trle(format, dst, stream, |tile, pixels| {
+                                        Ok(tx_events.send(Event::PutPixels(tile, pixels)).is_ok())
+                                    })
+                                })?;
+                                if !result {
+                                    break;
+                                }
+                            }
+                            protocol::Encoding::Hextile => {
+                                let result = stream.decode(|stream| {
+                                    hextile::decode(format, dst, stream, |tile, pixels| {
+                                        Ok(tx_events.send(Event::PutPixels(tile, pixels)).is_ok())
+                                    })
+                                })?;
                                 if !result {
                                     break;
                                 }
                             }
+                            protocol::Encoding::Tight => {
+                                let pixels = stream
+                                    .decode(|stream| tight_decoder.decode(format, dst, stream))?;
+                                debug!("<- ...tight pixels");
+                                send!(tx_events, Event::PutPixels(dst, pixels))
+                            }
                             protocol::Encoding::Cursor => {
                                 let mut pixels = vec![
                                     0;
@@ -169,14 +345,70 @@ impl Event {
                             protocol::Encoding::DesktopSize => {
                                 send!(tx_events, Event::Resize(rectangle.width, rectangle.height))
                             }
+                            protocol::Encoding::ExtendedDesktopSize => {
+                                let count = stream.read_u8()?;
+                                stream.read_exact(&mut [0; 3])?;
+                                let screens = (0..count)
+                                    .map(|_| protocol::Screen::read_from(&mut stream))
+                                    .collect::<Result<Vec<_>>>()?;
+                                let reason = match rectangle.x_position {
+                                    1 => ResizeReason::Client,
+                                    2 => ResizeReason::OtherClient,
+                                    _ => ResizeReason::Server,
+                                };
+                                send!(
+                                    tx_events,
+                                    Event::ExtendedResize {
+                                        width: rectangle.width,
+                                        height: rectangle.height,
+                                        reason,
+                                        status: rectangle.y_position,
+                                        screens,
+                                    }
+                                )
+                            }
+                            // Servers that don't know the rectangle count up front send
+                            // 0xFFFF and terminate the update with a LastRect.
+                            protocol::Encoding::LastRect => break,
                             _ => return Err(Error::Unexpected("encoding")),
                         };
+                        if let Some(i) = PIXEL_ENCODINGS
+                            .iter()
+                            .position(|&encoding| encoding == rectangle.encoding)
+                        {
+                            frame_bytes[i] +=
+                                counters.bytes_received.load(Ordering::Relaxed) - started_at;
+                        }
                     }
 
+                    let dominant = (0..frame_bytes.len())
+                        .filter(|&i| frame_bytes[i] > 0)
+                        .max_by_key(|&i| frame_bytes[i]);
+                    if let Some(i) = dominant {
+                        counters
+                            .frame_encoding
+                            .store(i as u8 + 1, Ordering::Relaxed);
+                    }
+                    counters.updates.fetch_add(1, Ordering::Relaxed);
+                    let _ = tx_pacer.send(Pace::FrameEnd);
                     send!(tx_events, Event::EndOfFrame);
                 }
                 protocol::S2C::Bell => send!(tx_events, Event::Bell),
                 protocol::S2C::CutText(text) => send!(tx_events, Event::Clipboard(text)),
+                protocol::S2C::ExtendedCutText(payload) => {
+                    let message = ExtendedClipboard::read_from(&mut &payload[..])?;
+                    match message {
+                        ExtendedClipboard::Provide(items) => {
+                            for (format, data) in items {
+                                if format == clipboard::FORMAT_TEXT {
+                                    let text = clipboard::text_from_wire(&data);
+                                    send!(tx_events, Event::Clipboard(text))
+                                }
+                            }
+                        }
+                        message => send!(tx_events, Event::ExtendedClipboard(message)),
+                    }
+                }
             }
         }
 
@@ -184,12 +416,159 @@ impl Event {
     }
 }
 
+/* Outgoing messages are written by a dedicated thread, so that a congested link
+doesn't stall the caller. Once this many messages are queued, pointer motion is
+coalesced (see `Client::send_pointer_event`) and everything else waits for room. */
+const OUTGOING_QUEUE_LENGTH: usize = 64;
+
+/// Where and how often to keep asking for incremental updates, see
+/// `Client::set_update_pacing`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UpdatePacing {
+    pub area: Rect,
+    /// Most requests a second, 0 for no limit.
+    pub max_fps: u32,
+    /// Nothing is asked for while paused.
+    pub paused: bool,
+}
+
+enum Pace {
+    /// A frame has been received in full.
+    FrameEnd,
+    Set(Option<UpdatePacing>),
+}
+
+/* Update requests are paced by a thread of their own, which hears from the
+reader thread as soon as a frame ends. So the next request doesn't wait on the
+caller polling events, which may happen far less often when it only does so
+as often as it repaints. */
+fn pace_updates(pacer: Receiver<Pace>, outgoing: SyncSender<protocol::C2S>) {
+    let mut pacing: Option<UpdatePacing> = None;
+    let mut frame_ended = false;
+    let mut last_request: Option<Instant> = None;
+    loop {
+        let due = pacing.filter(|pacing| frame_ended && !pacing.paused);
+        let message = match due {
+            Some(pacing) => {
+                let wait = last_request
+                    .filter(|_| pacing.max_fps > 0)
+                    .and_then(|last| {
+                        (Duration::from_secs(1) / pacing.max_fps).checked_sub(last.elapsed())
+                    })
+                    .filter(|wait| !wait.is_zero());
+                let Some(wait) = wait else {
+                    let update_req = protocol::C2S::FramebufferUpdateRequest {
+                        incremental: true,
+                        x_position: pacing.area.left,
+                        y_position: pacing.area.top,
+                        width: pacing.area.width,
+                        height: pacing.area.height,
+                    };
+                    trace!("-> {:?}", update_req);
+                    if outgoing.send(update_req).is_err() {
+                        return;
+                    }
+                    frame_ended = false;
+                    last_request = Some(Instant::now());
+                    continue;
+                };
+                match pacer.recv_timeout(wait) {
+                    Ok(message) => message,
+                    Err(RecvTimeoutError::Timeout) => continue,
+                    Err(RecvTimeoutError::Disconnected) => return,
+                }
+            }
+            None => match pacer.recv() {
+                Ok(message) => message,
+                Err(_) => return,
+            },
+        };
+        match message {
+            Pace::FrameEnd => frame_ended = true,
+            Pace::Set(new_pacing) => pacing = new_pacing,
+        }
+    }
+}
+
+/// The connection after the security handshake, which may have switched to TLS.
+enum Transport {
+    Tcp(TcpStream),
+    #[cfg(feature = "vencrypt")]
+    Tls(TlsStream),
+}
+
+impl Transport {
+    fn try_clone(&self) -> std::io::Result<Transport> {
+        match self {
+            Transport::Tcp(stream) => stream.try_clone().map(Transport::Tcp),
+            #[cfg(feature = "vencrypt")]
+            Transport::Tls(stream) => stream.try_clone().map(Transport::Tls),
+        }
+    }
+}
+
+impl Read for Transport {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        match self {
+            Transport::Tcp(stream) => stream.read(buf),
+            #[cfg(feature = "vencrypt")]
+            Transport::Tls(stream) => stream.read(buf),
+        }
+    }
+}
+
+impl Write for Transport {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        match self {
+            Transport::Tcp(stream) => stream.write(buf),
+            #[cfg(feature = "vencrypt")]
+            Transport::Tls(stream) => stream.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self {
+            Transport::Tcp(stream) => stream.flush(),
+            #[cfg(feature = "vencrypt")]
+            Transport::Tls(stream) => stream.flush(),
+        }
+    }
+}
+
+fn write_outgoing(stream: Transport, outgoing: Receiver<protocol::C2S>) -> Result<()> {
+    let mut writer = BufWriter::new(stream);
+    while let Ok(message) = outgoing.recv() {
+        message.write_to(&mut writer)?;
+        // Batch whatever else is already queued into the same write.
+        while let Ok(message) = outgoing.try_recv() {
+            message.write_to(&mut writer)?;
+        }
+        writer.flush()?;
+    }
+    Ok(())
+}
+
 pub struct Client {
     stream: TcpStream,
+    outgoing: SyncSender<protocol::C2S>,
+    pacer: Sender<Pace>,
+    pacing: Option<UpdatePacing>,
+    pending_pointer: Option<protocol::C2S>,
+    button_mask: u8,
     events: Receiver<Event>,
     name: String,
+    version: protocol::Version,
+    security_type: protocol::SecurityType,
     size: (u16, u16),
+    /// The layout from the last ExtendedDesktopSize, empty if the server never
+    /// sent one and so can't be asked to resize.
+    screens: Vec<protocol::Screen>,
     format: Arc<Mutex<protocol::PixelFormat>>,
+    /// Whether any FramebufferUpdateRequest has gone out yet.
+    updates_requested: bool,
+    counters: Arc<Counters>,
+    clipboard_caps: Option<clipboard::Caps>,
+    clipboard_data: Vec<(u32, Vec<u8>)>,
 }
 
 impl Client {
@@ -244,27 +623,72 @@ impl Client {
                 protocol::SecurityType::AppleRemoteDesktop => {
                     auth_methods.push(AuthMethod::AppleRemoteDesktop)
                 }
+                protocol::SecurityType::MsLogon => auth_methods.push(AuthMethod::MsLogon),
+                #[cfg(feature = "vencrypt")]
+                protocol::SecurityType::VeNCrypt => auth_methods.push(AuthMethod::VeNCrypt),
                 _ => (),
             }
         }
 
         let auth_choice = auth(&auth_methods).ok_or(Error::AuthenticationUnavailable)?;
+        let used_security_type = match auth_choice {
+            AuthChoice::None => protocol::SecurityType::None,
+            AuthChoice::Password(_) => protocol::SecurityType::VncAuthentication,
+            AuthChoice::AppleRemoteDesktop(_, _) => protocol::SecurityType::AppleRemoteDesktop,
+            AuthChoice::MsLogon(_, _) => protocol::SecurityType::MsLogon,
+            AuthChoice::VeNCrypt { .. } => protocol::SecurityType::VeNCrypt,
+        };
 
         match version {
             protocol::Version::Rfb33 => (),
             _ => {
-                let used_security_type = match auth_choice {
-                    AuthChoice::None => protocol::SecurityType::None,
-                    AuthChoice::Password(_) => protocol::SecurityType::VncAuthentication,
-                    AuthChoice::AppleRemoteDesktop(_, _) => {
-                        protocol::SecurityType::AppleRemoteDesktop
-                    }
-                };
                 info!("-> Selecting SecurityType: {:?}", used_security_type);
                 protocol::SecurityType::write_to(&used_security_type, &mut stream)?;
             }
         }
 
+        let security_result_expected = !matches!(
+            (&auth_choice, version),
+            (
+                AuthChoice::None,
+                protocol::Version::Rfb33 | protocol::Version::Rfb37
+            )
+        );
+
+        // From here on the session may be encrypted, with VeNCrypt handing over
+        // to one of the plain authentications inside TLS.
+        let mut transport = Transport::Tcp(stream.try_clone()?);
+        let auth_choice = match auth_choice {
+            #[cfg(feature = "vencrypt")]
+            AuthChoice::VeNCrypt {
+                server_name,
+                verify_certificate,
+                username,
+                password,
+            } => {
+                let (tls, subtype) = vencrypt(
+                    &mut stream,
+                    &server_name,
+                    verify_certificate,
+                    &username,
+                    &password,
+                )?;
+                transport = Transport::Tls(tls);
+                if subtype == X509_VNC {
+                    let mut key = [0u8; 8];
+                    for (k, b) in key.iter_mut().zip(password.bytes()) {
+                        *k = b;
+                    }
+                    AuthChoice::Password(key)
+                } else {
+                    AuthChoice::None
+                }
+            }
+            #[cfg(not(feature = "vencrypt"))]
+            AuthChoice::VeNCrypt { .. } => return Err(Error::AuthenticationUnavailable),
+            choice => choice,
+        };
+
         info!("Auth choice picked: {:?}", auth_choice);
         match auth_choice {
             AuthChoice::Password(mut password) => {
@@ -288,32 +712,37 @@ impl Client {
 
                 info!("Reading 16-byte VNC challenge...");
                 let mut challenge = [0; 16];
-                stream.read_exact(&mut challenge)?;
+                transport.read_exact(&mut challenge)?;
                 info!("Challenge received: {:02X?}", challenge);
                 let response = des(&challenge, &password);
                 info!("Sending 16-byte VNC response...");
-                stream.write_all(&response)?;
+                transport.write_all(&response)?;
+            }
+            AuthChoice::MsLogon(ref username, ref password) => {
+                let generator = transport.read_u64::<BigEndian>()?;
+                let modulus = transport.read_u64::<BigEndian>()?;
+                let server_key = transport.read_u64::<BigEndian>()?;
+                if modulus < 2 {
+                    return Err(Error::Unexpected("MS-Logon modulus"));
+                }
+                let response = mslogon(generator, modulus, server_key, username, password);
+                transport.write_u64::<BigEndian>(response.public_key)?;
+                transport.write_all(&response.username)?;
+                transport.write_all(&response.password)?;
             }
             #[cfg(feature = "apple-auth")]
             AuthChoice::AppleRemoteDesktop(ref username, ref password) => {
-                let handshake = protocol::AppleAuthHandshake::read_from(&mut stream)?;
+                let handshake = protocol::AppleAuthHandshake::read_from(&mut transport)?;
                 let response = apple_auth(username, password, &handshake);
-                response.write_to(&mut stream)?;
+                response.write_to(&mut transport)?;
             }
             _ => (),
         }
 
-        let mut skip_security_result = false;
-        match &(auth_choice, version) {
-            &(AuthChoice::None, protocol::Version::Rfb33)
-            | &(AuthChoice::None, protocol::Version::Rfb37) => skip_security_result = true,
-            _ => (),
-        }
-
-        if !skip_security_result {
+        if security_result_expected {
             info!("Waiting for SecurityResult...");
             match {
-                let result = stream.read_u32::<BigEndian>()?;
+                let result = transport.read_u32::<BigEndian>()?;
                 match result {
                     0 => Ok(protocol::SecurityResult::Succeeded),
                     1 => Ok(protocol::SecurityResult::Failed),
@@ -333,7 +762,7 @@ impl Client {
                             return Err(Error::AuthenticationFailure(String::from("")))
                         }
                         protocol::Version::Rfb38 => {
-                            let reason = String::read_from(&mut stream)?;
+                            let reason = String::read_from(&mut transport)?;
                             info!("Failure reason: {}", reason);
                             return Err(Error::AuthenticationFailure(reason));
                         }
@@ -344,39 +773,81 @@ impl Client {
 
         let client_init = protocol::ClientInit { shared };
         debug!("-> {:?}", client_init);
-        protocol::ClientInit::write_to(&client_init, &mut stream)?;
+        protocol::ClientInit::write_to(&client_init, &mut transport)?;
 
-        let server_init = protocol::ServerInit::read_from(&mut stream)?;
+        let server_init = protocol::ServerInit::read_from(&mut transport)?;
         debug!("<- {:?}", server_init);
 
         let format = Arc::new(Mutex::new(server_init.pixel_format));
 
         let (tx_events, rx_events) = channel();
+        let (tx_outgoing, rx_outgoing) = sync_channel(OUTGOING_QUEUE_LENGTH);
+        {
+            let stream = transport.try_clone()?;
+            let tx_events = tx_events.clone();
+            thread::spawn(move || {
+                if let Err(error) = write_outgoing(stream, rx_outgoing) {
+                    let _ = tx_events.send(Event::Disconnected(Some(error)));
+                }
+            });
+        }
+        let (tx_pacer, rx_pacer) = channel();
+        {
+            let tx_outgoing = tx_outgoing.clone();
+            thread::spawn(move || pace_updates(rx_pacer, tx_outgoing));
+        }
+        let counters = Arc::new(Counters::default());
         {
-            let stream = stream.try_clone().unwrap();
             let format = format.clone();
+            let counters = counters.clone();
+            let tx_pacer = tx_pacer.clone();
             thread::spawn(move || {
                 let mut tx_events = tx_events;
-                let error = Event::pump(stream, format, &mut tx_events).err();
+                let error =
+                    Event::pump(transport, format, counters, &mut tx_events, &tx_pacer).err();
                 let _ = tx_events.send(Event::Disconnected(error));
             });
         }
 
         Ok(Client {
             stream,
+            outgoing: tx_outgoing,
+            pacer: tx_pacer,
+            pacing: None,
+            pending_pointer: None,
+            button_mask: 0,
             events: rx_events,
             name: server_init.name,
+            version,
+            security_type: used_security_type,
             size: (
                 server_init.framebuffer_width,
                 server_init.framebuffer_height,
             ),
+            screens: Vec::new(),
             format,
+            updates_requested: false,
+            counters,
+            clipboard_caps: None,
+            clipboard_data: Vec::new(),
         })
     }
 
     pub fn name(&self) -> &str {
         &self.name
     }
+    /// The protocol version both sides agreed on.
+    pub fn version(&self) -> protocol::Version {
+        self.version
+    }
+    /// The security type the session was set up with. For VeNCrypt, the login
+    /// inside the TLS session isn't included.
+    pub fn security_type(&self) -> protocol::SecurityType {
+        self.security_type
+    }
+    pub fn stats(&self) -> Stats {
+        self.counters.snapshot()
+    }
     pub fn size(&self) -> (u16, u16) {
         self.size
     }
@@ -384,10 +855,39 @@ impl Client {
         *self.format.lock().unwrap()
     }
 
+    /// Whether the server takes `set_desktop_size` requests, known once it has
+    /// answered ExtendedDesktopSize in the encodings.
+    pub fn can_resize_desktop(&self) -> bool {
+        !self.screens.is_empty()
+    }
+
+    /// Asks the server to change the screen size. With more than one screen the
+    /// layout can't be kept, so the first screen is made to cover the new size.
+    /// The answer comes as an `Event::ExtendedResize` with reason `Client`.
+    pub fn set_desktop_size(&mut self, width: u16, height: u16) -> Result<()> {
+        let Some(&first) = self.screens.first() else {
+            return Err(Error::Unexpected("resize without ExtendedDesktopSize"));
+        };
+        let set_desktop_size = protocol::C2S::SetDesktopSize {
+            width,
+            height,
+            screens: vec![protocol::Screen {
+                x_position: 0,
+                y_position: 0,
+                width,
+                height,
+                ..first
+            }],
+        };
+        debug!("-> {:?}", set_desktop_size);
+        self.send(set_desktop_size)?;
+        Ok(())
+    }
+
     pub fn set_encodings(&mut self, encodings: &[protocol::Encoding]) -> Result<()> {
         let set_encodings = protocol::C2S::SetEncodings(Vec::from(encodings));
         debug!("-> {:?}", set_encodings);
-        protocol::C2S::write_to(&set_encodings, &mut self.stream)?;
+        self.send(set_encodings)?;
         Ok(())
     }
 
@@ -400,17 +900,43 @@ impl Client {
             height: rect.height,
         };
         trace!("-> {:?}", update_req);
-        protocol::C2S::write_to(&update_req, &mut self.stream)?;
+        self.send(update_req)?;
+        self.updates_requested = true;
         Ok(())
     }
 
+    /// Keeps asking for incremental updates of `pacing.area` from now on, one
+    /// as soon as each frame has arrived and no more often than `max_fps`.
+    /// `None` stops asking. The first request waits for a frame to end, so an
+    /// update has to be requested once to get going.
+    pub fn set_update_pacing(&mut self, pacing: Option<UpdatePacing>) -> Result<()> {
+        self.pacer
+            .send(Pace::Set(pacing))
+            .map_err(|_| Error::Disconnected)?;
+        self.pacing = pacing;
+        self.updates_requested |= pacing.is_some();
+        Ok(())
+    }
+
+    /// Takes the frame as complete though its end never came, for servers that
+    /// don't mark it. The next paced request goes out.
+    pub fn assume_frame_end(&mut self) -> Result<()> {
+        self.pacer
+            .send(Pace::FrameEnd)
+            .map_err(|_| Error::Disconnected)
+    }
+
     pub fn send_key_event(&mut self, down: bool, key: u32) -> Result<()> {
         let key_event = protocol::C2S::KeyEvent { down, key };
         debug!("-> {:?}", key_event);
-        protocol::C2S::write_to(&key_event, &mut self.stream)?;
+        self.send(key_event)?;
         Ok(())
     }
 
+    /// Sends a pointer event without blocking on a congested link, as long as the
+    /// button state is unchanged: if the outgoing queue is full, the motion is held
+    /// back and replaced by any later motion, and the latest position goes out as
+    /// soon as there is room. Button presses and releases are never dropped.
     pub fn send_pointer_event(&mut self, buttons: u8, x: u16, y: u16) -> Result<()> {
         let pointer_event = protocol::C2S::PointerEvent {
             button_mask: buttons,
@@ -418,14 +944,156 @@ impl Client {
             y_position: y,
         };
         debug!("-> {:?}", pointer_event);
-        protocol::C2S::write_to(&pointer_event, &mut self.stream)?;
+        if buttons != self.button_mask {
+            self.button_mask = buttons;
+            // Supersedes any motion still held back.
+            self.pending_pointer = None;
+            return self.send(pointer_event);
+        }
+
+        self.flush_pending_pointer()?;
+        if self.pending_pointer.is_some() {
+            self.pending_pointer = Some(pointer_event);
+            return Ok(());
+        }
+        match self.outgoing.try_send(pointer_event) {
+            Ok(()) => Ok(()),
+            Err(TrySendError::Full(pointer_event)) => {
+                trace!("outgoing queue full, coalescing pointer motion");
+                self.pending_pointer = Some(pointer_event);
+                Ok(())
+            }
+            Err(TrySendError::Disconnected(_)) => Err(Error::Disconnected),
+        }
+    }
+
+    fn flush_pending_pointer(&mut self) -> Result<()> {
+        if let Some(pointer_event) = self.pending_pointer.take() {
+            match self.outgoing.try_send(pointer_event) {
+                Ok(()) => (),
+                Err(TrySendError::Full(pointer_event)) => {
+                    self.pending_pointer = Some(pointer_event)
+                }
+                Err(TrySendError::Disconnected(_)) => return Err(Error::Disconnected),
+            }
+        }
         Ok(())
     }
 
+    /// Queues a message, waiting for room if the outgoing queue is full.
+    fn send(&mut self, message: protocol::C2S) -> Result<()> {
+        // Held back motion predates this message, so it must go out first.
+        if let Some(pointer_event) = self.pending_pointer.take() {
+            self.outgoing
+                .send(pointer_event)
+                .map_err(|_| Error::Disconnected)?;
+        }
+        self.outgoing.send(message).map_err(|_| Error::Disconnected)
+    }
+
+    /// Returns the extended clipboard capabilities announced by the server, if any.
+    /// The server only announces them if `Encoding::ExtendedClipboard` was requested.
+    pub fn clipboard_caps(&self) -> Option<clipboard::Caps> {
+        self.clipboard_caps
+    }
+
+    /// Offers an RGBA image to the server as a DIB over the extended clipboard.
+    /// Returns `false` without sending anything if the server can't accept images.
+    pub fn update_clipboard_image(
+        &mut self,
+        width: usize,
+        height: usize,
+        rgba: &[u8],
+    ) -> Result<bool> {
+        let caps = match self.clipboard_caps {
+            Some(caps) if caps.supports(clipboard::FORMAT_DIB) => caps,
+            _ => return Ok(false),
+        };
+        let dib = clipboard::rgba_to_dib(width, height, rgba);
+        self.offer_clipboard(caps, clipboard::FORMAT_DIB, dib)
+    }
+
+    /// Makes `data` the client clipboard and tells the server about it, in whichever
+    /// way it accepts. Returns `false` if it accepts neither.
+    fn offer_clipboard(
+        &mut self,
+        caps: clipboard::Caps,
+        format: u32,
+        data: Vec<u8>,
+    ) -> Result<bool> {
+        self.clipboard_data = vec![(format, data)];
+
+        if caps.actions & clipboard::ACTION_NOTIFY != 0 {
+            // The server will ask for the data it wants.
+            self.send_extended_clipboard(&ExtendedClipboard::Notify(format))?;
+        } else if caps.actions & clipboard::ACTION_PROVIDE != 0 {
+            let provide = ExtendedClipboard::Provide(self.clipboard_data.clone());
+            self.send_extended_clipboard(&provide)?;
+        } else {
+            return Ok(false);
+        }
+        Ok(true)
+    }
+
+    fn send_extended_clipboard(&mut self, message: &ExtendedClipboard) -> Result<()> {
+        let mut payload = Vec::new();
+        message.write_to(&mut payload)?;
+        let cut_text = protocol::C2S::ExtendedCutText(payload);
+        debug!("-> {:?}", message);
+        self.send(cut_text)?;
+        Ok(())
+    }
+
+    fn handle_extended_clipboard(&mut self, message: &ExtendedClipboard) -> Result<()> {
+        match message {
+            ExtendedClipboard::Caps { caps, .. } => {
+                info!("Server extended clipboard formats: {:#x}", caps.formats);
+                self.clipboard_caps = Some(*caps);
+                self.send_extended_clipboard(&ExtendedClipboard::client_caps())?;
+            }
+            ExtendedClipboard::Notify(formats) if formats & clipboard::FORMAT_TEXT != 0 => {
+                self.send_extended_clipboard(&ExtendedClipboard::Request(clipboard::FORMAT_TEXT))?;
+            }
+            ExtendedClipboard::Request(formats) => {
+                let items = self
+                    .clipboard_data
+                    .iter()
+                    .filter(|(format, _)| formats & format != 0)
+                    .cloned()
+                    .collect();
+                self.send_extended_clipboard(&ExtendedClipboard::Provide(items))?;
+            }
+            ExtendedClipboard::Peek => {
+                let formats = self
+                    .clipboard_data
+                    .iter()
+                    .fold(0, |acc, (format, _)| acc | format);
+                self.send_extended_clipboard(&ExtendedClipboard::Notify(formats))?;
+            }
+            _ => (),
+        }
+        Ok(())
+    }
+
+    /// Sends text to the server clipboard, as UTF-8 if the extended clipboard is
+    /// available and as Latin-1 otherwise, with other characters replaced by `?`.
     pub fn update_clipboard(&mut self, text: &str) -> Result<()> {
-        let cut_text = protocol::C2S::CutText(String::from(text));
+        if let Some(caps) = self
+            .clipboard_caps
+            .filter(|caps| caps.supports(clipboard::FORMAT_TEXT))
+        {
+            let data = clipboard::text_to_wire(text);
+            if self.offer_clipboard(caps, clipboard::FORMAT_TEXT, data)? {
+                return Ok(());
+            }
+        }
+        let latin1 = text
+            .chars()
+            .map(|c| if (c as u32) < 0x100 { c } else { '?' })
+            .collect();
+        let cut_text = protocol::C2S::CutText(latin1);
         debug!("-> {:?}", cut_text);
-        protocol::C2S::write_to(&cut_text, &mut self.stream)?;
+        self.send(cut_text)?;
         Ok(())
     }
 
@@ -433,23 +1101,41 @@ impl Client {
     // function is prone to race conditions that break the connection framing.
     // The ZRLE encoding is self-delimiting and if both the client and server
     // support and use it, there can be no race condition, but we currently don't.
+    // Before the first update request nothing can be in flight, so the format
+    // is switched straight away.
     pub fn set_format(&mut self, format: protocol::PixelFormat) -> Result<()> {
-        // Request (and discard) one full update to try and ensure that there
-        // are no FramebufferUpdate's in the buffers somewhere.
-        // This is not fully robust though (and cannot possibly be).
-        let _ = self.poll_iter().count(); // drain it
-        let framebuffer_rect = Rect {
-            left: 0,
-            top: 0,
-            width: self.size.0,
-            height: self.size.1,
-        };
-        self.request_update(framebuffer_rect, false)?;
-        'outer: loop {
-            for event in self.poll_iter() {
-                match event {
-                    Event::PutPixels(rect, _) if rect == framebuffer_rect => break 'outer,
-                    _ => (),
+        // Paced requests would keep updates in flight.
+        let pacing = self.pacing;
+        if pacing.is_some() {
+            self.set_update_pacing(None)?;
+        }
+        if self.updates_requested {
+            // Request (and discard) one full update to try and ensure that there
+            // are no FramebufferUpdate's in the buffers somewhere.
+            // This is not fully robust though (and cannot possibly be).
+            let _ = self.poll_iter().count(); // drain it
+            let framebuffer_rect = Rect {
+                left: 0,
+                top: 0,
+                width: self.size.0,
+                height: self.size.1,
+            };
+            self.request_update(framebuffer_rect, false)?;
+            // ZRLE and TRLE send the screen tile by tile, so the full update is
+            // only recognisable at its end, as a frame covering the whole area.
+            let area = |rect: Rect| rect.width as u64 * rect.height as u64;
+            let mut covered = 0;
+            'outer: loop {
+                for event in self.poll_iter() {
+                    match event {
+                        Event::PutPixels(rect, _) | Event::CopyPixels { dst: rect, .. } => {
+                            covered += area(rect)
+                        }
+                        Event::EndOfFrame if covered >= area(framebuffer_rect) => break 'outer,
+                        Event::EndOfFrame => covered = 0,
+                        Event::Disconnected(_) => return Err(Error::Disconnected),
+                        _ => (),
+                    }
                 }
             }
         }
@@ -459,9 +1145,12 @@ impl Client {
         // so it's safe to switch to the new pixel format.
         let set_pixel_format = protocol::C2S::SetPixelFormat(format);
         debug!("-> {:?}", set_pixel_format);
-        protocol::C2S::write_to(&set_pixel_format, &mut self.stream)?;
+        self.send(set_pixel_format)?;
         *self.format.lock().unwrap() = format;
 
+        if pacing.is_some() {
+            self.set_update_pacing(pacing)?;
+        }
         Ok(())
     }
 
@@ -469,17 +1158,45 @@ impl Client {
     pub fn poke_qemu(&mut self) -> Result<()> {
         let set_pixel_format = protocol::C2S::SetPixelFormat(*self.format.lock().unwrap());
         debug!("-> {:?}", set_pixel_format);
-        protocol::C2S::write_to(&set_pixel_format, &mut self.stream)?;
+        self.send(set_pixel_format)?;
         Ok(())
     }
 
     pub fn poll_event(&mut self) -> Option<Event> {
+        if let Err(error) = self.flush_pending_pointer() {
+            return Some(Event::Disconnected(Some(error)));
+        }
         match self.events.try_recv() {
             Err(TryRecvError::Empty) | Err(TryRecvError::Disconnected) => None,
             Ok(Event::Resize(width, height)) => {
                 self.size = (width, height);
                 Some(Event::Resize(width, height))
             }
+            Ok(Event::ExtendedResize {
+                width,
+                height,
+                reason,
+                status,
+                screens,
+            }) => {
+                self.size = (width, height);
+                self.screens.clone_from(&screens);
+                Some(Event::ExtendedResize {
+                    width,
+                    height,
+                    reason,
+                    status,
+                    screens,
+                })
+            }
+            Ok(Event::ExtendedClipboard(message)) => {
+                // Replies are sent from here, since only this side of the
+                // connection may write to the stream.
+                if let Err(error) = self.handle_extended_clipboard(&message) {
+                    return Some(Event::Disconnected(Some(error)));
+                }
+                Some(Event::ExtendedClipboard(message))
+            }
             Ok(event) => Some(event),
         }
     }
@@ -505,3 +1222,503 @@ impl<'a> Iterator for EventPollIterator<'a> {
         self.client.poll_event()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use byteorder::WriteBytesExt;
+    use std::io::Cursor;
+
+    fn pump_events(input: Vec<u8>) -> Vec<Event> {
+        let format = Arc::new(Mutex::new(protocol::PixelFormat::rgb888()));
+        let (mut tx_events, rx_events) = channel();
+        Event::pump(
+            Cursor::new(input),
+            format,
+            Arc::new(Counters::default()),
+            &mut tx_events,
+            &channel().0,
+        )
+        .unwrap();
+        rx_events.try_iter().collect()
+    }
+
+    fn write_rectangle(buffer: &mut Vec<u8>, rect: Rect, encoding: protocol::Encoding) {
+        protocol::Rectangle {
+            x_position: rect.left,
+            y_position: rect.top,
+            width: rect.width,
+            height: rect.height,
+            encoding,
+        }
+        .write_to(buffer)
+        .unwrap();
+    }
+
+    #[test]
+    fn test_last_rect_ends_frame() {
+        let rect = Rect {
+            left: 1,
+            top: 2,
+            width: 2,
+            height: 1,
+        };
+        let mut input = Vec::new();
+        protocol::S2C::FramebufferUpdate { count: 0xffff }
+            .write_to(&mut input)
+            .unwrap();
+        write_rectangle(&mut input, rect, protocol::Encoding::Raw);
+        input.extend_from_slice(&[0; 8]);
+        write_rectangle(
+            &mut input,
+            Rect {
+                left: 0,
+                top: 0,
+                width: 0,
+                height: 0,
+            },
+            protocol::Encoding::LastRect,
+        );
+        // Anything after the LastRect must be parsed as a new message.
+        input.write_u8(2).unwrap(); // Bell
+
+        let events = pump_events(input);
+        assert!(
+            matches!(events[0], Event::PutPixels(r, ref pixels) if r == rect && pixels.len() == 8)
+        );
+        assert!(matches!(events[1], Event::EndOfFrame));
+        assert!(matches!(events[2], Event::Bell));
+        assert!(matches!(events[3], Event::Disconnected(None)));
+    }
+
+    #[test]
+    fn test_trle() {
+        let mut input = Vec::new();
+        protocol::S2C::FramebufferUpdate { count: 1 }
+            .write_to(&mut input)
+            .unwrap();
+        let rect = Rect {
+            left: 0,
+            top: 0,
+            width: 18,
+            height: 1,
+        };
+        write_rectangle(&mut input, rect, protocol::Encoding::Trle);
+        // A 16x1 tile with a red and white palette, one bit per pixel.
+        input.extend_from_slice(&[2, 0, 0, 255, 255, 255, 255, 0b1000_0000, 0b0000_0001]);
+        // A 2x1 tile that reuses that palette.
+        input.extend_from_slice(&[127, 0b0100_0000]);
+
+        let events = pump_events(input);
+        let (red, white) = ([0, 0, 255, 0], [255, 255, 255, 0]);
+        let mut first = [red; 16];
+        first[0] = white;
+        first[15] = white;
+        assert!(matches!(
+            events[0],
+            Event::PutPixels(r, ref pixels) if r.width == 16 && pixels == &first.concat()
+        ));
+        assert!(matches!(
+            events[1],
+            Event::PutPixels(r, ref pixels) if r.left == 16 && pixels == &[red, white].concat()
+        ));
+        assert!(matches!(events[2], Event::EndOfFrame));
+    }
+
+    #[test]
+    fn test_hextile() {
+        let mut input = Vec::new();
+        protocol::S2C::FramebufferUpdate { count: 1 }
+            .write_to(&mut input)
+            .unwrap();
+        let rect = Rect {
+            left: 0,
+            top: 0,
+            width: 20,
+            height: 1,
+        };
+        write_rectangle(&mut input, rect, protocol::Encoding::Hextile);
+        // A red tile, then one that keeps its background.
+        input.extend_from_slice(&[2, 0, 0, 255, 0, 0]);
+
+        let events = pump_events(input);
+        let red = [0, 0, 255, 0];
+        assert!(matches!(
+            events[0],
+            Event::PutPixels(r, ref pixels) if r.width == 16 && pixels == &[red; 16].concat()
+        ));
+        assert!(matches!(
+            events[1],
+            Event::PutPixels(r, ref pixels) if r.left == 16 && pixels == &[red; 4].concat()
+        ));
+        assert!(matches!(events[2], Event::EndOfFrame));
+    }
+
+    #[test]
+    fn test_frame_encoding() {
+        let mut input = Vec::new();
+        protocol::S2C::FramebufferUpdate { count: 2 }
+            .write_to(&mut input)
+            .unwrap();
+        let pixel = Rect {
+            left: 0,
+            top: 1,
+            width: 1,
+            height: 1,
+        };
+        write_rectangle(&mut input, pixel, protocol::Encoding::Raw);
+        input.extend_from_slice(&[0; 4]);
+        let row = Rect {
+            left: 0,
+            top: 0,
+            width: 18,
+            height: 1,
+        };
+        write_rectangle(&mut input, row, protocol::Encoding::Trle);
+        input.extend_from_slice(&[2, 0, 0, 255, 255, 255, 255, 0b1000_0000, 0b0000_0001]);
+        input.extend_from_slice(&[127, 0b0100_0000]);
+
+        let counters = Arc::new(Counters::default());
+        assert_eq!(counters.snapshot().frame_encoding, None);
+        let (mut tx_events, _rx_events) = channel();
+        Event::pump(
+            Cursor::new(input),
+            Arc::new(Mutex::new(protocol::PixelFormat::rgb888())),
+            counters.clone(),
+            &mut tx_events,
+            &channel().0,
+        )
+        .unwrap();
+        // More bytes came as TRLE than as Raw.
+        assert_eq!(
+            counters.snapshot().frame_encoding,
+            Some(protocol::Encoding::Trle)
+        );
+    }
+
+    #[test]
+    fn test_tight_decode_time() {
+        let mut input = Vec::new();
+        protocol::S2C::FramebufferUpdate { count: 1 }
+            .write_to(&mut input)
+            .unwrap();
+        let screen = Rect {
+            left: 0,
+            top: 0,
+            width: 640,
+            height: 480,
+        };
+        write_rectangle(&mut input, screen, protocol::Encoding::Tight);
+        // The whole screen filled with one colour.
+        input.extend_from_slice(&[0x80, 0x10, 0x20, 0x30]);
+
+        let counters = Arc::new(Counters::default());
+        let (mut tx_events, _rx_events) = channel();
+        Event::pump(
+            Cursor::new(input),
+            Arc::new(Mutex::new(protocol::PixelFormat::rgb888())),
+            counters.clone(),
+            &mut tx_events,
+            &channel().0,
+        )
+        .unwrap();
+        // Tight reads from the connection as it decodes, and still counts.
+        assert!(counters.snapshot().decode_time > Duration::ZERO);
+    }
+
+    #[test]
+    fn test_extended_desktop_size() {
+        let mut input = Vec::new();
+        protocol::S2C::FramebufferUpdate { count: 1 }
+            .write_to(&mut input)
+            .unwrap();
+        // Reason 1 (this client), status 3 (invalid layout).
+        write_rectangle(
+            &mut input,
+            Rect {
+                left: 1,
+                top: 3,
+                width: 1920,
+                height: 1080,
+            },
+            protocol::Encoding::ExtendedDesktopSize,
+        );
+        input.extend_from_slice(&[1, 0, 0, 0]);
+        let screen = protocol::Screen {
+            id: 7,
+            x_position: 0,
+            y_position: 0,
+            width: 1920,
+            height: 1080,
+            flags: 0,
+        };
+        screen.write_to(&mut input).unwrap();
+
+        let events = pump_events(input);
+        assert!(matches!(
+            events[0],
+            Event::ExtendedResize {
+                width: 1920,
+                height: 1080,
+                reason: ResizeReason::Client,
+                status: 3,
+                ref screens,
+            } if screens == &[screen]
+        ));
+        assert!(matches!(events[1], Event::EndOfFrame));
+    }
+
+    #[test]
+    fn test_level_encodings() {
+        let mut output = Vec::new();
+        protocol::Encoding::CompressionLevel(0)
+            .write_to(&mut output)
+            .unwrap();
+        protocol::Encoding::QualityLevel(9)
+            .write_to(&mut output)
+            .unwrap();
+        let mut reader = Cursor::new(&output);
+        assert_eq!(output[..4], (-256i32).to_be_bytes());
+        assert_eq!(output[4..], (-23i32).to_be_bytes());
+        assert_eq!(
+            protocol::Encoding::read_from(&mut reader).unwrap(),
+            protocol::Encoding::CompressionLevel(0)
+        );
+        assert_eq!(
+            protocol::Encoding::read_from(&mut reader).unwrap(),
+            protocol::Encoding::QualityLevel(9)
+        );
+    }
+
+    /// A stripped-down server announcing `version`, which dictates the security
+    /// type the 3.3 way and for VNC authentication only takes `password`. Returns
+    /// the client's end of the connection.
+    fn rfb33_server(
+        version: &'static [u8; 12],
+        security_type: u32,
+        password: [u8; 8],
+    ) -> TcpStream {
+        // Stay connected until the client goes.
+        rfb33_session(version, security_type, password, |mut stream| {
+            let _ = stream.read(&mut [0; 1]);
+        })
+    }
+
+    /// `rfb33_server`, handing the connection to `session` after ServerInit.
+    fn rfb33_session<F>(
+        version: &'static [u8; 12],
+        security_type: u32,
+        password: [u8; 8],
+        session: F,
+    ) -> TcpStream
+    where
+        F: FnOnce(TcpStream) + Send + 'static,
+    {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            stream.write_all(version).unwrap();
+            let mut reply = [0; 12];
+            stream.read_exact(&mut reply).unwrap();
+            if &reply != b"RFB 003.003\n" {
+                return;
+            }
+            stream.write_u32::<BigEndian>(security_type).unwrap();
+            if security_type == 2 {
+                let challenge = [0x5A; 16];
+                stream.write_all(&challenge).unwrap();
+                let mut response = [0; 16];
+                stream.read_exact(&mut response).unwrap();
+                let accepted = des(&challenge, &password.map(u8::reverse_bits)) == response;
+                stream.write_u32::<BigEndian>(!accepted as u32).unwrap();
+                if !accepted {
+                    return;
+                }
+            }
+            let mut shared = [0; 1];
+            stream.read_exact(&mut shared).unwrap();
+            protocol::ServerInit {
+                framebuffer_width: 640,
+                framebuffer_height: 480,
+                pixel_format: protocol::PixelFormat::rgb888(),
+                name: "minimal".to_string(),
+            }
+            .write_to(&mut stream)
+            .unwrap();
+            session(stream);
+        });
+        TcpStream::connect(address).unwrap()
+    }
+
+    #[test]
+    fn test_rfb33_without_authentication() {
+        let stream = rfb33_server(b"RFB 003.003\n", 1, [0; 8]);
+        let client = Client::from_tcp_stream(stream, true, |methods| {
+            assert!(matches!(methods, [AuthMethod::None]));
+            Some(AuthChoice::None)
+        })
+        .unwrap();
+        assert_eq!(client.version(), protocol::Version::Rfb33);
+        assert_eq!(client.size(), (640, 480));
+    }
+
+    #[test]
+    fn test_rfb33_vnc_authentication() {
+        let password = *b"secret\0\0";
+        // Versions between 3.3 and 3.7 only know the 3.3 handshake.
+        let stream = rfb33_server(b"RFB 003.006\n", 2, password);
+        let client = Client::from_tcp_stream(stream, false, |methods| {
+            assert!(matches!(methods, [AuthMethod::Password]));
+            Some(AuthChoice::Password(password))
+        })
+        .unwrap();
+        assert_eq!(client.version(), protocol::Version::Rfb33);
+
+        let stream = rfb33_server(b"RFB 003.003\n", 2, password);
+        let result = Client::from_tcp_stream(stream, false, |_| {
+            Some(AuthChoice::Password(*b"wrong\0\0\0"))
+        });
+        assert!(matches!(result, Err(Error::AuthenticationFailure(_))));
+    }
+
+    /// Answers every update request with the whole screen as solid ZRLE tiles,
+    /// and passes on all the client sends.
+    fn serve_zrle(mut stream: TcpStream, messages: Sender<protocol::C2S>) {
+        let mut compressor =
+            flate2::write::ZlibEncoder::new(Vec::new(), flate2::Compression::fast());
+        while let Ok(message) = protocol::C2S::read_from(&mut stream) {
+            if let protocol::C2S::FramebufferUpdateRequest { .. } = message {
+                // 640x480 is 10x8 tiles of 64 pixels square.
+                for _ in 0..80 {
+                    compressor.write_all(&[1, 0, 0, 255]).unwrap();
+                }
+                compressor.flush().unwrap();
+                let data = std::mem::take(compressor.get_mut());
+                let mut update = Vec::new();
+                protocol::S2C::FramebufferUpdate { count: 1 }
+                    .write_to(&mut update)
+                    .unwrap();
+                let screen = Rect {
+                    left: 0,
+                    top: 0,
+                    width: 640,
+                    height: 480,
+                };
+                write_rectangle(&mut update, screen, protocol::Encoding::Zrle);
+                update.write_u32::<BigEndian>(data.len() as u32).unwrap();
+                update.extend_from_slice(&data);
+                stream.write_all(&update).unwrap();
+            }
+            let _ = messages.send(message);
+        }
+    }
+
+    /// What the client sends next, or `None` if it falls silent.
+    fn next_message(messages: &Receiver<protocol::C2S>, wait: Duration) -> Option<protocol::C2S> {
+        messages.recv_timeout(wait).ok()
+    }
+
+    #[test]
+    fn test_set_format_during_zrle_updates() {
+        let (tx_messages, rx_messages) = channel();
+        let stream = rfb33_session(b"RFB 003.003\n", 1, [0; 8], move |stream| {
+            serve_zrle(stream, tx_messages)
+        });
+        let mut client = Client::from_tcp_stream(stream, true, |_| Some(AuthChoice::None)).unwrap();
+        client.set_encodings(&[protocol::Encoding::Zrle]).unwrap();
+        let (width, height) = client.size();
+        let screen = Rect {
+            left: 0,
+            top: 0,
+            width,
+            height,
+        };
+        client.request_update(screen, true).unwrap();
+
+        let rgb565 = protocol::PixelFormat {
+            bits_per_pixel: 16,
+            depth: 16,
+            red_max: 31,
+            green_max: 63,
+            blue_max: 31,
+            red_shift: 11,
+            green_shift: 5,
+            blue_shift: 0,
+            ..protocol::PixelFormat::rgb888()
+        };
+        // No update arrives as a single rectangle, which must not stall the switch.
+        let (tx_done, rx_done) = channel();
+        thread::spawn(move || {
+            let result = client.set_format(rgb565);
+            let _ = tx_done.send((result.is_ok(), client.format()));
+        });
+        let (switched, format) = rx_done.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert!(switched);
+        assert_eq!(format, rgb565);
+        let sent = std::iter::from_fn(|| next_message(&rx_messages, Duration::from_secs(5)))
+            .find_map(|message| match message {
+                protocol::C2S::SetPixelFormat(format) => Some(format),
+                _ => None,
+            });
+        assert_eq!(sent, Some(rgb565));
+    }
+
+    #[test]
+    fn test_update_pacing() {
+        let (tx_messages, rx_messages) = channel();
+        let stream = rfb33_session(b"RFB 003.003\n", 1, [0; 8], move |stream| {
+            serve_zrle(stream, tx_messages)
+        });
+        let mut client = Client::from_tcp_stream(stream, true, |_| Some(AuthChoice::None)).unwrap();
+        client.set_encodings(&[protocol::Encoding::Zrle]).unwrap();
+        let (width, height) = client.size();
+        let screen = Rect {
+            left: 0,
+            top: 0,
+            width,
+            height,
+        };
+        let mut pacing = UpdatePacing {
+            area: screen,
+            max_fps: 20,
+            paused: false,
+        };
+        client.set_update_pacing(Some(pacing)).unwrap();
+        client.request_update(screen, false).unwrap();
+
+        // Events are never polled, yet each frame brings on the next request.
+        let paced = |message: &protocol::C2S| {
+            matches!(
+                message,
+                protocol::C2S::FramebufferUpdateRequest {
+                    incremental: true,
+                    ..
+                }
+            )
+        };
+        let requests = std::iter::from_fn(|| next_message(&rx_messages, Duration::from_secs(5)))
+            .filter(paced)
+            .take(3)
+            .count();
+        assert_eq!(requests, 3);
+
+        pacing.paused = true;
+        client.set_update_pacing(Some(pacing)).unwrap();
+        // Whatever was already on its way is let through, then nothing more.
+        while next_message(&rx_messages, Duration::from_millis(200)).is_some() {}
+        assert!(next_message(&rx_messages, Duration::from_millis(300)).is_none());
+    }
+
+    #[test]
+    fn test_version_numbers() {
+        let read = |version: &[u8]| protocol::Version::read_from(&mut &version[..]).ok();
+        assert_eq!(read(b"RFB 003.003\n"), Some(protocol::Version::Rfb33));
+        assert_eq!(read(b"RFB 003.005\n"), Some(protocol::Version::Rfb33));
+        assert_eq!(read(b"RFB 003.007\n"), Some(protocol::Version::Rfb37));
+        assert_eq!(read(b"RFB 003.889\n"), Some(protocol::Version::Rfb38));
+        assert_eq!(read(b"RFB 005.000\n"), Some(protocol::Version::Rfb38));
+        assert_eq!(read(b"RFB 002.000\n"), None);
+        assert_eq!(read(b"HTTP/1.1 400"), None);
+    }
+}
@@ -0,0 +1,230 @@
+use crate::protocol::Message;
+use crate::{Error, Result};
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+use std::io::{Read, Write};
+
+/* Extended clipboard pseudo-encoding. Once both sides have it enabled, cut text
+messages with a negative length carry a flags word followed by an action-specific
+payload instead of a Latin-1 string. */
+
+pub const FORMAT_TEXT: u32 = 1 << 0;
+pub const FORMAT_RTF: u32 = 1 << 1;
+pub const FORMAT_HTML: u32 = 1 << 2;
+pub const FORMAT_DIB: u32 = 1 << 3;
+pub const FORMAT_FILES: u32 = 1 << 4;
+const FORMAT_MASK: u32 = 0x0000ffff;
+
+pub const ACTION_CAPS: u32 = 1 << 24;
+pub const ACTION_REQUEST: u32 = 1 << 25;
+pub const ACTION_PEEK: u32 = 1 << 26;
+pub const ACTION_NOTIFY: u32 = 1 << 27;
+pub const ACTION_PROVIDE: u32 = 1 << 28;
+
+// Formats we can put on the wire, and the largest payload we accept for each.
+pub const CLIENT_FORMATS: u32 = FORMAT_TEXT | FORMAT_DIB;
+const CLIENT_MAX_SIZE: u32 = 20 * 1024 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Caps {
+    pub formats: u32,
+    pub actions: u32,
+}
+
+impl Caps {
+    pub fn supports(&self, format: u32) -> bool {
+        self.formats & format != 0
+    }
+}
+
+#[derive(Debug)]
+pub enum ExtendedClipboard {
+    Caps { caps: Caps, max_sizes: Vec<u32> },
+    Request(u32),
+    Peek,
+    Notify(u32),
+    Provide(Vec<(u32, Vec<u8>)>),
+}
+
+fn formats_of(flags: u32) -> impl Iterator<Item = u32> {
+    (0..16)
+        .map(|bit| 1u32 << bit)
+        .filter(move |format| flags & format != 0)
+}
+
+impl ExtendedClipboard {
+    pub fn client_caps() -> ExtendedClipboard {
+        ExtendedClipboard::Caps {
+            caps: Caps {
+                formats: CLIENT_FORMATS,
+                actions: ACTION_CAPS
+                    | ACTION_REQUEST
+                    | ACTION_PEEK
+                    | ACTION_NOTIFY
+                    | ACTION_PROVIDE,
+            },
+            max_sizes: formats_of(CLIENT_FORMATS)
+                .map(|_| CLIENT_MAX_SIZE)
+                .collect(),
+        }
+    }
+}
+
+impl Message for ExtendedClipboard {
+    fn read_from<R: Read>(reader: &mut R) -> Result<ExtendedClipboard> {
+        let flags = reader.read_u32::<BigEndian>()?;
+        let formats = flags & FORMAT_MASK;
+        if flags & ACTION_CAPS != 0 {
+            let mut max_sizes = Vec::new();
+            for _ in formats_of(formats) {
+                max_sizes.push(reader.read_u32::<BigEndian>()?);
+            }
+            Ok(ExtendedClipboard::Caps {
+                caps: Caps {
+                    formats,
+                    actions: flags & !FORMAT_MASK,
+                },
+                max_sizes,
+            })
+        } else if flags & ACTION_REQUEST != 0 {
+            Ok(ExtendedClipboard::Request(formats))
+        } else if flags & ACTION_PEEK != 0 {
+            Ok(ExtendedClipboard::Peek)
+        } else if flags & ACTION_NOTIFY != 0 {
+            Ok(ExtendedClipboard::Notify(formats))
+        } else if flags & ACTION_PROVIDE != 0 {
+            let mut compressed = Vec::new();
+            reader.read_to_end(&mut compressed)?;
+            let mut inflated = flate2::read::ZlibDecoder::new(&compressed[..]);
+            let mut items = Vec::new();
+            for format in formats_of(formats) {
+                let length = inflated.read_u32::<BigEndian>()?;
+                if length > CLIENT_MAX_SIZE {
+                    return Err(Error::Unexpected("extended clipboard payload size"));
+                }
+                let mut data = vec![0; length as usize];
+                inflated.read_exact(&mut data)?;
+                items.push((format, data));
+            }
+            Ok(ExtendedClipboard::Provide(items))
+        } else {
+            Err(Error::Unexpected("extended clipboard action"))
+        }
+    }
+
+    fn write_to<W: Write>(&self, writer: &mut W) -> Result<()> {
+        match self {
+            ExtendedClipboard::Caps { caps, max_sizes } => {
+                writer.write_u32::<BigEndian>(ACTION_CAPS | caps.actions | caps.formats)?;
+                for max_size in max_sizes {
+                    writer.write_u32::<BigEndian>(*max_size)?;
+                }
+            }
+            ExtendedClipboard::Request(formats) => {
+                writer.write_u32::<BigEndian>(ACTION_REQUEST | formats)?
+            }
+            ExtendedClipboard::Peek => writer.write_u32::<BigEndian>(ACTION_PEEK)?,
+            ExtendedClipboard::Notify(formats) => {
+                writer.write_u32::<BigEndian>(ACTION_NOTIFY | formats)?
+            }
+            ExtendedClipboard::Provide(ref items) => {
+                // Items must go out in ascending format order, as that is the
+                // only way the receiver can tell them apart.
+                let mut items: Vec<_> = items.iter().collect();
+                items.sort_by_key(|(format, _)| *format);
+                let formats = items.iter().fold(0, |acc, (format, _)| acc | format);
+                writer.write_u32::<BigEndian>(ACTION_PROVIDE | formats)?;
+
+                let mut deflated =
+                    flate2::write::ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+                for (_, data) in items {
+                    deflated.write_u32::<BigEndian>(data.len() as u32)?;
+                    deflated.write_all(data)?;
+                }
+                writer.write_all(&deflated.finish()?)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Converts extended clipboard text (UTF-8, CRLF, NUL-terminated) to a Rust string.
+pub fn text_from_wire(data: &[u8]) -> String {
+    let data = match data.iter().position(|&b| b == 0) {
+        Some(nul) => &data[..nul],
+        None => data,
+    };
+    String::from_utf8_lossy(data).replace("\r\n", "\n")
+}
+
+pub fn text_to_wire(text: &str) -> Vec<u8> {
+    let mut data = text
+        .replace("\r\n", "\n")
+        .replace('\n', "\r\n")
+        .into_bytes();
+    data.push(0);
+    data
+}
+
+/// Encodes a top-down RGBA image as a packed 32bpp DIB (a BMP without the file header).
+pub fn rgba_to_dib(width: usize, height: usize, rgba: &[u8]) -> Vec<u8> {
+    let image_size = width * height * 4;
+    let mut dib = Vec::with_capacity(40 + image_size);
+    // BITMAPINFOHEADER
+    dib.extend_from_slice(&40u32.to_le_bytes());
+    dib.extend_from_slice(&(width as i32).to_le_bytes());
+    dib.extend_from_slice(&(height as i32).to_le_bytes()); // positive: bottom-up rows
+    dib.extend_from_slice(&1u16.to_le_bytes()); // planes
+    dib.extend_from_slice(&32u16.to_le_bytes()); // bit count
+    dib.extend_from_slice(&0u32.to_le_bytes()); // BI_RGB
+    dib.extend_from_slice(&(image_size as u32).to_le_bytes());
+    dib.extend_from_slice(&2835i32.to_le_bytes()); // 72 DPI
+    dib.extend_from_slice(&2835i32.to_le_bytes());
+    dib.extend_from_slice(&0u32.to_le_bytes()); // colours used
+    dib.extend_from_slice(&0u32.to_le_bytes()); // colours important
+
+    if width == 0 {
+        return dib;
+    }
+    for row in rgba.chunks_exact(width * 4).take(height).rev() {
+        for pixel in row.chunks_exact(4) {
+            dib.extend_from_slice(&[pixel[2], pixel[1], pixel[0], pixel[3]]);
+        }
+    }
+    dib
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_provide_roundtrip() {
+        let message = ExtendedClipboard::Provide(vec![
+            (FORMAT_DIB, vec![1, 2, 3]),
+            (FORMAT_TEXT, text_to_wire("a\nb")),
+        ]);
+        let mut buffer = Vec::new();
+        message.write_to(&mut buffer).unwrap();
+
+        match ExtendedClipboard::read_from(&mut Cursor::new(buffer)).unwrap() {
+            ExtendedClipboard::Provide(items) => {
+                assert_eq!(items.len(), 2);
+                assert_eq!(items[0].0, FORMAT_TEXT);
+                assert_eq!(text_from_wire(&items[0].1), "a\nb");
+                assert_eq!(items[1], (FORMAT_DIB, vec![1, 2, 3]));
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_rgba_to_dib() {
+        // 1x2 image: red on top, blue below.
+        let dib = rgba_to_dib(1, 2, &[255, 0, 0, 255, 0, 0, 255, 255]);
+        assert_eq!(dib.len(), 40 + 8);
+        // Rows are stored bottom-up as BGRA.
+        assert_eq!(&dib[40..44], &[255, 0, 0, 255]);
+        assert_eq!(&dib[44..48], &[0, 0, 255, 255]);
+    }
+}
@@ -0,0 +1,169 @@
+use crate::{protocol, Error, Rect, Result};
+use byteorder::ReadBytesExt;
+use std::io::Read;
+
+const RAW: u8 = 1;
+const BACKGROUND_SPECIFIED: u8 = 2;
+const FOREGROUND_SPECIFIED: u8 = 4;
+const ANY_SUBRECTS: u8 = 8;
+const SUBRECTS_COLOURED: u8 = 16;
+
+const TILE_SIZE: u16 = 16;
+
+/// Decodes a Hextile rectangle, read straight from the connection. It comes in
+/// tiles 16 pixels square, each either raw or a background with solid
+/// subrectangles on it. The background and foreground carry over from one tile
+/// to the next.
+pub fn decode<F>(
+    format: protocol::PixelFormat,
+    rect: Rect,
+    reader: &mut dyn Read,
+    mut callback: F,
+) -> Result<bool>
+where
+    F: FnMut(Rect, Vec<u8>) -> Result<bool>,
+{
+    let bpp = format.bits_per_pixel as usize / 8;
+    let mut background = vec![0; bpp];
+    let mut foreground = vec![0; bpp];
+
+    let mut y = 0;
+    while y < rect.height {
+        let height = TILE_SIZE.min(rect.height - y);
+        let mut x = 0;
+        while x < rect.width {
+            let width = TILE_SIZE.min(rect.width - x);
+            let mut pixels = vec![0; width as usize * height as usize * bpp];
+
+            let subencoding = reader.read_u8()?;
+            if subencoding & RAW != 0 {
+                reader.read_exact(&mut pixels)?;
+            } else {
+                if subencoding & BACKGROUND_SPECIFIED != 0 {
+                    reader.read_exact(&mut background)?;
+                }
+                if subencoding & FOREGROUND_SPECIFIED != 0 {
+                    reader.read_exact(&mut foreground)?;
+                }
+                for pixel in pixels.chunks_exact_mut(bpp) {
+                    pixel.copy_from_slice(&background);
+                }
+                if subencoding & ANY_SUBRECTS != 0 {
+                    let count = reader.read_u8()?;
+                    for _ in 0..count {
+                        // A coloured subrectangle leaves the foreground undefined,
+                        // so its colour can simply take its place.
+                        if subencoding & SUBRECTS_COLOURED != 0 {
+                            reader.read_exact(&mut foreground)?;
+                        }
+                        let position = reader.read_u8()?;
+                        let size = reader.read_u8()?;
+                        let (left, top) = ((position >> 4) as usize, (position & 15) as usize);
+                        let (sub_width, sub_height) =
+                            ((size >> 4) as usize + 1, (size & 15) as usize + 1);
+                        if left + sub_width > width as usize || top + sub_height > height as usize {
+                            return Err(Error::Unexpected("hextile subrectangle"));
+                        }
+                        for row in top..top + sub_height {
+                            let start = (row * width as usize + left) * bpp;
+                            for pixel in
+                                pixels[start..start + sub_width * bpp].chunks_exact_mut(bpp)
+                            {
+                                pixel.copy_from_slice(&foreground);
+                            }
+                        }
+                    }
+                }
+            }
+
+            let tile = Rect {
+                left: rect.left + x,
+                top: rect.top + y,
+                width,
+                height,
+            };
+            if !callback(tile, pixels)? {
+                return Ok(false);
+            }
+            x += width;
+        }
+        y += height;
+    }
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decode_all(rect: Rect, input: &[u8]) -> Result<Vec<(Rect, Vec<u8>)>> {
+        let mut tiles = Vec::new();
+        decode(
+            protocol::PixelFormat::rgb888(),
+            rect,
+            &mut &input[..],
+            |tile, pixels| {
+                tiles.push((tile, pixels));
+                Ok(true)
+            },
+        )?;
+        Ok(tiles)
+    }
+
+    #[test]
+    fn test_subrects() {
+        let (red, blue, white) = ([0, 0, 255, 0], [255, 0, 0, 0], [255, 255, 255, 0]);
+        let mut input = vec![BACKGROUND_SPECIFIED | FOREGROUND_SPECIFIED | ANY_SUBRECTS];
+        input.extend_from_slice(&white);
+        input.extend_from_slice(&red);
+        // One subrectangle, 2x1 at (1, 0).
+        input.extend_from_slice(&[1, 0x10, 0x10]);
+        // The next tile keeps the white background and colours its own subrectangle.
+        input.extend_from_slice(&[ANY_SUBRECTS | SUBRECTS_COLOURED, 1]);
+        input.extend_from_slice(&blue);
+        input.extend_from_slice(&[0x01, 0x00]);
+
+        let rect = Rect {
+            left: 8,
+            top: 4,
+            width: 19,
+            height: 2,
+        };
+        let tiles = decode_all(rect, &input).unwrap();
+        assert_eq!(tiles.len(), 2);
+
+        let (tile, ref pixels) = tiles[0];
+        assert_eq!(
+            (tile.left, tile.top, tile.width, tile.height),
+            (8, 4, 16, 2)
+        );
+        let mut first = [white; 32];
+        first[1] = red;
+        first[2] = red;
+        assert_eq!(pixels, &first.concat());
+
+        let (tile, ref pixels) = tiles[1];
+        assert_eq!((tile.left, tile.width), (24, 3));
+        let mut second = [white; 6];
+        second[3] = blue;
+        assert_eq!(pixels, &second.concat());
+    }
+
+    #[test]
+    fn test_raw_tile() {
+        let rect = Rect {
+            left: 0,
+            top: 0,
+            width: 2,
+            height: 1,
+        };
+        let input = [RAW, 1, 2, 3, 0, 4, 5, 6, 0];
+        let tiles = decode_all(rect, &input).unwrap();
+        assert_eq!(tiles, vec![(rect, vec![1, 2, 3, 0, 4, 5, 6, 0])]);
+
+        // A subrectangle running off the tile.
+        let mut input = vec![BACKGROUND_SPECIFIED | ANY_SUBRECTS];
+        input.extend_from_slice(&[0, 0, 0, 0, 1, 0x10, 0x10]);
+        assert!(decode_all(rect, &input).is_err());
+    }
+}
@@ -8,16 +8,20 @@ use num_bigint;
 #[cfg(feature = "apple-auth")]
 use octavo;
 
+mod hextile;
 mod protocol;
 mod security;
+mod tight;
 mod zrle;
 
 pub mod client;
+pub mod clipboard;
 pub mod proxy;
 
-pub use client::Client;
-pub use protocol::{Colour, Encoding, PixelFormat};
+pub use client::{Client, Stats, UpdatePacing};
+pub use protocol::{Colour, Encoding, PixelFormat, Screen, SecurityType, Version};
 pub use proxy::Proxy;
+pub use security::unobfuscate_password;
 
 #[derive(Debug, Clone, Copy, Eq, PartialEq)]
 pub struct Rect {
@@ -34,6 +38,8 @@ pub enum Error {
     Server(String),
     AuthenticationUnavailable,
     AuthenticationFailure(String),
+    /// The TLS handshake or certificate check failed.
+    Tls(String),
     Disconnected,
 }
 
@@ -47,6 +53,7 @@ impl std::fmt::Display for Error {
                 write!(f, "authentication failure: {}", descr)
             }
             Error::AuthenticationUnavailable => write!(f, "authentication unavailable"),
+            Error::Tls(ref descr) => write!(f, "TLS error: {}", descr),
             Error::Disconnected => write!(f, "disconnected"),
         }
     }
@@ -45,6 +45,25 @@ impl Message for String {
     }
 }
 
+/* With the extended clipboard enabled, a negative cut text length means
+the payload is an extended clipboard message rather than a Latin-1 string. */
+fn read_cut_text<R: Read>(reader: &mut R) -> Result<std::result::Result<String, Vec<u8>>> {
+    let length = reader.read_i32::<BigEndian>()?;
+    let mut buffer = vec![0; length.unsigned_abs() as usize];
+    reader.read_exact(&mut buffer)?;
+    if length >= 0 {
+        Ok(Ok(buffer.iter().map(|c| *c as char).collect()))
+    } else {
+        Ok(Err(buffer))
+    }
+}
+
+fn write_extended_cut_text<W: Write>(payload: &[u8], writer: &mut W) -> Result<()> {
+    writer.write_i32::<BigEndian>(-(payload.len() as i32))?;
+    writer.write_all(payload)?;
+    Ok(())
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Version {
     Rfb33,
@@ -52,16 +71,23 @@ pub enum Version {
     Rfb38,
 }
 
+/* Servers announce other versions too, such as 3.5 or 3.6 from old viewers'
+days, 3.889 from Apple and 4.x or 5.0 from RealVNC. Anything before 3.7 only
+knows the 3.3 handshake, and anything after 3.8 speaks 3.8 as well. */
 impl Message for Version {
     fn read_from<R: Read>(reader: &mut R) -> Result<Version> {
         let mut buf = [0; 12];
         reader.read_exact(&mut buf)?;
-        match &buf {
-            b"RFB 003.003\n" => Ok(Version::Rfb33),
-            b"RFB 003.007\n" => Ok(Version::Rfb37),
-            b"RFB 003.008\n" => Ok(Version::Rfb38),
-            // Apple remote desktop
-            b"RFB 003.889\n" => Ok(Version::Rfb38),
+        let number =
+            |digits: &[u8]| -> Option<u32> { std::str::from_utf8(digits).ok()?.parse().ok() };
+        let (major, minor) = match (&buf[..4], buf[7], buf[11]) {
+            (b"RFB ", b'.', b'\n') => (number(&buf[4..7]), number(&buf[8..11])),
+            _ => (None, None),
+        };
+        match (major, minor) {
+            (Some(3), Some(0..=6)) => Ok(Version::Rfb33),
+            (Some(3), Some(7)) => Ok(Version::Rfb37),
+            (Some(3..), Some(_)) => Ok(Version::Rfb38),
             _ => Err(Error::Unexpected("protocol version")),
         }
     }
@@ -83,8 +109,10 @@ pub enum SecurityType {
     Invalid,
     None,
     VncAuthentication,
+    VeNCrypt,
     // extensions
     AppleRemoteDesktop,
+    MsLogon,
 }
 
 impl Message for SecurityType {
@@ -94,7 +122,9 @@ impl Message for SecurityType {
             0 => Ok(SecurityType::Invalid),
             1 => Ok(SecurityType::None),
             2 => Ok(SecurityType::VncAuthentication),
+            19 => Ok(SecurityType::VeNCrypt),
             30 => Ok(SecurityType::AppleRemoteDesktop),
+            113 => Ok(SecurityType::MsLogon),
             n => Ok(SecurityType::Unknown(n)),
         }
     }
@@ -104,7 +134,9 @@ impl Message for SecurityType {
             SecurityType::Invalid => 0,
             SecurityType::None => 1,
             SecurityType::VncAuthentication => 2,
+            SecurityType::VeNCrypt => 19,
             SecurityType::AppleRemoteDesktop => 30,
+            SecurityType::MsLogon => 113,
             SecurityType::Unknown(n) => *n,
         };
         writer.write_u8(security_type)?;
@@ -245,6 +277,26 @@ pub struct PixelFormat {
     pub blue_shift: u8,
 }
 
+#[cfg(test)]
+impl PixelFormat {
+    /// The format the tests use: 32 bits per pixel, little-endian, with a byte
+    /// each for red, green and blue.
+    pub fn rgb888() -> PixelFormat {
+        PixelFormat {
+            bits_per_pixel: 32,
+            depth: 24,
+            big_endian: false,
+            true_colour: true,
+            red_max: 255,
+            green_max: 255,
+            blue_max: 255,
+            red_shift: 16,
+            green_shift: 8,
+            blue_shift: 0,
+        }
+    }
+}
+
 impl Message for PixelFormat {
     fn read_from<R: Read>(reader: &mut R) -> Result<PixelFormat> {
         let pixel_format = PixelFormat {
@@ -327,6 +379,41 @@ impl Message for CopyRect {
     }
 }
 
+/// One screen of the layout carried by ExtendedDesktopSize, in framebuffer
+/// coordinates.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Screen {
+    pub id: u32,
+    pub x_position: u16,
+    pub y_position: u16,
+    pub width: u16,
+    pub height: u16,
+    pub flags: u32,
+}
+
+impl Message for Screen {
+    fn read_from<R: Read>(reader: &mut R) -> Result<Screen> {
+        Ok(Screen {
+            id: reader.read_u32::<BigEndian>()?,
+            x_position: reader.read_u16::<BigEndian>()?,
+            y_position: reader.read_u16::<BigEndian>()?,
+            width: reader.read_u16::<BigEndian>()?,
+            height: reader.read_u16::<BigEndian>()?,
+            flags: reader.read_u32::<BigEndian>()?,
+        })
+    }
+
+    fn write_to<W: Write>(&self, writer: &mut W) -> Result<()> {
+        writer.write_u32::<BigEndian>(self.id)?;
+        writer.write_u16::<BigEndian>(self.x_position)?;
+        writer.write_u16::<BigEndian>(self.y_position)?;
+        writer.write_u16::<BigEndian>(self.width)?;
+        writer.write_u16::<BigEndian>(self.height)?;
+        writer.write_u32::<BigEndian>(self.flags)?;
+        Ok(())
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Encoding {
     Unknown(i32),
@@ -335,10 +422,19 @@ pub enum Encoding {
     CopyRect,
     Rre,
     Hextile,
+    Tight,
+    Trle,
     Zrle,
     Cursor,
     DesktopSize,
     // extensions
+    LastRect,
+    /// Tight zlib level, 0 to 9.
+    CompressionLevel(u8),
+    /// Tight JPEG quality, 0 to 9.
+    QualityLevel(u8),
+    ExtendedDesktopSize,
+    ExtendedClipboard,
 }
 
 impl Message for Encoding {
@@ -349,9 +445,16 @@ impl Message for Encoding {
             1 => Ok(Encoding::CopyRect),
             2 => Ok(Encoding::Rre),
             5 => Ok(Encoding::Hextile),
+            7 => Ok(Encoding::Tight),
+            15 => Ok(Encoding::Trle),
             16 => Ok(Encoding::Zrle),
             -239 => Ok(Encoding::Cursor),
             -223 => Ok(Encoding::DesktopSize),
+            -224 => Ok(Encoding::LastRect),
+            -256..=-247 => Ok(Encoding::CompressionLevel((encoding + 256) as u8)),
+            -32..=-23 => Ok(Encoding::QualityLevel((encoding + 32) as u8)),
+            -308 => Ok(Encoding::ExtendedDesktopSize),
+            -1063131698 => Ok(Encoding::ExtendedClipboard),
             n => Ok(Encoding::Unknown(n)),
         }
     }
@@ -362,9 +465,16 @@ impl Message for Encoding {
             Encoding::CopyRect => 1,
             Encoding::Rre => 2,
             Encoding::Hextile => 5,
+            Encoding::Tight => 7,
+            Encoding::Trle => 15,
             Encoding::Zrle => 16,
             Encoding::Cursor => -239,
             Encoding::DesktopSize => -223,
+            Encoding::LastRect => -224,
+            Encoding::CompressionLevel(level) => -256 + (*level).min(9) as i32,
+            Encoding::QualityLevel(level) => -32 + (*level).min(9) as i32,
+            Encoding::ExtendedDesktopSize => -308,
+            Encoding::ExtendedClipboard => -1063131698,
             Encoding::Unknown(n) => *n,
         };
         writer.write_i32::<BigEndian>(encoding)?;
@@ -395,6 +505,12 @@ pub enum C2S {
     },
     CutText(String),
     // extensions
+    ExtendedCutText(Vec<u8>),
+    SetDesktopSize {
+        width: u16,
+        height: u16,
+        screens: Vec<Screen>,
+    },
 }
 
 impl Message for C2S {
@@ -439,7 +555,25 @@ impl Message for C2S {
             }),
             6 => {
                 reader.read_exact(&mut [0u8; 3])?;
-                Ok(C2S::CutText(String::read_from(reader)?))
+                match read_cut_text(reader)? {
+                    Ok(text) => Ok(C2S::CutText(text)),
+                    Err(payload) => Ok(C2S::ExtendedCutText(payload)),
+                }
+            }
+            251 => {
+                reader.read_exact(&mut [0u8; 1])?;
+                let width = reader.read_u16::<BigEndian>()?;
+                let height = reader.read_u16::<BigEndian>()?;
+                let count = reader.read_u8()?;
+                reader.read_exact(&mut [0u8; 1])?;
+                let screens = (0..count)
+                    .map(|_| Screen::read_from(reader))
+                    .collect::<Result<Vec<_>>>()?;
+                Ok(C2S::SetDesktopSize {
+                    width,
+                    height,
+                    screens,
+                })
             }
             _ => Err(Error::Unexpected("client to server message type")),
         }
@@ -490,8 +624,30 @@ impl Message for C2S {
                 writer.write_u16::<BigEndian>(*y_position)?;
             }
             C2S::CutText(ref text) => {
+                writer.write_u8(6)?;
+                writer.write_all(&[0u8; 3])?;
                 String::write_to(text, writer)?;
             }
+            C2S::ExtendedCutText(ref payload) => {
+                writer.write_u8(6)?;
+                writer.write_all(&[0u8; 3])?;
+                write_extended_cut_text(payload, writer)?;
+            }
+            C2S::SetDesktopSize {
+                width,
+                height,
+                screens,
+            } => {
+                writer.write_u8(251)?;
+                writer.write_all(&[0u8; 1])?;
+                writer.write_u16::<BigEndian>(*width)?;
+                writer.write_u16::<BigEndian>(*height)?;
+                writer.write_u8(screens.len() as u8)?;
+                writer.write_all(&[0u8; 1])?;
+                for screen in screens {
+                    screen.write_to(writer)?;
+                }
+            }
         }
         Ok(())
     }
@@ -565,6 +721,7 @@ pub enum S2C {
     Bell,
     CutText(String),
     // extensions
+    ExtendedCutText(Vec<u8>),
 }
 
 impl Message for S2C {
@@ -598,7 +755,10 @@ impl Message for S2C {
             2 => Ok(S2C::Bell),
             3 => {
                 reader.read_exact(&mut [0u8; 3])?;
-                Ok(S2C::CutText(String::read_from(reader)?))
+                match read_cut_text(reader)? {
+                    Ok(text) => Ok(S2C::CutText(text)),
+                    Err(payload) => Ok(S2C::ExtendedCutText(payload)),
+                }
             }
             _ => Err(Error::Unexpected("server to client message type")),
         }
@@ -630,6 +790,11 @@ impl Message for S2C {
                 writer.write_all(&[0u8; 3])?;
                 String::write_to(text, writer)?;
             }
+            S2C::ExtendedCutText(ref payload) => {
+                writer.write_u8(3)?;
+                writer.write_all(&[0u8; 3])?;
+                write_extended_cut_text(payload, writer)?;
+            }
         }
         Ok(())
     }
@@ -1,5 +1,26 @@
 mod des;
+mod mslogon;
 pub use self::des::encrypt as des;
+pub use self::mslogon::mslogon;
+
+/// The fixed key VNC software saves passwords under. Like the password in VNC
+/// authentication, it goes to DES with the bits of each byte reversed.
+const SAVED_PASSWORD_KEY: [u8; 8] = [23, 82, 107, 6, 35, 78, 88, 7];
+
+/// Recovers a password as saved by other VNC software, for instance in a `.vnc`
+/// file. The encryption only keeps it from being read at a glance.
+pub fn unobfuscate_password(saved: &[u8]) -> Vec<u8> {
+    let mut password = des::decrypt(saved, &SAVED_PASSWORD_KEY.map(u8::reverse_bits));
+    if let Some(end) = password.iter().position(|&byte| byte == 0) {
+        password.truncate(end);
+    }
+    password
+}
+
+#[cfg(feature = "vencrypt")]
+mod vencrypt;
+#[cfg(feature = "vencrypt")]
+pub use self::vencrypt::{vencrypt, TlsStream, X509_VNC};
 
 #[cfg(feature = "apple-auth")]
 mod apple;
@@ -7,3 +28,16 @@ mod apple;
 mod md5;
 #[cfg(feature = "apple-auth")]
 pub use self::apple::apple_auth;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unobfuscate_password() {
+        let saved = [0xDB, 0xD8, 0x3C, 0xFD, 0x72, 0x7A, 0x14, 0x58];
+        assert_eq!(unobfuscate_password(&saved), b"password");
+        let short = des::encrypt(b"abc\0\0\0\0\0", &SAVED_PASSWORD_KEY.map(u8::reverse_bits));
+        assert_eq!(unobfuscate_password(&short), b"abc");
+    }
+}
@@ -0,0 +1,109 @@
+use crate::security::des;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+
+/* UltraVNC MS-Logon II. A Diffie-Hellman exchange over 64-bit numbers yields a
+DES key, which encrypts fixed-size, NUL-padded username and password fields in
+CBC mode with the key doubling as the IV. Only as strong as it sounds. */
+
+pub const USERNAME_LENGTH: usize = 256;
+pub const PASSWORD_LENGTH: usize = 64;
+
+pub struct MsLogonResponse {
+    pub public_key: u64,
+    pub username: [u8; USERNAME_LENGTH],
+    pub password: [u8; PASSWORD_LENGTH],
+}
+
+fn mod_pow(base: u64, mut exponent: u64, modulus: u64) -> u64 {
+    let modulus = modulus as u128;
+    let mut result = 1u128 % modulus;
+    let mut base = base as u128 % modulus;
+    while exponent > 0 {
+        if exponent & 1 == 1 {
+            result = result * base % modulus;
+        }
+        base = base * base % modulus;
+        exponent >>= 1;
+    }
+    result as u64
+}
+
+fn random_u64() -> u64 {
+    RandomState::new().build_hasher().finish()
+}
+
+/// DES-CBC as done by UltraVNC's `vncEncryptBytes2`, in place.
+fn encrypt_cbc(data: &mut [u8], key: [u8; 8]) {
+    // Same bit order quirk as the classic VNC password, see `Client::from_tcp_stream`.
+    let des_key = key.map(u8::reverse_bits);
+    let mut previous = key;
+    for block in data.chunks_exact_mut(8) {
+        for (byte, previous) in block.iter_mut().zip(previous) {
+            *byte ^= previous;
+        }
+        block.copy_from_slice(&des(block, &des_key));
+        previous.copy_from_slice(block);
+    }
+}
+
+fn credential<const N: usize>(value: &str) -> [u8; N] {
+    let mut field = [0u8; N];
+    // Leave room for the terminating NUL.
+    let length = value.len().min(N - 1);
+    field[..length].copy_from_slice(&value.as_bytes()[..length]);
+    field
+}
+
+pub fn mslogon(
+    generator: u64,
+    modulus: u64,
+    server_key: u64,
+    username: &str,
+    password: &str,
+) -> MsLogonResponse {
+    let private_key = random_u64() % modulus.max(2);
+    let public_key = mod_pow(generator, private_key, modulus);
+    let key = mod_pow(server_key, private_key, modulus).to_be_bytes();
+
+    let mut response = MsLogonResponse {
+        public_key,
+        username: credential(username),
+        password: credential(password),
+    };
+    encrypt_cbc(&mut response.username, key);
+    encrypt_cbc(&mut response.password, key);
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::security::des::decrypt;
+
+    #[test]
+    fn test_key_agreement() {
+        let (generator, modulus) = (5, 0xFFFF_FFFF_FFFF_FFC5);
+        let (a, b) = (0x1234_5678_9abc, 0x0fed_cba9_8765);
+        let (public_a, public_b) = (
+            mod_pow(generator, a, modulus),
+            mod_pow(generator, b, modulus),
+        );
+        assert_eq!(mod_pow(public_b, a, modulus), mod_pow(public_a, b, modulus));
+    }
+
+    #[test]
+    fn test_encrypt_cbc() {
+        let key = *b"secret!!";
+        let mut data: [u8; 16] = credential("administrator");
+        encrypt_cbc(&mut data, key);
+
+        let des_key = key.map(u8::reverse_bits);
+        let mut plain = decrypt(&data[..8], &des_key);
+        plain.extend(decrypt(&data[8..], &des_key));
+        for (i, byte) in plain.iter_mut().enumerate() {
+            *byte ^= if i < 8 { key[i] } else { data[i - 8] };
+        }
+        assert_eq!(&plain, b"administrator\0\0\0");
+    }
+}
@@ -0,0 +1,265 @@
+use crate::{Error, Result};
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+use log::info;
+use rustls::client::{ServerCertVerified, ServerCertVerifier};
+use rustls::{
+    Certificate, ClientConfig, ClientConnection, OwnedTrustAnchor, RootCertStore, ServerName,
+};
+use std::io::{self, Read, Write};
+use std::net::TcpStream;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::SystemTime;
+
+/* VeNCrypt wraps the rest of the session in TLS and then runs one of the usual
+authentications inside it. Only the X509 subtypes are supported, since rustls has
+no anonymous cipher suites for the plain TLS ones. */
+
+const VERSION: (u8, u8) = (0, 2);
+
+pub const X509_NONE: u32 = 260;
+pub const X509_VNC: u32 = 261;
+pub const X509_PLAIN: u32 = 262;
+
+const READ_BUFFER_SIZE: usize = 16 * 1024;
+
+/// Negotiates a VeNCrypt subtype, sets up TLS and, for the Plain subtype, sends
+/// the credentials. Returns the encrypted stream and the subtype in use; with
+/// `X509_VNC` the caller carries on with VNC authentication over the stream.
+pub fn vencrypt(
+    stream: &mut TcpStream,
+    server_name: &str,
+    verify_certificate: bool,
+    username: &str,
+    password: &str,
+) -> Result<(TlsStream, u32)> {
+    let version = (stream.read_u8()?, stream.read_u8()?);
+    info!("<- VeNCrypt version {}.{}", version.0, version.1);
+    if version < VERSION {
+        return Err(Error::Unexpected("VeNCrypt version"));
+    }
+    stream.write_all(&[VERSION.0, VERSION.1])?;
+    if stream.read_u8()? != 0 {
+        return Err(Error::Server("VeNCrypt version rejected".to_string()));
+    }
+
+    let count = stream.read_u8()?;
+    let subtypes = (0..count)
+        .map(|_| stream.read_u32::<BigEndian>())
+        .collect::<io::Result<Vec<u32>>>()?;
+    info!("<- VeNCrypt subtypes: {:?}", subtypes);
+    let subtype = choose_subtype(&subtypes, username).ok_or_else(|| {
+        Error::Tls("the server offers no certificate based VeNCrypt subtype".to_string())
+    })?;
+    info!("-> VeNCrypt subtype: {}", subtype);
+    stream.write_u32::<BigEndian>(subtype)?;
+    if stream.read_u8()? != 1 {
+        return Err(Error::Server("VeNCrypt subtype rejected".to_string()));
+    }
+
+    let mut tls = TlsStream::connect(stream.try_clone()?, server_name, verify_certificate)?;
+    if subtype == X509_PLAIN {
+        tls.write_u32::<BigEndian>(username.len() as u32)?;
+        tls.write_u32::<BigEndian>(password.len() as u32)?;
+        tls.write_all(username.as_bytes())?;
+        tls.write_all(password.as_bytes())?;
+    }
+    Ok((tls, subtype))
+}
+
+/// Plain login if there is a username, otherwise VNC authentication or none.
+fn choose_subtype(offered: &[u32], username: &str) -> Option<u32> {
+    let preferred: &[u32] = if username.is_empty() {
+        &[X509_VNC, X509_NONE]
+    } else {
+        &[X509_PLAIN, X509_VNC, X509_NONE]
+    };
+    preferred
+        .iter()
+        .copied()
+        .find(|subtype| offered.contains(subtype))
+}
+
+fn config(verify_certificate: bool) -> ClientConfig {
+    let mut roots = RootCertStore::empty();
+    roots.add_trust_anchors(webpki_roots::TLS_SERVER_ROOTS.iter().map(|anchor| {
+        OwnedTrustAnchor::from_subject_spki_name_constraints(
+            anchor.subject,
+            anchor.spki,
+            anchor.name_constraints,
+        )
+    }));
+    let mut config = ClientConfig::builder()
+        .with_safe_defaults()
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+    if !verify_certificate {
+        config
+            .dangerous()
+            .set_certificate_verifier(Arc::new(AcceptAnyCertificate));
+    }
+    config
+}
+
+/// Most VNC servers use a self-signed certificate, which the user can choose to
+/// trust. The connection is then encrypted but not authenticated.
+struct AcceptAnyCertificate;
+
+impl ServerCertVerifier for AcceptAnyCertificate {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &Certificate,
+        _intermediates: &[Certificate],
+        _server_name: &ServerName,
+        _scts: &mut dyn Iterator<Item = &[u8]>,
+        _ocsp_response: &[u8],
+        _now: SystemTime,
+    ) -> std::result::Result<ServerCertVerified, rustls::Error> {
+        Ok(ServerCertVerified::assertion())
+    }
+}
+
+/// A TLS session shared between the reading and the writing thread, each with a
+/// handle of its own (see `try_clone`).
+pub struct TlsStream {
+    session: Arc<Mutex<ClientConnection>>,
+    /// Locked while ciphertext is written, so records from both handles stay in order.
+    writer: Arc<Mutex<TcpStream>>,
+    socket: TcpStream,
+    /// Ciphertext read from the socket but not yet handed to the session.
+    incoming: Vec<u8>,
+    consumed: usize,
+}
+
+impl TlsStream {
+    fn connect(
+        mut socket: TcpStream,
+        server_name: &str,
+        verify_certificate: bool,
+    ) -> Result<TlsStream> {
+        let name = ServerName::try_from(server_name)
+            .map_err(|_| Error::Tls(format!("invalid server name {:?}", server_name)))?;
+        let mut session = ClientConnection::new(Arc::new(config(verify_certificate)), name)
+            .map_err(|e| Error::Tls(e.to_string()))?;
+        while session.is_handshaking() {
+            match session.complete_io(&mut socket) {
+                Ok((0, 0)) => {
+                    return Err(Error::Tls("connection closed during handshake".to_string()))
+                }
+                Ok(_) => (),
+                Err(e) => return Err(Error::Tls(e.to_string())),
+            }
+        }
+        info!(
+            "TLS established: {:?}, {:?}",
+            session.protocol_version(),
+            session.negotiated_cipher_suite().map(|suite| suite.suite())
+        );
+
+        Ok(TlsStream {
+            session: Arc::new(Mutex::new(session)),
+            writer: Arc::new(Mutex::new(socket.try_clone()?)),
+            socket,
+            incoming: Vec::new(),
+            consumed: 0,
+        })
+    }
+
+    pub fn try_clone(&self) -> io::Result<TlsStream> {
+        Ok(TlsStream {
+            session: self.session.clone(),
+            writer: self.writer.clone(),
+            socket: self.socket.try_clone()?,
+            incoming: Vec::new(),
+            consumed: 0,
+        })
+    }
+
+    /// Writes out whatever the session has queued. The socket is taken before the
+    /// session is let go, but a slow write doesn't hold up the reading side.
+    fn send_queued(&self, mut session: MutexGuard<ClientConnection>) -> io::Result<()> {
+        if !session.wants_write() {
+            return Ok(());
+        }
+        let mut ciphertext = Vec::new();
+        while session.wants_write() {
+            session.write_tls(&mut ciphertext)?;
+        }
+        let mut socket = self.writer.lock().unwrap();
+        drop(session);
+        socket.write_all(&ciphertext)
+    }
+}
+
+impl Read for TlsStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            {
+                let mut session = self.session.lock().unwrap();
+                match session.reader().read(buf) {
+                    Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => (),
+                    result => return result,
+                }
+                if self.consumed < self.incoming.len() {
+                    let mut pending = &self.incoming[self.consumed..];
+                    let length = pending.len();
+                    session.read_tls(&mut pending)?;
+                    self.consumed += length - pending.len();
+                    session
+                        .process_new_packets()
+                        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+                    // Alerts and key updates are answered right away.
+                    self.send_queued(session)?;
+                    continue;
+                }
+            }
+
+            // Wait for more without holding the session, so writes can go ahead.
+            self.incoming.resize(READ_BUFFER_SIZE, 0);
+            let length = self.socket.read(&mut self.incoming)?;
+            self.incoming.truncate(length);
+            self.consumed = 0;
+            if length == 0 {
+                return Ok(0);
+            }
+        }
+    }
+}
+
+impl Write for TlsStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut session = self.session.lock().unwrap();
+        let length = session.writer().write(buf)?;
+        self.send_queued(session)?;
+        Ok(length)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        let mut session = self.session.lock().unwrap();
+        session.writer().flush()?;
+        self.send_queued(session)?;
+        self.writer.lock().unwrap().flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_choose_subtype() {
+        // TLS (anonymous) subtypes and unencrypted Plain are never picked.
+        assert_eq!(choose_subtype(&[256, 257, 258, 259], ""), None);
+        assert_eq!(
+            choose_subtype(&[258, X509_NONE, X509_VNC], ""),
+            Some(X509_VNC)
+        );
+        assert_eq!(
+            choose_subtype(&[X509_NONE, X509_VNC, X509_PLAIN], "admin"),
+            Some(X509_PLAIN)
+        );
+        assert_eq!(
+            choose_subtype(&[X509_PLAIN, X509_NONE], ""),
+            Some(X509_NONE)
+        );
+    }
+}
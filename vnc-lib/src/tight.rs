@@ -0,0 +1,388 @@
+use crate::{protocol, Error, Rect, Result};
+use byteorder::ReadBytesExt;
+use std::io::Read;
+use zune_jpeg::zune_core::bytestream::ZCursor;
+use zune_jpeg::zune_core::colorspace::ColorSpace;
+use zune_jpeg::zune_core::options::DecoderOptions;
+use zune_jpeg::JpegDecoder;
+
+/// Data shorter than this is sent as it is, without zlib.
+const MIN_TO_COMPRESS: usize = 12;
+
+const FILL: u8 = 0x08;
+const JPEG: u8 = 0x09;
+
+const FILTER_COPY: u8 = 0;
+const FILTER_PALETTE: u8 = 1;
+const FILTER_GRADIENT: u8 = 2;
+
+/// How pixels of `format` look in Tight data. For 24 bit true colour a pixel is
+/// sent as three bytes of red, green and blue, otherwise as it is.
+struct Pixels {
+    format: protocol::PixelFormat,
+    bpp: usize,
+    packed: bool,
+}
+
+impl Pixels {
+    fn new(format: protocol::PixelFormat) -> Pixels {
+        let packed = format.true_colour
+            && format.bits_per_pixel == 32
+            && format.depth == 24
+            && format.red_max == 255
+            && format.green_max == 255
+            && format.blue_max == 255;
+        Pixels {
+            format,
+            bpp: format.bits_per_pixel as usize / 8,
+            packed,
+        }
+    }
+
+    /// Bytes per pixel in Tight data.
+    fn size(&self) -> usize {
+        if self.packed {
+            3
+        } else {
+            self.bpp
+        }
+    }
+
+    fn maxes(&self) -> [u32; 3] {
+        [
+            self.format.red_max as u32,
+            self.format.green_max as u32,
+            self.format.blue_max as u32,
+        ]
+    }
+
+    fn read_value(&self, bytes: &[u8]) -> u32 {
+        let fold = |value: u32, &byte: &u8| value << 8 | byte as u32;
+        if self.format.big_endian {
+            bytes.iter().fold(0, fold)
+        } else {
+            bytes.iter().rev().fold(0, fold)
+        }
+    }
+
+    /// Red, green and blue of a pixel in Tight data.
+    fn components(&self, tpixel: &[u8]) -> [u32; 3] {
+        if self.packed {
+            return [tpixel[0] as u32, tpixel[1] as u32, tpixel[2] as u32];
+        }
+        let value = self.read_value(tpixel);
+        let format = &self.format;
+        [
+            value >> format.red_shift & format.red_max as u32,
+            value >> format.green_shift & format.green_max as u32,
+            value >> format.blue_shift & format.blue_max as u32,
+        ]
+    }
+
+    fn push_components(&self, pixels: &mut Vec<u8>, [red, green, blue]: [u32; 3]) {
+        let format = &self.format;
+        let value =
+            red << format.red_shift | green << format.green_shift | blue << format.blue_shift;
+        let bytes = value.to_be_bytes();
+        let bytes = &bytes[4 - self.bpp..];
+        if format.big_endian {
+            pixels.extend_from_slice(bytes);
+        } else {
+            pixels.extend(bytes.iter().rev());
+        }
+    }
+
+    /// Appends a pixel in Tight data as a pixel of `format`.
+    fn push(&self, pixels: &mut Vec<u8>, tpixel: &[u8]) {
+        if self.packed {
+            self.push_components(pixels, self.components(tpixel));
+        } else {
+            pixels.extend_from_slice(tpixel);
+        }
+    }
+
+    /// Appends an 8 bit per component colour, as from a JPEG.
+    fn push_rgb(&self, pixels: &mut Vec<u8>, rgb: &[u8]) {
+        let maxes = self.maxes();
+        let scale = |i: usize| (rgb[i] as u32 * maxes[i] + 127) / 255;
+        self.push_components(pixels, [scale(0), scale(1), scale(2)]);
+    }
+}
+
+/// Tight keeps four zlib streams going for the whole connection; each rectangle
+/// says which one its data continues, and which to start over.
+pub struct Decoder {
+    streams: [flate2::Decompress; 4],
+}
+
+impl Decoder {
+    pub fn new() -> Decoder {
+        Decoder {
+            streams: std::array::from_fn(|_| flate2::Decompress::new(/*zlib_header*/ true)),
+        }
+    }
+
+    /// Reads a Tight rectangle from `reader` and returns its pixels in `format`.
+    pub fn decode(
+        &mut self,
+        format: protocol::PixelFormat,
+        rect: Rect,
+        reader: &mut dyn Read,
+    ) -> Result<Vec<u8>> {
+        let pixels = Pixels::new(format);
+        let pixel_count = rect.width as usize * rect.height as usize;
+
+        let control = reader.read_u8()?;
+        for (index, stream) in self.streams.iter_mut().enumerate() {
+            if control & (1 << index) != 0 {
+                stream.reset(true);
+            }
+        }
+
+        let mut output = Vec::with_capacity(pixel_count * pixels.bpp);
+        match control >> 4 {
+            FILL => {
+                let mut tpixel = [0; 4];
+                reader.read_exact(&mut tpixel[..pixels.size()])?;
+                pixels.push(&mut output, &tpixel[..pixels.size()]);
+                let pixel = output.clone();
+                for _ in 1..pixel_count {
+                    output.extend_from_slice(&pixel);
+                }
+            }
+            JPEG => {
+                let length = read_compact_length(reader)?;
+                let mut data = vec![0; length];
+                reader.read_exact(&mut data)?;
+                let rgb = decode_jpeg(&data, rect)?;
+                for rgb in rgb.chunks_exact(3) {
+                    pixels.push_rgb(&mut output, rgb);
+                }
+            }
+            basic if basic & 0x08 == 0 => {
+                let stream = (basic & 0x03) as usize;
+                let filter = if basic & 0x04 != 0 {
+                    reader.read_u8()?
+                } else {
+                    FILTER_COPY
+                };
+                match filter {
+                    FILTER_COPY => {
+                        let data = self.read_data(reader, stream, pixel_count * pixels.size())?;
+                        for tpixel in data.chunks_exact(pixels.size()) {
+                            pixels.push(&mut output, tpixel);
+                        }
+                    }
+                    FILTER_PALETTE => {
+                        let colours = reader.read_u8()? as usize + 1;
+                        let mut palette = vec![0; colours * pixels.size()];
+                        reader.read_exact(&mut palette)?;
+                        let palette: Vec<&[u8]> = palette.chunks_exact(pixels.size()).collect();
+                        if colours == 2 {
+                            let row_bytes = (rect.width as usize).div_ceil(8);
+                            let data =
+                                self.read_data(reader, stream, row_bytes * rect.height as usize)?;
+                            for row in data.chunks_exact(row_bytes.max(1)) {
+                                for x in 0..rect.width as usize {
+                                    let index = row[x / 8] >> (7 - x % 8) & 1;
+                                    pixels.push(&mut output, palette[index as usize]);
+                                }
+                            }
+                        } else {
+                            let data = self.read_data(reader, stream, pixel_count)?;
+                            for &index in &data {
+                                let tpixel = palette
+                                    .get(index as usize)
+                                    .ok_or(Error::Unexpected("Tight palette index"))?;
+                                pixels.push(&mut output, tpixel);
+                            }
+                        }
+                    }
+                    FILTER_GRADIENT => {
+                        if !format.true_colour {
+                            return Err(Error::Unexpected("Tight gradient without true colour"));
+                        }
+                        let data = self.read_data(reader, stream, pixel_count * pixels.size())?;
+                        let diffs: Vec<[u32; 3]> = data
+                            .chunks_exact(pixels.size())
+                            .map(|tpixel| pixels.components(tpixel))
+                            .collect();
+                        for colour in gradient(&diffs, rect.width as usize, pixels.maxes()) {
+                            pixels.push_components(&mut output, colour);
+                        }
+                    }
+                    _ => return Err(Error::Unexpected("Tight filter")),
+                }
+            }
+            _ => return Err(Error::Unexpected("Tight compression type")),
+        }
+        Ok(output)
+    }
+
+    /// Reads `length` bytes of filtered data, inflating them with `stream` unless
+    /// they are too short to have been compressed.
+    fn read_data(
+        &mut self,
+        reader: &mut dyn Read,
+        stream: usize,
+        length: usize,
+    ) -> Result<Vec<u8>> {
+        if length < MIN_TO_COMPRESS {
+            let mut data = vec![0; length];
+            reader.read_exact(&mut data)?;
+            return Ok(data);
+        }
+        let mut input = vec![0; read_compact_length(reader)?];
+        reader.read_exact(&mut input)?;
+
+        let decompressor = &mut self.streams[stream];
+        let mut data = Vec::with_capacity(length);
+        let mut consumed = 0;
+        while consumed < input.len() && data.len() < length {
+            let in_before = decompressor.total_in();
+            let out_before = data.len();
+            decompressor
+                .decompress_vec(&input[consumed..], &mut data, flate2::FlushDecompress::Sync)
+                .map_err(|_| Error::Unexpected("Tight zlib data"))?;
+            consumed += (decompressor.total_in() - in_before) as usize;
+            if data.len() == out_before && decompressor.total_in() == in_before {
+                break;
+            }
+        }
+        if data.len() != length {
+            return Err(Error::Unexpected("Tight data length"));
+        }
+        Ok(data)
+    }
+}
+
+/// A length of one to three bytes, seven bits to a byte (eight in the last),
+/// least significant first.
+fn read_compact_length(reader: &mut dyn Read) -> Result<usize> {
+    let mut length = 0;
+    for index in 0..3 {
+        let byte = reader.read_u8()? as usize;
+        if index == 2 {
+            return Ok(length | byte << 14);
+        }
+        length |= (byte & 0x7f) << (7 * index);
+        if byte & 0x80 == 0 {
+            break;
+        }
+    }
+    Ok(length)
+}
+
+/// Undoes the gradient filter: each component was sent as its difference from
+/// left + above - above left, clamped to the component's range.
+fn gradient(diffs: &[[u32; 3]], width: usize, maxes: [u32; 3]) -> Vec<[u32; 3]> {
+    let mut colours: Vec<[u32; 3]> = Vec::with_capacity(diffs.len());
+    for (index, diff) in diffs.iter().enumerate() {
+        let (x, y) = (index % width, index / width);
+        let at = |dx: usize, dy: usize, colours: &[[u32; 3]], c: usize| -> i64 {
+            if x < dx || y < dy {
+                0
+            } else {
+                colours[(y - dy) * width + x - dx][c] as i64
+            }
+        };
+        let colour = std::array::from_fn(|c| {
+            let predicted = at(1, 0, &colours, c) + at(0, 1, &colours, c) - at(1, 1, &colours, c);
+            let predicted = predicted.clamp(0, maxes[c] as i64) as u32;
+            predicted.wrapping_add(diff[c]) & maxes[c]
+        });
+        colours.push(colour);
+    }
+    colours
+}
+
+/// Decodes a JPEG to 8 bit RGB, which has to be the size of `rect`.
+fn decode_jpeg(data: &[u8], rect: Rect) -> Result<Vec<u8>> {
+    let options = DecoderOptions::default().jpeg_set_out_colorspace(ColorSpace::RGB);
+    let mut decoder = JpegDecoder::new_with_options(ZCursor::new(data), options);
+    let rgb = decoder
+        .decode()
+        .map_err(|_| Error::Unexpected("Tight JPEG data"))?;
+    let size = decoder.info().map(|info| (info.width, info.height));
+    if size != Some((rect.width, rect.height))
+        || rgb.len() != rect.width as usize * rect.height as usize * 3
+    {
+        return Err(Error::Unexpected("Tight JPEG size"));
+    }
+    Ok(rgb)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    fn rect(width: u16, height: u16) -> Rect {
+        Rect {
+            left: 0,
+            top: 0,
+            width,
+            height,
+        }
+    }
+
+    fn compress(data: &[u8]) -> Vec<u8> {
+        let mut encoder =
+            flate2::write::ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(data).unwrap();
+        encoder.flush().unwrap();
+        encoder.get_ref().clone()
+    }
+
+    #[test]
+    fn test_compact_length() {
+        let read = |bytes: &[u8]| read_compact_length(&mut &bytes[..]).unwrap();
+        assert_eq!(read(&[0x05]), 5);
+        assert_eq!(read(&[0x90, 0x4e]), 10000);
+        assert_eq!(read(&[0xff, 0xff, 0xff]), 0x3fffff);
+    }
+
+    #[test]
+    fn test_fill_and_palette() {
+        let mut decoder = Decoder::new();
+        let fill = [FILL << 4, 0x10, 0x20, 0x30];
+        let pixels = decoder
+            .decode(protocol::PixelFormat::rgb888(), rect(2, 1), &mut &fill[..])
+            .unwrap();
+        assert_eq!(pixels, [0x30, 0x20, 0x10, 0, 0x30, 0x20, 0x10, 0]);
+
+        // Two colours, one bit per pixel, rows padded to a byte. 3x2 is too
+        // little data to be compressed.
+        let palette = [0x40, 1, 1, 0, 0, 0, 255, 255, 255, 0b0100_0000, 0b1010_0000];
+        let pixels = decoder
+            .decode(
+                protocol::PixelFormat::rgb888(),
+                rect(3, 2),
+                &mut &palette[..],
+            )
+            .unwrap();
+        let (black, white) = ([0, 0, 0, 0], [255, 255, 255, 0]);
+        assert_eq!(pixels, [black, white, black, white, black, white].concat());
+    }
+
+    #[test]
+    fn test_copy_and_gradient() {
+        let mut decoder = Decoder::new();
+        let tpixels: Vec<u8> = (0..12).collect();
+        let mut data = vec![0x00, 12];
+        data.extend(compress(&tpixels));
+        data[1] = (data.len() - 2) as u8;
+        let pixels = decoder
+            .decode(protocol::PixelFormat::rgb888(), rect(2, 2), &mut &data[..])
+            .unwrap();
+        assert_eq!(pixels, [2, 1, 0, 0, 5, 4, 3, 0, 8, 7, 6, 0, 11, 10, 9, 0]);
+
+        // A flat colour is the colour once, then no differences.
+        let diffs = [[10, 20, 30], [0, 0, 0], [0, 0, 0], [0, 0, 0]];
+        let colour = [10, 20, 30];
+        assert_eq!(gradient(&diffs, 2, [255; 3]), [colour; 4]);
+        // Differences wrap around.
+        let diffs = [[250, 0, 0], [10, 0, 0]];
+        assert_eq!(gradient(&diffs, 2, [255; 3]), [[250, 0, 0], [4, 0, 0]]);
+    }
+}
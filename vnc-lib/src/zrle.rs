@@ -131,176 +131,199 @@ impl Decoder {
         format: protocol::PixelFormat,
         rect: Rect,
         input: &[u8],
-        mut callback: F,
+        callback: F,
     ) -> Result<bool>
     where
         F: FnMut(Rect, Vec<u8>) -> Result<bool>,
     {
-        fn read_run_length(reader: &mut dyn Read) -> Result<usize> {
-            let mut run_length_part = reader.read_u8()?;
-            let mut run_length = 1 + run_length_part as usize;
-            while run_length_part == 255 {
-                run_length_part = reader.read_u8()?;
-                run_length += run_length_part as usize;
-            }
-            Ok(run_length)
+        let mut reader = BitReader::new(ZlibReader::new(self.decompressor.take().unwrap(), input));
+        if !decode_tiles(&mut reader, format, rect, 64, callback)? {
+            return Ok(false);
         }
+        self.decompressor = Some(reader.into_inner()?.into_inner()?);
+        Ok(true)
+    }
+}
 
-        fn copy_true_color(
-            reader: &mut dyn Read,
-            pixels: &mut Vec<u8>,
-            pad: bool,
-            compressed_bpp: usize,
-            bpp: usize,
-        ) -> Result<()> {
-            let mut buf = [0; 4];
-            reader.read_exact(&mut buf[pad as usize..pad as usize + compressed_bpp])?;
-            pixels.extend_from_slice(&buf[..bpp]);
-            Ok(())
-        }
+/// Decodes a TRLE rectangle: ZRLE's tiles without the zlib, 16 pixels square,
+/// read straight from the connection.
+pub fn decode_trle<F>(
+    format: protocol::PixelFormat,
+    rect: Rect,
+    reader: &mut dyn Read,
+    callback: F,
+) -> Result<bool>
+where
+    F: FnMut(Rect, Vec<u8>) -> Result<bool>,
+{
+    decode_tiles(&mut BitReader::new(reader), format, rect, 16, callback)
+}
 
-        fn copy_indexed(palette: &[u8], pixels: &mut Vec<u8>, bpp: usize, index: u8) {
-            let start = index as usize * bpp;
-            pixels.extend_from_slice(&palette[start..start + bpp])
+fn decode_tiles<R: Read, F>(
+    reader: &mut BitReader<R>,
+    format: protocol::PixelFormat,
+    rect: Rect,
+    tile_size: u16,
+    mut callback: F,
+) -> Result<bool>
+where
+    F: FnMut(Rect, Vec<u8>) -> Result<bool>,
+{
+    fn read_run_length(reader: &mut dyn Read) -> Result<usize> {
+        let mut run_length_part = reader.read_u8()?;
+        let mut run_length = 1 + run_length_part as usize;
+        while run_length_part == 255 {
+            run_length_part = reader.read_u8()?;
+            run_length += run_length_part as usize;
         }
+        Ok(run_length)
+    }
 
-        let bpp = format.bits_per_pixel as usize / 8;
-        let pixel_mask = (format.red_max as u32) << format.red_shift
-            | (format.green_max as u32) << format.green_shift
-            | (format.blue_max as u32) << format.blue_shift;
-
-        let (compressed_bpp, pad_pixel) =
-            if format.bits_per_pixel == 32 && format.true_colour && format.depth <= 24 {
-                if pixel_mask & 0x000000ff == 0 {
-                    (3, !format.big_endian)
-                } else if pixel_mask & 0xff000000 == 0 {
-                    (3, format.big_endian)
-                } else {
-                    (4, false)
-                }
+    fn copy_true_color(
+        reader: &mut dyn Read,
+        pixels: &mut Vec<u8>,
+        pad: bool,
+        compressed_bpp: usize,
+        bpp: usize,
+    ) -> Result<()> {
+        let mut buf = [0; 4];
+        reader.read_exact(&mut buf[pad as usize..pad as usize + compressed_bpp])?;
+        pixels.extend_from_slice(&buf[..bpp]);
+        Ok(())
+    }
+
+    fn copy_indexed(palette: &[u8], pixels: &mut Vec<u8>, bpp: usize, index: u8) {
+        let start = index as usize * bpp;
+        pixels.extend_from_slice(&palette[start..start + bpp])
+    }
+
+    let bpp = format.bits_per_pixel as usize / 8;
+    let pixel_mask = (format.red_max as u32) << format.red_shift
+        | (format.green_max as u32) << format.green_shift
+        | (format.blue_max as u32) << format.blue_shift;
+
+    let (compressed_bpp, pad_pixel) =
+        if format.bits_per_pixel == 32 && format.true_colour && format.depth <= 24 {
+            if pixel_mask & 0x000000ff == 0 {
+                (3, !format.big_endian)
+            } else if pixel_mask & 0xff000000 == 0 {
+                (3, format.big_endian)
             } else {
-                (bpp, false)
-            };
+                (4, false)
+            }
+        } else {
+            (bpp, false)
+        };
 
-        let mut palette = Vec::with_capacity(128 * bpp);
-        let mut reader = BitReader::new(ZlibReader::new(self.decompressor.take().unwrap(), input));
+    let mut palette = Vec::with_capacity(128 * bpp);
+    let mut palette_size = 0;
 
-        let mut y = 0;
-        while y < rect.height {
-            let height = if y + 64 > rect.height {
-                rect.height - y
+    let mut y = 0;
+    while y < rect.height {
+        let height = if y + tile_size > rect.height {
+            rect.height - y
+        } else {
+            tile_size
+        };
+        let mut x = 0;
+        while x < rect.width {
+            let width = if x + tile_size > rect.width {
+                rect.width - x
             } else {
-                64
+                tile_size
             };
-            let mut x = 0;
-            while x < rect.width {
-                let width = if x + 64 > rect.width {
-                    rect.width - x
-                } else {
-                    64
-                };
-                let pixel_count = height as usize * width as usize;
-
-                let is_rle = reader.read_bit()?;
-                let palette_size = reader.read_bits(7)?;
+            let pixel_count = height as usize * width as usize;
 
+            let is_rle = reader.read_bit()?;
+            let subencoding = reader.read_bits(7)?;
+
+            // TRLE can keep the palette of the previous tile, ZRLE never does.
+            if !matches!((is_rle, subencoding), (false, 127) | (true, 1)) {
+                palette_size = subencoding;
                 palette.truncate(0);
                 for _ in 0..palette_size {
-                    copy_true_color(&mut reader, &mut palette, pad_pixel, compressed_bpp, bpp)?
+                    copy_true_color(reader, &mut palette, pad_pixel, compressed_bpp, bpp)?
                 }
+            }
 
-                let mut pixels = Vec::with_capacity(pixel_count * bpp);
-                match (is_rle, palette_size) {
-                    (false, 0) => {
-                        // True Color pixels
-                        for _ in 0..pixel_count {
-                            copy_true_color(
-                                &mut reader,
-                                &mut pixels,
-                                pad_pixel,
-                                compressed_bpp,
-                                bpp,
-                            )?
-                        }
+            let mut pixels = Vec::with_capacity(pixel_count * bpp);
+            match (is_rle, palette_size) {
+                (false, 0) => {
+                    // True Color pixels
+                    for _ in 0..pixel_count {
+                        copy_true_color(reader, &mut pixels, pad_pixel, compressed_bpp, bpp)?
                     }
-                    (false, 1) => {
-                        // Color fill
-                        for _ in 0..pixel_count {
-                            copy_indexed(&palette, &mut pixels, bpp, 0)
-                        }
+                }
+                (false, 1) => {
+                    // Color fill
+                    for _ in 0..pixel_count {
+                        copy_indexed(&palette, &mut pixels, bpp, 0)
                     }
-                    (false, 2) | (false, 3..=4) | (false, 5..=16) => {
-                        // Indexed pixels
-                        let bits_per_index = match palette_size {
-                            2 => 1,
-                            3..=4 => 2,
-                            5..=16 => 4,
-                            _ => unreachable!(),
-                        };
-                        for _ in 0..height {
-                            for _ in 0..width {
-                                let index = reader.read_bits(bits_per_index)?;
-                                copy_indexed(&palette, &mut pixels, bpp, index)
-                            }
-                            reader.align();
+                }
+                (false, 2) | (false, 3..=4) | (false, 5..=16) => {
+                    // Indexed pixels
+                    let bits_per_index = match palette_size {
+                        2 => 1,
+                        3..=4 => 2,
+                        5..=16 => 4,
+                        _ => unreachable!(),
+                    };
+                    for _ in 0..height {
+                        for _ in 0..width {
+                            let index = reader.read_bits(bits_per_index)?;
+                            copy_indexed(&palette, &mut pixels, bpp, index)
                         }
+                        reader.align();
                     }
-                    (true, 0) => {
-                        // True Color RLE
-                        let mut count = 0;
-                        let mut pixel = Vec::new();
-                        while count < pixel_count {
-                            pixel.truncate(0);
-                            copy_true_color(
-                                &mut reader,
-                                &mut pixel,
-                                pad_pixel,
-                                compressed_bpp,
-                                bpp,
-                            )?;
-                            let run_length = read_run_length(&mut reader)?;
-                            for _ in 0..run_length {
-                                pixels.extend(&pixel)
-                            }
-                            count += run_length;
+                }
+                (true, 0) => {
+                    // True Color RLE
+                    let mut count = 0;
+                    let mut pixel = Vec::new();
+                    while count < pixel_count {
+                        pixel.truncate(0);
+                        copy_true_color(reader, &mut pixel, pad_pixel, compressed_bpp, bpp)?;
+                        let run_length = read_run_length(reader)?;
+                        for _ in 0..run_length {
+                            pixels.extend(&pixel)
                         }
+                        count += run_length;
                     }
-                    (true, 2..=127) => {
-                        // Indexed RLE
-                        let mut count = 0;
-                        while count < pixel_count {
-                            let longer_than_one = reader.read_bit()?;
-                            let index = reader.read_bits(7)?;
-                            let run_length = if longer_than_one {
-                                read_run_length(&mut reader)?
-                            } else {
-                                1
-                            };
-                            for _ in 0..run_length {
-                                copy_indexed(&palette, &mut pixels, bpp, index);
-                            }
-                            count += run_length;
+                }
+                (true, 2..=127) => {
+                    // Indexed RLE
+                    let mut count = 0;
+                    while count < pixel_count {
+                        let longer_than_one = reader.read_bit()?;
+                        let index = reader.read_bits(7)?;
+                        let run_length = if longer_than_one {
+                            read_run_length(reader)?
+                        } else {
+                            1
+                        };
+                        for _ in 0..run_length {
+                            copy_indexed(&palette, &mut pixels, bpp, index);
                         }
+                        count += run_length;
                     }
-                    _ => return Err(Error::Unexpected("ZRLE subencoding")),
-                }
-
-                let tile = Rect {
-                    top: rect.top + y,
-                    left: rect.left + x,
-                    width,
-                    height,
-                };
-                if let false = callback(tile, pixels)? {
-                    return Ok(false);
                 }
+                _ => return Err(Error::Unexpected("ZRLE subencoding")),
+            }
 
-                x += width;
+            let tile = Rect {
+                top: rect.top + y,
+                left: rect.left + x,
+                width,
+                height,
+            };
+            if let false = callback(tile, pixels)? {
+                return Ok(false);
             }
-            y += height;
-        }
 
-        self.decompressor = Some(reader.into_inner()?.into_inner()?);
-        Ok(true)
+            x += width;
+        }
+        y += height;
     }
+
+    Ok(true)
 }